@@ -0,0 +1,92 @@
+//! Circuit breaker that suspends uploads during sustained storage outages.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// Where the breaker is in its closed → open → half-open cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerState {
+    /// Uploads proceed normally.
+    Closed,
+    /// Uploads are suspended until the cooldown ends.
+    Open { until: Instant },
+    /// The cooldown ended; the next upload is a probe.
+    HalfOpen,
+}
+
+impl fmt::Display for BreakerState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BreakerState::Closed => "closed",
+            BreakerState::Open { .. } => "open",
+            BreakerState::HalfOpen => "half_open",
+        })
+    }
+}
+
+/// Opens after `threshold` consecutive upload failures, so frames stay
+/// queued for the cooldown instead of being retried every tick.
+pub struct CircuitBreaker {
+    /// Consecutive failures that open the circuit (0 = never open).
+    threshold: u32,
+    cooldown: Duration,
+    consecutive_failures: u32,
+    state: BreakerState,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            consecutive_failures: 0,
+            state: BreakerState::Closed,
+        }
+    }
+
+    /// Current state, without advancing an expired cooldown.
+    pub fn state(&self) -> BreakerState {
+        self.state
+    }
+
+    /// Returns true if an upload may be attempted now. Once the cooldown
+    /// ends the circuit goes half-open, letting a single probe through.
+    pub fn allow(&mut self) -> bool {
+        match self.state {
+            BreakerState::Closed | BreakerState::HalfOpen => true,
+            BreakerState::Open { until } if Instant::now() >= until => {
+                info!("Upload circuit half-open, probing storage");
+                self.state = BreakerState::HalfOpen;
+                true
+            }
+            BreakerState::Open { .. } => false,
+        }
+    }
+
+    /// Record a successful upload, closing the circuit.
+    pub fn record_success(&mut self) {
+        if self.state != BreakerState::Closed {
+            info!("Upload circuit closed, storage reachable again");
+        }
+        self.consecutive_failures = 0;
+        self.state = BreakerState::Closed;
+    }
+
+    /// Record a failed upload, opening the circuit at the threshold or when
+    /// a half-open probe fails.
+    pub fn record_failure(&mut self) {
+        self.consecutive_failures += 1;
+        let trips = self.state == BreakerState::HalfOpen
+            || (self.threshold > 0 && self.consecutive_failures >= self.threshold);
+        if trips {
+            warn!(
+                "Upload circuit open after {} consecutive failures, suspending uploads for {:?}",
+                self.consecutive_failures, self.cooldown
+            );
+            self.state = BreakerState::Open {
+                until: Instant::now() + self.cooldown,
+            };
+        }
+    }
+}
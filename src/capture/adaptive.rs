@@ -0,0 +1,56 @@
+//! Adaptive capture interval driven by frame-to-frame change.
+
+use std::time::Duration;
+
+use crate::config::AdaptiveConfig;
+
+/// Adjusts the capture interval between a minimum and maximum based on screen activity.
+pub struct AdaptiveInterval {
+    min: Duration,
+    max: Duration,
+    current: Duration,
+    active_threshold: f32,
+    static_threshold: f32,
+}
+
+impl AdaptiveInterval {
+    /// Create a new adaptive interval starting at `initial`, clamped to the configured bounds.
+    pub fn new(config: &AdaptiveConfig, initial: Duration) -> Self {
+        let min = config.min_interval();
+        let max = config.max_interval().max(min);
+        Self {
+            min,
+            max,
+            current: initial.clamp(min, max),
+            active_threshold: config.active_threshold,
+            static_threshold: config.static_threshold,
+        }
+    }
+
+    /// Returns the current interval.
+    pub fn current(&self) -> Duration {
+        self.current
+    }
+
+    /// Update the interval from the latest frame difference (0.0-1.0).
+    /// Returns the new interval if it changed.
+    pub fn update(&mut self, difference: f32) -> Option<Duration> {
+        let next = if difference >= self.active_threshold {
+            // Busy screen: capture more often
+            self.current / 2
+        } else if difference <= self.static_threshold {
+            // Static screen: back off
+            self.current.mul_f32(1.5)
+        } else {
+            self.current
+        }
+        .clamp(self.min, self.max);
+
+        if next == self.current {
+            return None;
+        }
+
+        self.current = next;
+        Some(next)
+    }
+}
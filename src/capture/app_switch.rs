@@ -0,0 +1,54 @@
+//! Notices when the foreground application changes, for extra captures at
+//! each context switch.
+
+use anyhow::Result;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+use super::frontmost::{foreground_app, ForegroundApp};
+
+/// How often the frontmost window's owner is checked.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Start a thread calling `on_switch` each time a different app has stayed
+/// frontmost for `debounce`, so cycling through apps with cmd-tab reports
+/// only the one that was landed on. The app in front at startup isn't
+/// reported. The thread exits once `on_switch` returns false.
+pub fn watch_app_switches(debounce: Duration, on_switch: impl Fn(ForegroundApp) -> bool + Send + 'static) -> Result<()> {
+    thread::Builder::new()
+        .name("app-switch".to_string())
+        .spawn(move || {
+            let mut current = foreground_app();
+            let mut pending: Option<(ForegroundApp, Instant)> = None;
+
+            loop {
+                thread::sleep(POLL_INTERVAL);
+                let Some(app) = foreground_app() else { continue };
+                if current.as_ref() == Some(&app) {
+                    pending = None;
+                    continue;
+                }
+
+                let since = match &pending {
+                    Some((candidate, since)) if *candidate == app => *since,
+                    _ => {
+                        let now = Instant::now();
+                        pending = Some((app.clone(), now));
+                        now
+                    }
+                };
+                if since.elapsed() < debounce {
+                    continue;
+                }
+
+                debug!("Foreground app changed to {}", app.name);
+                pending = None;
+                current = Some(app.clone());
+                if !on_switch(app) {
+                    break;
+                }
+            }
+        })?;
+    Ok(())
+}
@@ -0,0 +1,71 @@
+//! Whether the default audio output device is playing, via CoreAudio.
+
+use std::ffi::c_void;
+
+/// CoreAudio's system object, which owns the hardware properties.
+const SYSTEM_OBJECT: u32 = 1;
+
+/// Selector for the default output device.
+const DEFAULT_OUTPUT_DEVICE: u32 = u32::from_be_bytes(*b"dOut");
+
+/// Selector for whether any process is doing I/O on a device.
+const DEVICE_IS_RUNNING_SOMEWHERE: u32 = u32::from_be_bytes(*b"gone");
+
+/// Global property scope.
+const SCOPE_GLOBAL: u32 = u32::from_be_bytes(*b"glob");
+
+/// Main element of a property.
+const ELEMENT_MAIN: u32 = 0;
+
+#[repr(C)]
+struct PropertyAddress {
+    selector: u32,
+    scope: u32,
+    element: u32,
+}
+
+#[link(name = "CoreAudio", kind = "framework")]
+extern "C" {
+    fn AudioObjectGetPropertyData(
+        object_id: u32,
+        address: *const PropertyAddress,
+        qualifier_size: u32,
+        qualifier: *const c_void,
+        data_size: *mut u32,
+        data: *mut c_void,
+    ) -> i32;
+}
+
+/// Returns true if some app is using the default output device (media
+/// playing, a call in progress). No audio is read. None if it can't be queried.
+pub fn output_active() -> Option<bool> {
+    let device: u32 = get_u32(SYSTEM_OBJECT, DEFAULT_OUTPUT_DEVICE)?;
+    if device == 0 {
+        return None;
+    }
+    get_u32(device, DEVICE_IS_RUNNING_SOMEWHERE).map(|running| running != 0)
+}
+
+/// Read a 32-bit global property of a CoreAudio object.
+fn get_u32(object_id: u32, selector: u32) -> Option<u32> {
+    let address = PropertyAddress {
+        selector,
+        scope: SCOPE_GLOBAL,
+        element: ELEMENT_MAIN,
+    };
+    let mut value: u32 = 0;
+    let mut size = std::mem::size_of::<u32>() as u32;
+
+    // SAFETY: address and value outlive the call, and size matches value
+    let status = unsafe {
+        AudioObjectGetPropertyData(
+            object_id,
+            &address,
+            0,
+            std::ptr::null(),
+            &mut size,
+            &mut value as *mut u32 as *mut c_void,
+        )
+    };
+    (status == 0).then_some(value)
+}
@@ -0,0 +1,88 @@
+//! Backoff for consecutive capture failures, so a lost permission or display
+//! doesn't produce an error on every tick.
+
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
+
+/// Pause after the second consecutive failure; doubles with each one after.
+const BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest pause between capture attempts while failures continue.
+const MAX_DELAY: Duration = Duration::from_secs(300);
+
+/// Tracks a run of failed captures and when the next attempt is allowed.
+#[derive(Debug, Default)]
+pub struct CaptureBackoff {
+    consecutive_failures: u32,
+    retry_at: Option<Instant>,
+    /// When the current run of failures began (or the capture was last restarted).
+    failing_since: Option<Instant>,
+    /// Whether the "still failing" warning was logged for this run.
+    warned: bool,
+}
+
+impl CaptureBackoff {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns true if a capture may be attempted now.
+    pub fn ready(&self) -> bool {
+        self.retry_at.is_none_or(|at| Instant::now() >= at)
+    }
+
+    /// Record a failed capture. The first failure is logged as an error; later
+    /// ones back off exponentially and only log once the backoff tops out.
+    pub fn record_failure(&mut self, err: &anyhow::Error) {
+        self.consecutive_failures += 1;
+        if self.consecutive_failures == 1 {
+            self.failing_since = Some(Instant::now());
+            error!("Failed to capture frame: {:#}", err);
+            return;
+        }
+
+        let delay = BASE_DELAY
+            .saturating_mul(2u32.saturating_pow(self.consecutive_failures - 2))
+            .min(MAX_DELAY);
+        self.retry_at = Some(Instant::now() + delay);
+
+        if delay == MAX_DELAY && !self.warned {
+            self.warned = true;
+            warn!(
+                "Capture has failed {} times in a row, retrying every {:?}: {:#}",
+                self.consecutive_failures, MAX_DELAY, err
+            );
+        } else {
+            debug!(
+                "Capture failed {} times in a row, next attempt in {:?}: {:#}",
+                self.consecutive_failures, delay, err
+            );
+        }
+    }
+
+    /// Consecutive failed captures so far.
+    pub fn consecutive_failures(&self) -> u32 {
+        self.consecutive_failures
+    }
+
+    /// How long captures have been failing (zero if the last one succeeded).
+    pub fn failing_for(&self) -> Duration {
+        self.failing_since.map(|at| at.elapsed()).unwrap_or_default()
+    }
+
+    /// Restart the failure clock after the capture subsystem was rebuilt,
+    /// keeping the backoff so attempts don't resume at full rate.
+    pub fn record_restart(&mut self) {
+        if self.failing_since.is_some() {
+            self.failing_since = Some(Instant::now());
+        }
+    }
+
+    /// Record a successful capture, ending any backoff.
+    pub fn record_success(&mut self) {
+        if self.consecutive_failures > 1 {
+            info!("Capture recovered after {} consecutive failures", self.consecutive_failures);
+        }
+        *self = Self::default();
+    }
+}
@@ -0,0 +1,120 @@
+//! Color matching captured pixels from a display's color space to sRGB.
+
+use anyhow::Result;
+use core_foundation::base::TCFType;
+use core_foundation::string::{CFString, CFStringRef};
+use core_graphics::color_space::{kCGColorSpaceSRGB, CGColorSpace};
+use core_graphics::context::CGContext;
+use core_graphics::data_provider::CGDataProvider;
+use core_graphics::geometry::{CGPoint, CGRect, CGSize};
+use core_graphics::image::CGImage;
+use core_graphics::sys;
+use foreign_types::ForeignType;
+use serde::{Deserialize, Serialize};
+
+use super::encode::RawFrame;
+
+/// Target name recorded for frames normalized to sRGB.
+pub const SRGB: &str = "sRGB";
+
+/// Prefix of CoreGraphics color space names, dropped when recording them.
+const NAME_PREFIX: &str = "kCGColorSpace";
+
+/// BGRA as delivered by ScreenCaptureKit: kCGImageAlphaPremultipliedFirst
+/// with kCGBitmapByteOrder32Little.
+const BGRA_BITMAP_INFO: u32 = 2 | (2 << 12);
+
+/// kCGRenderingIntentDefault.
+const DEFAULT_INTENT: u32 = 0;
+
+// Not wrapped by core-graphics
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGDisplayCopyColorSpace(display: u32) -> *mut sys::CGColorSpace;
+    fn CGColorSpaceCopyName(space: *mut sys::CGColorSpace) -> CFStringRef;
+}
+
+/// The display's color space, or None if it can't be queried.
+fn display_space(display_id: u32) -> Option<CGColorSpace> {
+    // SAFETY: the copy is owned by the returned CGColorSpace, which releases it
+    unsafe {
+        let space = CGDisplayCopyColorSpace(display_id);
+        (!space.is_null()).then(|| CGColorSpace::from_ptr(space))
+    }
+}
+
+/// How a frame's pixels were color matched.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColorConversion {
+    /// The display's color space, e.g. "DisplayP3" (None for custom
+    /// profiles, or frames stitched from several displays).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// The color space the pixels were converted to.
+    pub target: String,
+}
+
+/// Name of the display's color space, e.g. "DisplayP3". None if the display
+/// uses a profile without a standard name, or it can't be queried.
+pub fn display_color_space(display_id: u32) -> Option<String> {
+    let space = display_space(display_id)?;
+    // SAFETY: the copied name is checked for null and released by its CFString
+    let name = unsafe {
+        let name = CGColorSpaceCopyName(space.as_ptr());
+        if name.is_null() {
+            return None;
+        }
+        CFString::wrap_under_create_rule(name).to_string()
+    };
+    Some(name.trim_start_matches(NAME_PREFIX).to_string())
+}
+
+/// Convert a BGRA frame from the display's color space to sRGB by drawing it
+/// into an sRGB bitmap, letting ColorSync do the matching.
+pub fn to_srgb(raw: &RawFrame, display_id: u32) -> Result<RawFrame> {
+    let bytes_per_row = raw.width * 4;
+    let mut data = vec![0u8; bytes_per_row * raw.height];
+
+    // SAFETY: kCGColorSpaceSRGB is a constant CoreGraphics string
+    let target_space = unsafe { CGColorSpace::create_with_name(kCGColorSpaceSRGB) };
+    let (Some(source_space), Some(target_space)) = (display_space(display_id), target_space) else {
+        anyhow::bail!("Failed to convert display {} frame to sRGB", display_id);
+    };
+
+    {
+        // SAFETY: the provider borrows raw.data, and it and the image drawn
+        // from it are dropped at the end of this block
+        let provider = unsafe { CGDataProvider::from_slice(&raw.data) };
+        let image = CGImage::new(
+            raw.width,
+            raw.height,
+            8,
+            32,
+            raw.bytes_per_row,
+            &source_space,
+            BGRA_BITMAP_INFO,
+            &provider,
+            false,
+            DEFAULT_INTENT,
+        );
+        // The context draws straight into `data`, which outlives it
+        let context = CGContext::create_bitmap_context(
+            Some(data.as_mut_ptr().cast()),
+            raw.width,
+            raw.height,
+            8,
+            bytes_per_row,
+            &target_space,
+            BGRA_BITMAP_INFO,
+        );
+        let rect = CGRect::new(&CGPoint::new(0.0, 0.0), &CGSize::new(raw.width as f64, raw.height as f64));
+        context.draw_image(rect, &image);
+    }
+
+    Ok(RawFrame {
+        width: raw.width,
+        height: raw.height,
+        bytes_per_row,
+        data,
+    })
+}
@@ -0,0 +1,30 @@
+//! Cursor position, for captures that follow the pointer.
+
+use core_graphics::event::CGEvent;
+use core_graphics::event_source::{CGEventSource, CGEventSourceStateID};
+
+use crate::config::CaptureRegion;
+
+/// Current cursor location in global desktop coordinates (points, top-left
+/// origin). None if it can't be read.
+pub fn position() -> Option<(f64, f64)> {
+    let source = CGEventSource::new(CGEventSourceStateID::CombinedSessionState).ok()?;
+    let location = CGEvent::new(source).ok()?.location();
+    Some((location.x, location.y))
+}
+
+/// A `width`×`height` region centered on `center`, shifted to stay within a
+/// display of size `bounds` and shrunk if larger than it. All in points.
+pub fn centered_region(center: (f64, f64), width: u32, height: u32, bounds: (u32, u32)) -> CaptureRegion {
+    let width = width.min(bounds.0);
+    let height = height.min(bounds.1);
+    let origin = |center: f64, size: u32, bound: u32| {
+        (center - size as f64 / 2.0).round().clamp(0.0, (bound - size) as f64) as u32
+    };
+    CaptureRegion {
+        x: origin(center.0, width, bounds.0),
+        y: origin(center.1, height, bounds.1),
+        width,
+        height,
+    }
+}
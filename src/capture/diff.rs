@@ -0,0 +1,56 @@
+//! Compact frame fingerprints for cheap frame-to-frame change detection.
+
+use image::{GenericImageView, Pixel};
+
+/// Fingerprint grid size (cells per side).
+const GRID: u32 = 16;
+
+/// Compute a grayscale fingerprint by averaging luminance over a fixed grid.
+pub fn fingerprint<I>(img: &I) -> Vec<u8>
+where
+    I: GenericImageView,
+    I::Pixel: Pixel<Subpixel = u8>,
+{
+    let (width, height) = img.dimensions();
+    if width == 0 || height == 0 {
+        return Vec::new();
+    }
+
+    let mut sums = vec![0u64; (GRID * GRID) as usize];
+    let mut counts = vec![0u64; (GRID * GRID) as usize];
+
+    // Sample every few pixels; the grid average doesn't need every one
+    let step = ((width.min(height) / (GRID * 8)).max(1)) as usize;
+    for y in (0..height).step_by(step) {
+        let cell_y = y * GRID / height;
+        for x in (0..width).step_by(step) {
+            let cell_x = x * GRID / width;
+            let p = img.get_pixel(x, y).to_rgb();
+            let luma = (299 * p[0] as u64 + 587 * p[1] as u64 + 114 * p[2] as u64) / 1000;
+            let idx = (cell_y * GRID + cell_x) as usize;
+            sums[idx] += luma;
+            counts[idx] += 1;
+        }
+    }
+
+    sums.iter()
+        .zip(&counts)
+        .map(|(&sum, &count)| sum.checked_div(count).unwrap_or(0) as u8)
+        .collect()
+}
+
+/// Fraction of change between two fingerprints (0.0 = identical, 1.0 = inverted).
+/// Returns 1.0 when the fingerprints are not comparable.
+pub fn difference(a: &[u8], b: &[u8]) -> f32 {
+    if a.is_empty() || a.len() != b.len() {
+        return 1.0;
+    }
+
+    let total: u64 = a
+        .iter()
+        .zip(b)
+        .map(|(&x, &y)| (x as i32 - y as i32).unsigned_abs() as u64)
+        .sum();
+
+    total as f32 / (a.len() as f32 * 255.0)
+}
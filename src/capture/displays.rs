@@ -0,0 +1,131 @@
+//! Display reconfiguration (hotplug / resolution change) detection, whether
+//! any display is attached at all, and each display's pixel density.
+
+use anyhow::Result;
+use core_foundation::runloop::{kCFRunLoopDefaultMode, CFRunLoop, CFRunLoopRunResult};
+use core_graphics::display::CGDisplay;
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+use tracing::debug;
+
+/// Set on the "begin" notification that precedes the actual change.
+const BEGIN_CONFIGURATION_FLAG: u32 = 1 << 0;
+
+/// How long the watcher's run loop runs before rechecking whether to stop.
+const WATCHER_RUN_SLICE: Duration = Duration::from_millis(500);
+
+type ReconfigurationCallback = extern "C" fn(display: u32, flags: u32, user_info: *mut c_void);
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGDisplayRegisterReconfigurationCallback(
+        callback: ReconfigurationCallback,
+        user_info: *mut c_void,
+    ) -> i32;
+    fn CGDisplayRemoveReconfigurationCallback(
+        callback: ReconfigurationCallback,
+        user_info: *mut c_void,
+    ) -> i32;
+}
+
+/// Number of active displays, e.g. 0 on a clamshell MacBook with no
+/// external monitor. None if it can't be queried.
+pub fn active_display_count() -> Option<u32> {
+    CGDisplay::active_display_count().ok()
+}
+
+/// Pixels per point on the display, e.g. 2.0 on Retina and 1.0 on most
+/// external monitors. None if the display mode can't be queried.
+pub fn backing_scale(display_id: u32) -> Option<f64> {
+    let mode = CGDisplay::new(display_id).display_mode()?;
+    let (points, pixels) = (mode.width(), mode.pixel_width());
+    (points > 0 && pixels > 0).then(|| pixels as f64 / points as f64)
+}
+
+extern "C" fn on_reconfiguration(display_id: u32, flags: u32, user_info: *mut c_void) {
+    if flags & BEGIN_CONFIGURATION_FLAG != 0 || user_info.is_null() {
+        return;
+    }
+
+    debug!("Display {} reconfigured (flags={:#x})", display_id, flags);
+
+    // SAFETY: user_info is the AtomicBool kept alive by the watcher thread
+    let changed = unsafe { &*(user_info as *const AtomicBool) };
+    changed.store(true, Ordering::SeqCst);
+}
+
+/// Watches for displays being attached, detached, or reconfigured.
+///
+/// Notifications are delivered through a run loop, which the main thread
+/// only runs when there's a menu bar, so the watcher runs its own on a
+/// helper thread.
+pub struct DisplayWatcher {
+    changed: Arc<AtomicBool>,
+    running: Arc<AtomicBool>,
+}
+
+impl DisplayWatcher {
+    /// Register for display reconfiguration notifications.
+    pub fn new() -> Result<Self> {
+        let changed = Arc::new(AtomicBool::new(false));
+        let running = Arc::new(AtomicBool::new(true));
+        let (ready_tx, ready_rx) = mpsc::channel();
+
+        let (thread_changed, thread_running) = (changed.clone(), running.clone());
+        thread::Builder::new()
+            .name("display-watcher".to_string())
+            .spawn(move || run_watcher(thread_changed, thread_running, ready_tx))?;
+
+        let err = ready_rx.recv().unwrap_or(-1);
+        if err != 0 {
+            anyhow::bail!("Failed to register display reconfiguration callback (error {})", err);
+        }
+
+        Ok(Self { changed, running })
+    }
+
+    /// Returns true if displays changed since the last call.
+    pub fn take_changed(&self) -> bool {
+        self.changed.swap(false, Ordering::SeqCst)
+    }
+}
+
+impl Drop for DisplayWatcher {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Register the callback and run this thread's run loop until the watcher is
+/// dropped. The thread's own reference keeps `changed` alive until the
+/// callback is removed.
+fn run_watcher(changed: Arc<AtomicBool>, running: Arc<AtomicBool>, ready_tx: mpsc::Sender<i32>) {
+    // SAFETY: user_info points into `changed`, held until the callback is removed below
+    let err = unsafe { CGDisplayRegisterReconfigurationCallback(on_reconfiguration, user_info(&changed)) };
+    let _ = ready_tx.send(err);
+    if err != 0 {
+        return;
+    }
+
+    while running.load(Ordering::SeqCst) {
+        // SAFETY: kCFRunLoopDefaultMode is an immutable CFString constant
+        let result = CFRunLoop::run_in_mode(unsafe { kCFRunLoopDefaultMode }, WATCHER_RUN_SLICE, false);
+        // A run loop without sources returns at once instead of waiting
+        if matches!(result, CFRunLoopRunResult::Finished) {
+            thread::sleep(WATCHER_RUN_SLICE);
+        }
+    }
+
+    // SAFETY: removes the registration made above with the same arguments
+    unsafe {
+        CGDisplayRemoveReconfigurationCallback(on_reconfiguration, user_info(&changed));
+    }
+    debug!("Display watcher thread exiting");
+}
+
+fn user_info(changed: &Arc<AtomicBool>) -> *mut c_void {
+    Arc::as_ptr(changed) as *mut c_void
+}
@@ -0,0 +1,332 @@
+//! Pixel conversion and image encoding for captured frames.
+
+use anyhow::{Context, Result};
+use image::codecs::avif::AvifEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageBuffer, ImageEncoder, RgbImage, RgbaImage};
+use jpeg_encoder::SamplingFactor;
+use std::io::Cursor;
+
+use super::diff;
+use crate::config::{JpegSubsampling, OutputFormat};
+
+/// AVIF encoder speed (1 = slowest/smallest, 10 = fastest).
+const AVIF_SPEED: u8 = 8;
+
+/// Image encoding settings.
+#[derive(Debug, Clone, Copy)]
+pub struct EncodeOptions {
+    pub format: OutputFormat,
+    pub quality: u8,
+    /// JPEG chroma subsampling (None = image crate encoder defaults).
+    pub jpeg_subsampling: Option<JpegSubsampling>,
+    /// Width of an additional JPEG thumbnail (None = no thumbnail).
+    pub thumbnail_width: Option<u32>,
+    /// Keep the alpha channel in formats that can store it.
+    pub keep_alpha: bool,
+    /// Re-encode JPEGs with Huffman tables optimized for each frame.
+    pub optimize_jpeg: bool,
+}
+
+impl EncodeOptions {
+    /// Whether frames need an alpha channel; JPEG never stores one.
+    pub fn needs_alpha(&self) -> bool {
+        self.keep_alpha && self.format != OutputFormat::Jpeg
+    }
+}
+
+/// A raw BGRA frame copied out of a capture buffer.
+#[derive(Debug)]
+pub struct RawFrame {
+    pub width: usize,
+    pub height: usize,
+    pub bytes_per_row: usize,
+    pub data: Vec<u8>,
+}
+
+impl RawFrame {
+    /// Copy out the `width`×`height` pixel rectangle at (`x`, `y`), clamped
+    /// to the frame.
+    pub fn crop(&self, x: usize, y: usize, width: usize, height: usize) -> RawFrame {
+        let x = x.min(self.width);
+        let y = y.min(self.height);
+        let width = width.min(self.width - x);
+        let height = height.min(self.height - y);
+
+        let mut data = Vec::with_capacity(width * height * 4);
+        for row in y..y + height {
+            let start = row * self.bytes_per_row + x * 4;
+            data.extend_from_slice(&self.data[start..start + width * 4]);
+        }
+        RawFrame {
+            width,
+            height,
+            bytes_per_row: width * 4,
+            data,
+        }
+    }
+}
+
+/// An encoded image and its fingerprint.
+pub struct EncodedImage {
+    pub data: Vec<u8>,
+    pub format: OutputFormat,
+    pub fingerprint: Vec<u8>,
+    /// JPEG thumbnail, if requested.
+    pub thumbnail: Option<Vec<u8>>,
+    /// Size before Huffman optimization, when it was enabled.
+    pub unoptimized_size: Option<usize>,
+}
+
+/// Encode a raw frame to the configured format and compute its fingerprint.
+pub fn encode_frame(raw: &RawFrame, encode: EncodeOptions) -> Result<EncodedImage> {
+    let img = if encode.needs_alpha() {
+        DynamicImage::ImageRgba8(to_rgba(raw)?)
+    } else {
+        DynamicImage::ImageRgb8(to_rgb(raw)?)
+    };
+    encode_image(&img, encode)
+}
+
+/// Convert a raw BGRA frame to an RGBA image.
+pub fn to_rgba(raw: &RawFrame) -> Result<RgbaImage> {
+    ImageBuffer::from_raw(raw.width as u32, raw.height as u32, convert_bgra(raw, 4)?)
+        .context("Pixel data does not match frame dimensions")
+}
+
+/// Convert a raw BGRA frame to an RGB image, skipping the alpha byte.
+pub fn to_rgb(raw: &RawFrame) -> Result<RgbImage> {
+    ImageBuffer::from_raw(raw.width as u32, raw.height as u32, convert_bgra(raw, 3)?)
+        .context("Pixel data does not match frame dimensions")
+}
+
+/// Repack BGRA rows into tightly packed RGB (`channels` = 3) or RGBA (4).
+fn convert_bgra(raw: &RawFrame, channels: usize) -> Result<Vec<u8>> {
+    let width = raw.width;
+    let height = raw.height;
+    let bytes_per_row = raw.bytes_per_row;
+    let pixel_data = &raw.data;
+    if pixel_data.is_empty() || width == 0 || height == 0 {
+        anyhow::bail!("Captured frame has no pixel data");
+    }
+
+    // Rows may be padded past width*4 bytes; the last row need not be
+    let row_bytes = width * 4;
+    if bytes_per_row < row_bytes {
+        anyhow::bail!(
+            "Row stride {} is smaller than {} pixels of BGRA data",
+            bytes_per_row,
+            width
+        );
+    }
+    let required = height.saturating_sub(1) * bytes_per_row + row_bytes;
+    if pixel_data.len() < required {
+        anyhow::bail!(
+            "Captured frame has {} bytes, expected at least {} for {}x{}",
+            pixel_data.len(),
+            required,
+            width,
+            height
+        );
+    }
+
+    let mut out = vec![0u8; width * height * channels];
+    for (y, dst_row) in out.chunks_exact_mut(width * channels).enumerate() {
+        let row_start = y * bytes_per_row;
+        let src_row = &pixel_data[row_start..row_start + row_bytes];
+        for (dst, src) in dst_row.chunks_exact_mut(channels).zip(src_row.chunks_exact(4)) {
+            dst[0] = src[2]; // R
+            dst[1] = src[1]; // G
+            dst[2] = src[0]; // B
+            if channels == 4 {
+                dst[3] = src[3]; // A
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Encode an RGB or RGBA image to the configured format and compute its fingerprint.
+pub fn encode_image(img: &DynamicImage, encode: EncodeOptions) -> Result<EncodedImage> {
+    let mut data = match (encode.format, encode.jpeg_subsampling) {
+        (OutputFormat::Jpeg, Some(subsampling)) => {
+            encode_jpeg_subsampled(img, encode.quality, subsampling, false)?
+        }
+        (OutputFormat::Jpeg, None) => encode_jpeg(img, encode.quality)?,
+        (OutputFormat::Avif, _) => {
+            let mut buffer = Cursor::new(Vec::new());
+            AvifEncoder::new_with_speed_quality(&mut buffer, AVIF_SPEED, encode.quality)
+                .write_image(img.as_bytes(), img.width(), img.height(), img.color().into())
+                .context("Failed to encode frame")?;
+            buffer.into_inner()
+        }
+    };
+
+    // Keep the optimized encoding only if it's actually smaller
+    let mut unoptimized_size = None;
+    if encode.format == OutputFormat::Jpeg && encode.optimize_jpeg {
+        // Unset means the image crate's encoder, which doesn't subsample
+        let subsampling = encode.jpeg_subsampling.unwrap_or(JpegSubsampling::S444);
+        let optimized = encode_jpeg_subsampled(img, encode.quality, subsampling, true)?;
+        unoptimized_size = Some(data.len());
+        if optimized.len() < data.len() {
+            data = optimized;
+        }
+    }
+
+    let thumbnail = encode
+        .thumbnail_width
+        .map(|max_width| encode_thumbnail(img, max_width, encode.quality))
+        .transpose()?;
+
+    Ok(EncodedImage {
+        data,
+        format: encode.format,
+        fingerprint: diff::fingerprint(img),
+        thumbnail,
+        unoptimized_size,
+    })
+}
+
+/// Encode a JPEG with the image crate's default settings.
+fn encode_jpeg(img: &DynamicImage, quality: u8) -> Result<Vec<u8>> {
+    let mut buffer = Cursor::new(Vec::new());
+    JpegEncoder::new_with_quality(&mut buffer, quality)
+        .write_image(img.as_bytes(), img.width(), img.height(), img.color().into())
+        .context("Failed to encode frame")?;
+    Ok(buffer.into_inner())
+}
+
+/// Downscale to at most `max_width` (preserving aspect ratio) and encode as JPEG.
+fn encode_thumbnail(img: &DynamicImage, max_width: u32, quality: u8) -> Result<Vec<u8>> {
+    if img.width() <= max_width {
+        return encode_jpeg(img, quality);
+    }
+    let height = (img.height() as u64 * max_width as u64 / img.width() as u64).max(1) as u32;
+    let thumb = img.resize_exact(max_width, height, FilterType::Triangle);
+    encode_jpeg(&thumb, quality).context("Failed to encode thumbnail")
+}
+
+/// Encode a JPEG with an explicit chroma subsampling ratio, optionally with
+/// Huffman tables optimized for the image (an extra pass, for smaller files).
+fn encode_jpeg_subsampled(
+    img: &DynamicImage,
+    quality: u8,
+    subsampling: JpegSubsampling,
+    optimize: bool,
+) -> Result<Vec<u8>> {
+    let width = u16::try_from(img.width()).context("Frame too wide for JPEG")?;
+    let height = u16::try_from(img.height()).context("Frame too tall for JPEG")?;
+
+    let mut data = Vec::new();
+    let mut encoder = jpeg_encoder::Encoder::new(&mut data, quality);
+    encoder.set_sampling_factor(match subsampling {
+        JpegSubsampling::S444 => SamplingFactor::R_4_4_4,
+        JpegSubsampling::S422 => SamplingFactor::R_4_2_2,
+        JpegSubsampling::S420 => SamplingFactor::R_4_2_0,
+    });
+    encoder.set_optimized_huffman_tables(optimize);
+    let color = if img.color().has_alpha() {
+        jpeg_encoder::ColorType::Rgba
+    } else {
+        jpeg_encoder::ColorType::Rgb
+    };
+    encoder
+        .encode(img.as_bytes(), width, height, color)
+        .context("Failed to encode frame")?;
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 3x2 BGRA frame with 4 bytes of row padding, except after the last row.
+    fn padded_frame() -> RawFrame {
+        let mut data = Vec::new();
+        for y in 0..2u8 {
+            for x in 0..3u8 {
+                // B, G, R, A
+                data.extend_from_slice(&[x, y, 10 * x + y, 255 - x]);
+            }
+            if y == 0 {
+                data.extend_from_slice(&[0xEE; 4]);
+            }
+        }
+        RawFrame {
+            width: 3,
+            height: 2,
+            bytes_per_row: 16,
+            data,
+        }
+    }
+
+    #[test]
+    fn converts_padded_rows_with_odd_width() {
+        let rgba = to_rgba(&padded_frame()).unwrap();
+        assert_eq!(rgba.dimensions(), (3, 2));
+        for (x, y, pixel) in rgba.enumerate_pixels() {
+            let (x, y) = (x as u8, y as u8);
+            assert_eq!(pixel.0, [10 * x + y, y, x, 255 - x], "pixel ({}, {})", x, y);
+        }
+
+        let rgb = to_rgb(&padded_frame()).unwrap();
+        assert_eq!(rgb.as_raw().len(), 3 * 2 * 3);
+        assert_eq!(rgb.get_pixel(2, 1).0, [21, 1, 2]);
+    }
+
+    #[test]
+    fn rejects_truncated_pixel_data() {
+        let mut frame = padded_frame();
+        frame.data.pop();
+        assert!(to_rgba(&frame).is_err());
+
+        let mut frame = padded_frame();
+        frame.bytes_per_row = 8;
+        assert!(to_rgb(&frame).is_err());
+    }
+
+    /// Sampling factor byte (horizontal << 4 | vertical) of each component
+    /// in a JPEG's frame header.
+    fn sampling_factors(jpeg: &[u8]) -> Vec<u8> {
+        let mut at = 2;
+        while at + 4 <= jpeg.len() {
+            let marker = jpeg[at + 1];
+            let len = u16::from_be_bytes([jpeg[at + 2], jpeg[at + 3]]) as usize;
+            // Baseline, extended, or progressive frame header
+            if matches!(marker, 0xC0..=0xC2) {
+                let components = jpeg[at + 9] as usize;
+                return (0..components).map(|c| jpeg[at + 11 + c * 3]).collect();
+            }
+            at += 2 + len;
+        }
+        panic!("no frame header");
+    }
+
+    fn options(jpeg_subsampling: Option<JpegSubsampling>, optimize_jpeg: bool) -> EncodeOptions {
+        EncodeOptions {
+            format: OutputFormat::Jpeg,
+            quality: 80,
+            jpeg_subsampling,
+            thumbnail_width: None,
+            keep_alpha: false,
+            optimize_jpeg,
+        }
+    }
+
+    #[test]
+    fn optimized_jpegs_keep_the_configured_subsampling() {
+        let img = DynamicImage::ImageRgb8(to_rgb(&padded_frame()).unwrap());
+
+        // Unset, both encodings keep full chroma resolution
+        let plain = encode_image(&img, options(None, false)).unwrap();
+        assert_eq!(sampling_factors(&plain.data), [0x11, 0x11, 0x11]);
+        let optimized = encode_image(&img, options(None, true)).unwrap();
+        assert_eq!(sampling_factors(&optimized.data), [0x11, 0x11, 0x11]);
+
+        let optimized = encode_image(&img, options(Some(JpegSubsampling::S420), true)).unwrap();
+        assert_eq!(sampling_factors(&optimized.data), [0x22, 0x11, 0x11]);
+    }
+}
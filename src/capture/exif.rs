@@ -0,0 +1,265 @@
+//! Embedding capture metadata in JPEG frames as an EXIF APP1 segment.
+
+use chrono::{DateTime, Utc};
+use serde_json::json;
+
+/// JPEG start-of-image marker.
+const SOI: [u8; 2] = [0xFF, 0xD8];
+
+/// JPEG APP0 (JFIF) and APP1 (EXIF) marker bytes.
+const APP0: u8 = 0xE0;
+const APP1: u8 = 0xE1;
+
+/// Identifier that starts an EXIF APP1 payload.
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+
+// TIFF tags
+const TAG_IMAGE_DESCRIPTION: u16 = 0x010E;
+const TAG_SOFTWARE: u16 = 0x0131;
+const TAG_DATE_TIME: u16 = 0x0132;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+const TAG_OFFSET_TIME_ORIGINAL: u16 = 0x9011;
+const TAG_SUB_SEC_TIME_ORIGINAL: u16 = 0x9291;
+const TAG_PIXEL_X_DIMENSION: u16 = 0xA002;
+const TAG_PIXEL_Y_DIMENSION: u16 = 0xA003;
+
+// TIFF field types
+const TYPE_ASCII: u16 = 2;
+const TYPE_LONG: u16 = 4;
+
+/// Capture details written into each frame.
+pub struct FrameMetadata<'a> {
+    pub timestamp: DateTime<Utc>,
+    pub monitor_id: u32,
+    pub device_id: Option<&'a str>,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// An IFD field value.
+enum Value {
+    Ascii(String),
+    Long(u32),
+}
+
+/// Return `jpeg` with an EXIF segment describing the capture, placed after
+/// the JFIF header. Data that isn't a JPEG is returned unchanged.
+pub fn embed(jpeg: Vec<u8>, meta: &FrameMetadata) -> Vec<u8> {
+    if !jpeg.starts_with(&SOI) {
+        return jpeg;
+    }
+
+    let tiff = tiff_block(meta);
+    let Ok(segment_len) = u16::try_from(2 + EXIF_HEADER.len() + tiff.len()) else {
+        return jpeg;
+    };
+
+    // APP0 must stay first for JFIF readers, so insert after it when present
+    let mut insert_at = SOI.len();
+    if jpeg.get(2..4) == Some(&[0xFF, APP0]) {
+        if let Some(len) = jpeg.get(4..6).map(|b| u16::from_be_bytes([b[0], b[1]]) as usize) {
+            insert_at = (4 + len).min(jpeg.len());
+        }
+    }
+
+    let mut out = Vec::with_capacity(jpeg.len() + segment_len as usize + 2);
+    out.extend_from_slice(&jpeg[..insert_at]);
+    out.extend_from_slice(&[0xFF, APP1]);
+    out.extend_from_slice(&segment_len.to_be_bytes());
+    out.extend_from_slice(EXIF_HEADER);
+    out.extend_from_slice(&tiff);
+    out.extend_from_slice(&jpeg[insert_at..]);
+    out
+}
+
+/// Build a big-endian TIFF block with IFD0 and an EXIF sub-IFD.
+fn tiff_block(meta: &FrameMetadata) -> Vec<u8> {
+    let date_time = meta.timestamp.format("%Y:%m:%d %H:%M:%S").to_string();
+    let description = json!({
+        "timestamp": meta.timestamp,
+        "monitor_id": meta.monitor_id,
+        "device_id": meta.device_id,
+        "width": meta.width,
+        "height": meta.height,
+    })
+    .to_string();
+
+    let mut ifd0 = vec![
+        (TAG_IMAGE_DESCRIPTION, Value::Ascii(description)),
+        (TAG_SOFTWARE, Value::Ascii(format!("preprompter {}", env!("CARGO_PKG_VERSION")))),
+        (TAG_DATE_TIME, Value::Ascii(date_time.clone())),
+        (TAG_EXIF_IFD, Value::Long(0)),
+    ];
+    let exif = [
+        (TAG_DATE_TIME_ORIGINAL, Value::Ascii(date_time)),
+        (TAG_OFFSET_TIME_ORIGINAL, Value::Ascii("+00:00".to_string())),
+        (TAG_SUB_SEC_TIME_ORIGINAL, Value::Ascii(meta.timestamp.format("%3f").to_string())),
+        (TAG_PIXEL_X_DIMENSION, Value::Long(meta.width)),
+        (TAG_PIXEL_Y_DIMENSION, Value::Long(meta.height)),
+    ];
+
+    // The EXIF IFD follows IFD0 and its out-of-line values
+    let ifd0_offset = 8;
+    let exif_offset = ifd0_offset + ifd_len(&ifd0);
+    ifd0[3].1 = Value::Long(exif_offset);
+
+    let mut out = Vec::new();
+    out.extend_from_slice(b"MM");
+    out.extend_from_slice(&42u16.to_be_bytes());
+    out.extend_from_slice(&ifd0_offset.to_be_bytes());
+    write_ifd(&mut out, &ifd0);
+    write_ifd(&mut out, &exif);
+    out
+}
+
+/// Bytes taken by an IFD including values stored outside its entries.
+fn ifd_len(entries: &[(u16, Value)]) -> u32 {
+    let data: usize = entries.iter().map(|(_, value)| out_of_line_len(value)).sum();
+    (2 + entries.len() * 12 + 4 + data) as u32
+}
+
+/// Bytes a value needs past its entry (word-aligned), or 0 if it fits inline.
+fn out_of_line_len(value: &Value) -> usize {
+    match value {
+        Value::Ascii(s) if s.len() + 1 > 4 => (s.len() + 1).next_multiple_of(2),
+        _ => 0,
+    }
+}
+
+/// Append an IFD with no successor, offsets relative to the TIFF header at `out[0]`.
+fn write_ifd(out: &mut Vec<u8>, entries: &[(u16, Value)]) {
+    let start = out.len();
+    let mut data_offset = start + 2 + entries.len() * 12 + 4;
+    let mut data = Vec::new();
+
+    out.extend_from_slice(&(entries.len() as u16).to_be_bytes());
+    for (tag, value) in entries {
+        out.extend_from_slice(&tag.to_be_bytes());
+        match value {
+            Value::Ascii(s) => {
+                let mut bytes = s.as_bytes().to_vec();
+                bytes.push(0);
+                out.extend_from_slice(&TYPE_ASCII.to_be_bytes());
+                out.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+                if bytes.len() <= 4 {
+                    bytes.resize(4, 0);
+                    out.extend_from_slice(&bytes);
+                } else {
+                    out.extend_from_slice(&(data_offset as u32).to_be_bytes());
+                    let padded = out_of_line_len(value);
+                    bytes.resize(padded, 0);
+                    data_offset += padded;
+                    data.extend_from_slice(&bytes);
+                }
+            }
+            Value::Long(n) => {
+                out.extend_from_slice(&TYPE_LONG.to_be_bytes());
+                out.extend_from_slice(&1u32.to_be_bytes());
+                out.extend_from_slice(&n.to_be_bytes());
+            }
+        }
+    }
+    out.extend_from_slice(&0u32.to_be_bytes());
+    out.extend_from_slice(&data);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::jpeg::{JpegDecoder, JpegEncoder};
+    use image::{ImageDecoder, RgbImage};
+    use std::collections::HashMap;
+    use std::io::Cursor;
+
+    fn jpeg() -> Vec<u8> {
+        let mut out = Vec::new();
+        JpegEncoder::new(&mut out)
+            .encode_image(&RgbImage::from_pixel(8, 6, image::Rgb([10, 20, 30])))
+            .unwrap();
+        out
+    }
+
+    fn meta() -> FrameMetadata<'static> {
+        FrameMetadata {
+            timestamp: DateTime::parse_from_rfc3339("2024-03-05T06:07:08.123Z")
+                .unwrap()
+                .with_timezone(&Utc),
+            monitor_id: 2,
+            device_id: Some("laptop"),
+            width: 8,
+            height: 6,
+        }
+    }
+
+    /// Read an IFD's ASCII and LONG fields from a big-endian TIFF block.
+    fn read_ifd(tiff: &[u8], offset: usize) -> HashMap<u16, Value> {
+        let u16_at = |at: usize| u16::from_be_bytes([tiff[at], tiff[at + 1]]);
+        let u32_at = |at: usize| u32::from_be_bytes(tiff[at..at + 4].try_into().unwrap());
+
+        let mut fields = HashMap::new();
+        for i in 0..u16_at(offset) as usize {
+            let entry = offset + 2 + i * 12;
+            let count = u32_at(entry + 4) as usize;
+            let value = match u16_at(entry + 2) {
+                TYPE_ASCII => {
+                    let at = if count <= 4 { entry + 8 } else { u32_at(entry + 8) as usize };
+                    let bytes = &tiff[at..at + count];
+                    assert_eq!(bytes.last(), Some(&0), "ASCII field must be NUL-terminated");
+                    Value::Ascii(String::from_utf8(bytes[..count - 1].to_vec()).unwrap())
+                }
+                TYPE_LONG => Value::Long(u32_at(entry + 8)),
+                other => panic!("unexpected field type {}", other),
+            };
+            fields.insert(u16_at(entry), value);
+        }
+        fields
+    }
+
+    fn ascii(fields: &HashMap<u16, Value>, tag: u16) -> &str {
+        match &fields[&tag] {
+            Value::Ascii(s) => s,
+            Value::Long(_) => panic!("tag {:#x} is not ASCII", tag),
+        }
+    }
+
+    fn long(fields: &HashMap<u16, Value>, tag: u16) -> u32 {
+        match fields[&tag] {
+            Value::Long(n) => n,
+            Value::Ascii(_) => panic!("tag {:#x} is not a LONG", tag),
+        }
+    }
+
+    #[test]
+    fn embedded_metadata_round_trips() {
+        let tagged = embed(jpeg(), &meta());
+
+        // Still a JPEG the decoder reads, with the JFIF header kept first
+        assert_eq!(&tagged[2..4], &[0xFF, APP0]);
+        let mut decoder = JpegDecoder::new(Cursor::new(&tagged)).unwrap();
+        assert_eq!(decoder.dimensions(), (8, 6));
+        let tiff = decoder.exif_metadata().unwrap().expect("EXIF segment");
+        image::load_from_memory(&tagged).unwrap();
+
+        assert_eq!(&tiff[..4], b"MM\0\x2A");
+        let ifd0 = read_ifd(&tiff, u32::from_be_bytes(tiff[4..8].try_into().unwrap()) as usize);
+        assert_eq!(ascii(&ifd0, TAG_DATE_TIME), "2024:03:05 06:07:08");
+        assert!(ascii(&ifd0, TAG_SOFTWARE).starts_with("preprompter "));
+        let description: serde_json::Value = serde_json::from_str(ascii(&ifd0, TAG_IMAGE_DESCRIPTION)).unwrap();
+        assert_eq!(description["monitor_id"], 2);
+        assert_eq!(description["device_id"], "laptop");
+        assert_eq!(description["width"], 8);
+
+        let exif = read_ifd(&tiff, long(&ifd0, TAG_EXIF_IFD) as usize);
+        assert_eq!(ascii(&exif, TAG_DATE_TIME_ORIGINAL), "2024:03:05 06:07:08");
+        assert_eq!(ascii(&exif, TAG_OFFSET_TIME_ORIGINAL), "+00:00");
+        assert_eq!(ascii(&exif, TAG_SUB_SEC_TIME_ORIGINAL), "123");
+        assert_eq!(long(&exif, TAG_PIXEL_X_DIMENSION), 8);
+        assert_eq!(long(&exif, TAG_PIXEL_Y_DIMENSION), 6);
+    }
+
+    #[test]
+    fn non_jpeg_data_is_left_alone() {
+        assert_eq!(embed(b"\x00\x00\x00\x1cftypavif".to_vec(), &meta()), b"\x00\x00\x00\x1cftypavif");
+    }
+}
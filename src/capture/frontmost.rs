@@ -0,0 +1,115 @@
+//! Foreground application and full-screen lookup via the CoreGraphics window list.
+
+use core_foundation::array::CFArray;
+use core_foundation::base::{CFType, TCFType};
+use core_foundation::dictionary::CFDictionary;
+use core_foundation::number::CFNumber;
+use core_foundation::string::CFString;
+use core_graphics::geometry::CGRect;
+
+/// Only windows currently on screen.
+const LIST_ON_SCREEN_ONLY: u32 = 1 << 0;
+/// Skip the desktop and its icons.
+const LIST_EXCLUDE_DESKTOP_ELEMENTS: u32 = 1 << 4;
+/// Window layer used by ordinary application windows.
+const NORMAL_WINDOW_LAYER: i64 = 0;
+/// Slack, in points, when comparing a window's bounds to a display's.
+const BOUNDS_TOLERANCE: f64 = 1.0;
+/// Most displays checked for a full-screen window.
+const MAX_DISPLAYS: usize = 16;
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGGetActiveDisplayList(max_displays: u32, displays: *mut u32, display_count: *mut u32) -> i32;
+    fn CGDisplayBounds(display: u32) -> CGRect;
+}
+
+/// The application owning the frontmost window.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForegroundApp {
+    /// Application name, e.g. "Code" or "Safari".
+    pub name: String,
+    /// Process ID of the application.
+    pub pid: i32,
+}
+
+impl ForegroundApp {
+    /// Returns true if the app name matches any entry, ignoring case.
+    pub fn matches_any(&self, names: &[String]) -> bool {
+        names.iter().any(|n| n.eq_ignore_ascii_case(&self.name))
+    }
+}
+
+/// Find the application owning the frontmost normal window.
+pub fn foreground_app() -> Option<ForegroundApp> {
+    frontmost_window().map(|(app, _)| app)
+}
+
+/// Find the application whose frontmost window covers an entire display,
+/// as full-screen apps, presentations, and full-screen video calls do.
+pub fn fullscreen_app() -> Option<ForegroundApp> {
+    let (app, bounds) = frontmost_window()?;
+    let bounds = bounds?;
+
+    let mut displays = [0u32; MAX_DISPLAYS];
+    let mut count = 0;
+    // SAFETY: the list holds MAX_DISPLAYS entries, and count reports how many were written
+    let err = unsafe { CGGetActiveDisplayList(MAX_DISPLAYS as u32, displays.as_mut_ptr(), &mut count) };
+    if err != 0 {
+        return None;
+    }
+    displays[..count as usize]
+        .iter()
+        .any(|&id| covers(&bounds, &unsafe { CGDisplayBounds(id) }))
+        .then_some(app)
+}
+
+/// Returns true if `window` matches `display`, to within a point.
+fn covers(window: &CGRect, display: &CGRect) -> bool {
+    let close = |a: f64, b: f64| (a - b).abs() <= BOUNDS_TOLERANCE;
+    close(window.origin.x, display.origin.x)
+        && close(window.origin.y, display.origin.y)
+        && close(window.size.width, display.size.width)
+        && close(window.size.height, display.size.height)
+}
+
+/// The frontmost normal window's owner and bounds (in global points).
+fn frontmost_window() -> Option<(ForegroundApp, Option<CGRect>)> {
+    #[link(name = "CoreGraphics", kind = "framework")]
+    extern "C" {
+        fn CGWindowListCopyWindowInfo(
+            option: u32,
+            relative_to_window: u32,
+        ) -> core_foundation::array::CFArrayRef;
+    }
+
+    let windows: CFArray<CFDictionary<CFString, CFType>> = unsafe {
+        let list = CGWindowListCopyWindowInfo(LIST_ON_SCREEN_ONLY | LIST_EXCLUDE_DESKTOP_ELEMENTS, 0);
+        if list.is_null() {
+            return None;
+        }
+        CFArray::wrap_under_create_rule(list)
+    };
+
+    let layer_key = CFString::from_static_string("kCGWindowLayer");
+    let name_key = CFString::from_static_string("kCGWindowOwnerName");
+    let pid_key = CFString::from_static_string("kCGWindowOwnerPID");
+    let bounds_key = CFString::from_static_string("kCGWindowBounds");
+
+    // The list is ordered front to back; menu bar and overlays sit on other layers
+    windows.iter().find_map(|window| {
+        let layer = window.find(&layer_key)?.downcast::<CFNumber>()?.to_i64()?;
+        if layer != NORMAL_WINDOW_LAYER {
+            return None;
+        }
+        let app = ForegroundApp {
+            name: window.find(&name_key)?.downcast::<CFString>()?.to_string(),
+            pid: window.find(&pid_key)?.downcast::<CFNumber>()?.to_i32()?,
+        };
+        let bounds = window
+            .find(&bounds_key)
+            .and_then(|b| b.downcast::<CFDictionary>())
+            .and_then(|b| CGRect::from_dict_representation(&b));
+        Some((app, bounds))
+    })
+}
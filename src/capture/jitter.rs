@@ -0,0 +1,70 @@
+//! Random capture timing offsets that keep fleets of machines from capturing
+//! and uploading in lockstep.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::time::Duration;
+
+/// Draws start offsets and per-tick delays, reproducibly when seeded.
+pub struct Jitter {
+    rng: StdRng,
+    start: bool,
+    max_tick_delay: Duration,
+}
+
+impl Jitter {
+    /// Create a jitter source; a `seed` makes the sequence of delays deterministic.
+    pub fn new(start: bool, max_tick_delay: Duration, seed: Option<u64>) -> Self {
+        Self {
+            rng: match seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_entropy(),
+            },
+            start,
+            max_tick_delay,
+        }
+    }
+
+    /// Delay before the first capture: uniform in `0..period` if start jitter is enabled.
+    pub fn start_offset(&mut self, period: Duration) -> Duration {
+        if !self.start || period.is_zero() {
+            return Duration::ZERO;
+        }
+        Duration::from_millis(self.rng.gen_range(0..period.as_millis() as u64))
+    }
+
+    /// Extra delay before an individual capture, uniform in `0..=max_tick_delay`.
+    pub fn tick_delay(&mut self) -> Duration {
+        if self.max_tick_delay.is_zero() {
+            return Duration::ZERO;
+        }
+        Duration::from_millis(self.rng.gen_range(0..=self.max_tick_delay.as_millis() as u64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delays(jitter: &mut Jitter) -> Vec<Duration> {
+        let mut delays = vec![jitter.start_offset(Duration::from_secs(60))];
+        delays.extend((0..20).map(|_| jitter.tick_delay()));
+        delays
+    }
+
+    #[test]
+    fn seeded_jitter_is_reproducible() {
+        let max = Duration::from_secs(5);
+        let first = delays(&mut Jitter::new(true, max, Some(42)));
+        assert_eq!(first, delays(&mut Jitter::new(true, max, Some(42))));
+        assert_ne!(first, delays(&mut Jitter::new(true, max, Some(43))));
+        assert!(first[0] < Duration::from_secs(60));
+        assert!(first[1..].iter().all(|d| *d <= max));
+    }
+
+    #[test]
+    fn disabled_jitter_never_delays() {
+        let mut jitter = Jitter::new(false, Duration::ZERO, Some(42));
+        assert!(delays(&mut jitter).iter().all(Duration::is_zero));
+    }
+}
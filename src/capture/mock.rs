@@ -0,0 +1,130 @@
+//! Synthetic frames for running the pipeline without a real display (e.g. CI).
+
+use anyhow::{Context, Result};
+
+use super::encode::RawFrame;
+use super::screen::MonitorInfo;
+
+/// Environment variable enabling mock capture: `1` for the default size or
+/// `WIDTHxHEIGHT` for a custom one.
+pub const MOCK_CAPTURE_ENV: &str = "PREPROMPTER_MOCK_CAPTURE";
+
+/// Display ID reported for the mock display.
+const MOCK_MONITOR_ID: u32 = 1;
+
+/// Size of the mock display when none is given.
+const DEFAULT_MOCK_SIZE: (u32, u32) = (1920, 1080);
+
+/// Width of the bar that moves across each successive frame.
+const BAR_WIDTH: usize = 64;
+
+/// A fake display producing a moving test pattern.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MockDisplay {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl MockDisplay {
+    /// Read the mock display from the environment, if mock capture is enabled.
+    pub fn from_env() -> Result<Option<Self>> {
+        match std::env::var(MOCK_CAPTURE_ENV) {
+            Ok(value) => Self::parse(&value).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self> {
+        let value = value.trim();
+        let (width, height) = match value {
+            "" | "1" | "true" => DEFAULT_MOCK_SIZE,
+            _ => {
+                let (w, h) = value
+                    .split_once('x')
+                    .with_context(|| format!("Invalid {} '{}' (expected 1 or WIDTHxHEIGHT)", MOCK_CAPTURE_ENV, value))?;
+                let parse = |n: &str| {
+                    n.parse::<u32>()
+                        .ok()
+                        .filter(|&n| n > 0)
+                        .with_context(|| format!("Invalid {} dimension '{}'", MOCK_CAPTURE_ENV, n))
+                };
+                (parse(w)?, parse(h)?)
+            }
+        };
+        Ok(Self { width, height })
+    }
+
+    /// The monitor list reported while mocking.
+    pub fn monitors(&self) -> Vec<MonitorInfo> {
+        vec![MonitorInfo {
+            id: MOCK_MONITOR_ID,
+            width: self.width,
+            height: self.height,
+            scale_factor: 1.0,
+            is_primary: true,
+        }]
+    }
+
+    /// Display ID recorded on mock frames.
+    pub fn monitor_id(&self) -> u32 {
+        MOCK_MONITOR_ID
+    }
+}
+
+/// Render frame number `counter` as BGRA: a background whose color cycles
+/// with the counter and a white bar that moves across so frames differ.
+pub fn synthetic_frame(width: u32, height: u32, counter: u64) -> RawFrame {
+    let width = width.max(1) as usize;
+    let height = height.max(1) as usize;
+    let shade = (counter.wrapping_mul(16) % 256) as u8;
+    let background = [shade, 255 - shade, 128, 255];
+    let bar_start = (counter as usize * BAR_WIDTH) % width;
+
+    let mut data = Vec::with_capacity(width * height * 4);
+    for _ in 0..height {
+        for x in 0..width {
+            let in_bar = (x + width - bar_start) % width < BAR_WIDTH;
+            data.extend_from_slice(if in_bar { &[255, 255, 255, 255] } else { &background });
+        }
+    }
+
+    RawFrame {
+        width,
+        height,
+        bytes_per_row: width * 4,
+        data,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::encode::{to_rgb, to_rgba};
+
+    #[test]
+    fn synthetic_frames_draw_a_moving_bar() {
+        let frame = synthetic_frame(200, 2, 1);
+        assert_eq!(frame.data.len(), 200 * 2 * 4);
+        let pixel = |x: usize, y: usize| &frame.data[y * frame.bytes_per_row + x * 4..][..4];
+
+        // BGRA background for counter 1, and the bar one bar-width in
+        assert_eq!(pixel(0, 0), [16, 239, 128, 255]);
+        assert_eq!(pixel(BAR_WIDTH, 1), [255, 255, 255, 255]);
+        assert_eq!(pixel(2 * BAR_WIDTH - 1, 0), [255, 255, 255, 255]);
+        assert_eq!(pixel(2 * BAR_WIDTH, 1), [16, 239, 128, 255]);
+        assert_ne!(synthetic_frame(200, 2, 2).data, frame.data);
+    }
+
+    #[test]
+    fn synthetic_frames_convert_to_rgb_and_rgba() {
+        let frame = synthetic_frame(200, 2, 1);
+
+        let rgba = to_rgba(&frame).unwrap();
+        assert_eq!(rgba.get_pixel(0, 0).0, [128, 239, 16, 255]);
+        assert_eq!(rgba.get_pixel(BAR_WIDTH as u32, 0).0, [255, 255, 255, 255]);
+
+        let rgb = to_rgb(&frame).unwrap();
+        assert_eq!(rgb.as_raw().len(), 200 * 2 * 3);
+        assert_eq!(rgb.get_pixel(0, 1).0, [128, 239, 16]);
+    }
+}
@@ -1,6 +1,35 @@
 //! Screen capture module using ScreenCaptureKit.
 
+mod adaptive;
+mod app_switch;
+mod audio;
+mod backoff;
+mod color;
+mod cursor;
+mod diff;
+mod displays;
+mod encode;
+mod exif;
+mod frontmost;
+mod jitter;
+mod mock;
+mod permission;
+mod quality;
 mod screen;
+mod source;
+mod stream;
 
-pub use screen::{CapturedFrame, ScreenCapture};
+pub use adaptive::AdaptiveInterval;
+pub use app_switch::watch_app_switches;
+pub use backoff::CaptureBackoff;
+pub use color::ColorConversion;
+pub use diff::difference;
+pub use displays::{active_display_count, DisplayWatcher};
+pub use frontmost::{foreground_app, fullscreen_app, ForegroundApp};
+pub use jitter::Jitter;
+pub use mock::MockDisplay;
+pub use permission::check_screen_capture_access;
+pub use quality::AdaptiveQuality;
+pub use screen::{CaptureReason, CapturedFrame, FrameTooLarge, MonitorFailure, MonitorInfo, ScreenCapture};
+pub use source::FrameSource;
 
@@ -0,0 +1,43 @@
+//! The macOS Screen Recording permission, checked before capture starts.
+
+use tracing::{debug, warn};
+
+/// Where the permission is granted, for log messages.
+pub const SETTINGS_PATH: &str = "System Settings → Privacy & Security → Screen Recording";
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGPreflightScreenCaptureAccess() -> bool;
+    fn CGRequestScreenCaptureAccess() -> bool;
+}
+
+/// Check whether the process has Screen Recording permission.
+pub fn has_screen_capture_access() -> bool {
+    unsafe { CGPreflightScreenCaptureAccess() }
+}
+
+/// Check the permission before the first capture, so a missing grant is
+/// reported plainly instead of as captures timing out. With `request`, ask
+/// macOS to show its permission prompt (only shown once per app; after
+/// that the grant has to be made in System Settings). Returns true if
+/// permission is granted.
+pub fn check_screen_capture_access(request: bool) -> bool {
+    if has_screen_capture_access() {
+        debug!("Screen Recording permission granted");
+        return true;
+    }
+
+    if request {
+        warn!("Screen Recording permission not granted, requesting it");
+        // Returns immediately; the user's answer applies to later checks
+        if unsafe { CGRequestScreenCaptureAccess() } {
+            return true;
+        }
+    }
+    warn!(
+        "Screen Recording permission is required to capture. Allow preprompter (or the terminal \
+         running it) in {}; macOS may need it restarted before the grant takes effect",
+        SETTINGS_PATH
+    );
+    false
+}
@@ -0,0 +1,110 @@
+//! Encoding quality that backs off while uploads are slow.
+
+use std::collections::VecDeque;
+
+use crate::config::AdaptiveQualityConfig;
+
+/// Lowers quality when the moving average of upload times rises above a
+/// threshold, and restores it toward the configured quality once uploads
+/// speed up again.
+pub struct AdaptiveQuality {
+    max: u8,
+    min: u8,
+    step: u8,
+    current: u8,
+    slow_upload_ms: u64,
+    fast_upload_ms: u64,
+    window: usize,
+    samples: VecDeque<u64>,
+}
+
+impl AdaptiveQuality {
+    /// Create a controller starting at (and never exceeding) `quality`.
+    pub fn new(config: &AdaptiveQualityConfig, quality: u8) -> Self {
+        Self {
+            max: quality,
+            min: config.min_quality.min(quality),
+            step: config.step,
+            current: quality,
+            slow_upload_ms: config.slow_upload_ms,
+            fast_upload_ms: config.fast_upload_ms,
+            window: config.window,
+            samples: VecDeque::with_capacity(config.window),
+        }
+    }
+
+    /// Record a failed or timed-out upload, which counts as a slow one.
+    /// Returns the new quality if it changed.
+    pub fn record_failure(&mut self) -> Option<u8> {
+        self.record(self.slow_upload_ms)
+    }
+
+    /// Record an upload duration. Returns the new quality if it changed.
+    pub fn record(&mut self, upload_ms: u64) -> Option<u8> {
+        if self.samples.len() == self.window {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(upload_ms);
+        if self.samples.len() < self.window {
+            return None;
+        }
+
+        let average = self.samples.iter().sum::<u64>() / self.samples.len() as u64;
+        let next = if average >= self.slow_upload_ms {
+            // Congested link: smaller frames
+            self.current.saturating_sub(self.step).max(self.min)
+        } else if average <= self.fast_upload_ms {
+            // Link recovered: restore quality
+            self.current.saturating_add(self.step).min(self.max)
+        } else {
+            self.current
+        };
+
+        if next == self.current {
+            return None;
+        }
+
+        // Judge the new quality on fresh samples only
+        self.samples.clear();
+        self.current = next;
+        Some(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn controller() -> AdaptiveQuality {
+        let config = AdaptiveQualityConfig {
+            min_quality: 40,
+            step: 10,
+            slow_upload_ms: 2000,
+            fast_upload_ms: 500,
+            window: 2,
+        };
+        AdaptiveQuality::new(&config, 80)
+    }
+
+    #[test]
+    fn slow_uploads_lower_quality_and_fast_ones_restore_it() {
+        let mut quality = controller();
+        assert_eq!(quality.record(3000), None);
+        assert_eq!(quality.record(3000), Some(70));
+        assert_eq!(quality.record(100), None);
+        assert_eq!(quality.record(100), Some(80));
+        assert_eq!(quality.record(100), None);
+        assert_eq!(quality.record(100), None);
+    }
+
+    #[test]
+    fn failed_uploads_count_as_slow() {
+        let mut quality = controller();
+        assert_eq!(quality.record_failure(), None);
+        assert_eq!(quality.record_failure(), Some(70));
+        for _ in 0..10 {
+            quality.record_failure();
+        }
+        assert_eq!(quality.current, 40);
+    }
+}
@@ -2,29 +2,60 @@
 
 use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
-use image::codecs::jpeg::JpegEncoder;
-use image::{ImageBuffer, Rgba};
-use screencapturekit::cv::CVPixelBufferLockFlags;
 use screencapturekit::prelude::*;
-use std::io::Cursor;
-use std::sync::atomic::{AtomicBool, Ordering};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
-use std::time::Instant;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+use image::{imageops, DynamicImage, ImageBuffer, Pixel, Rgb, Rgba};
+
+use super::encode::{encode_frame, encode_image, to_rgb, to_rgba, EncodeOptions, EncodedImage, RawFrame};
+use super::audio;
+use super::color::{self, ColorConversion};
+use super::cursor;
+use super::displays;
+use super::exif::{self, FrameMetadata};
+use super::mock::{synthetic_frame, MockDisplay};
+use super::permission::{self, has_screen_capture_access};
+use super::stream::{DisplayStream, FrameReader, FrameUnavailable, StreamGeometry};
+use crate::config::{CaptureRegion, ColorSpace, CursorFollow, JpegSubsampling, MonitorErrorPolicy, OutputFormat};
+use crate::storage::KeyLayout;
+
+/// Longest minimum time between frames delivered by a capture stream.
+const STREAM_FRAME_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Monitor ID recorded for stitched frames, which span every display.
+const STITCHED_MONITOR_ID: u32 = 0;
+
+/// Fill color for canvas areas not covered by any display.
+const STITCH_BACKGROUND: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
+/// Opaque fill color for stitched frames without an alpha channel.
+const STITCH_BACKGROUND_RGB: Rgb<u8> = Rgb([0, 0, 0]);
 
 /// Information about a display/monitor.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MonitorInfo {
     pub id: u32,
+    /// Width in points.
     pub width: u32,
+    /// Height in points.
     pub height: u32,
+    /// Pixels per point, e.g. 2.0 on Retina.
+    pub scale_factor: f64,
     pub is_primary: bool,
 }
 
 /// A captured frame with metadata.
 #[derive(Debug, Clone)]
 pub struct CapturedFrame {
-    /// JPEG-encoded frame data.
+    /// Encoded frame data.
     pub data: Vec<u8>,
+    /// Image format of `data`.
+    pub format: OutputFormat,
     /// Frame width in pixels.
     pub width: u32,
     /// Frame height in pixels.
@@ -35,13 +66,123 @@ pub struct CapturedFrame {
     pub monitor_id: u32,
     /// Duration it took to capture and encode the frame.
     pub capture_duration_ms: u64,
+    /// Coarse grayscale fingerprint used for change detection.
+    pub fingerprint: Vec<u8>,
+    /// Downscaled JPEG preview, when thumbnails are enabled.
+    pub thumbnail: Option<Vec<u8>>,
+    /// Captured on demand (e.g. via the hotkey) rather than on schedule.
+    pub manual: bool,
+    /// Why the frame was captured outside the regular interval, other than on demand.
+    pub reason: Option<CaptureReason>,
+    /// Whether audio output was in use at capture time, when recorded.
+    pub audio_active: Option<bool>,
+    /// Keyboard and mouse events since the previous capture, when recorded.
+    pub input_events: Option<u64>,
+    /// Area captured around the cursor, in points relative to the monitor
+    /// (follow-cursor mode only).
+    pub region: Option<CaptureRegion>,
+    /// Encoded size before Huffman optimization, when `optimize_jpeg` is on.
+    pub unoptimized_size: Option<usize>,
+    /// Pixels per point of the captured display (e.g. 2.0 on Retina), when
+    /// the frame comes from a single display.
+    pub scale_factor: Option<f64>,
+    /// How the pixels were color matched, when normalizing to sRGB.
+    pub color_space: Option<ColorConversion>,
 }
 
-/// Screen capture manager using ScreenCaptureKit.
-pub struct ScreenCapture {
+/// Why a frame was captured between scheduled ticks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptureReason {
+    /// A different application came to the front.
+    AppSwitch,
+    /// The screen was unlocked after being locked.
+    Unlock,
+}
+
+/// A monitor that failed while capturing all monitors.
+#[derive(Debug, Clone)]
+pub struct MonitorFailure {
+    pub monitor_id: u32,
+    pub error: String,
+    /// Whether the capture was retried before giving up.
+    pub retried: bool,
+}
+
+/// An encoded frame from a specific display.
+struct EncodedFrame {
+    image: EncodedImage,
+    width: u32,
+    height: u32,
+    monitor_id: u32,
+    region: Option<CaptureRegion>,
+    scale_factor: Option<f64>,
+    color_space: Option<ColorConversion>,
+}
+
+impl EncodedFrame {
+    fn into_captured(self, timestamp: DateTime<Utc>, capture_duration_ms: u64) -> CapturedFrame {
+        CapturedFrame {
+            data: self.image.data,
+            format: self.image.format,
+            width: self.width,
+            height: self.height,
+            timestamp,
+            monitor_id: self.monitor_id,
+            capture_duration_ms,
+            fingerprint: self.image.fingerprint,
+            thumbnail: self.image.thumbnail,
+            manual: false,
+            reason: None,
+            audio_active: None,
+            input_events: None,
+            region: self.region,
+            unoptimized_size: self.image.unoptimized_size,
+            scale_factor: self.scale_factor,
+            color_space: self.color_space,
+        }
+    }
+}
+
+/// Capture settings shared with blocking capture tasks.
+#[derive(Debug, Clone, Copy)]
+struct CaptureSettings {
     monitor_id: i32,
-    jpeg_quality: u8,
+    encode: EncodeOptions,
     resolution_scale: f32,
+    /// Cap on the longest side of a frame, applied after `resolution_scale`.
+    max_dimension: Option<u32>,
+    region: Option<CaptureRegion>,
+    /// Capture a region of this size centered on the cursor instead.
+    follow_cursor: Option<CursorFollow>,
+    timeout: Duration,
+    /// Largest uncompressed RGBA frame to allocate (None = no limit).
+    max_frame_bytes: Option<u64>,
+    frame_interval: Duration,
+    stitch: bool,
+    on_monitor_error: MonitorErrorPolicy,
+    /// Color space pixels are converted to before encoding.
+    color_space: ColorSpace,
+    /// Produce synthetic frames instead of capturing a real display.
+    mock: Option<MockDisplay>,
+}
+
+/// Screen capture manager using ScreenCaptureKit.
+///
+/// Streams are started on first capture and kept running, so each capture
+/// only has to grab and encode the latest frame.
+pub struct ScreenCapture {
+    settings: CaptureSettings,
+    streams: Arc<Mutex<Vec<DisplayStream>>>,
+    timeouts: Arc<AtomicU64>,
+    /// Per-monitor failures from all-monitor captures, until taken.
+    monitor_failures: Arc<Mutex<Vec<MonitorFailure>>>,
+    mock_frames: AtomicU64,
+    /// Write capture metadata into each JPEG's EXIF block.
+    embed_metadata: bool,
+    device_id: Option<String>,
+    /// Record whether audio output is active with each frame.
+    audio_activity: bool,
 }
 
 impl ScreenCapture {
@@ -51,12 +192,160 @@ impl ScreenCapture {
         let quality = jpeg_quality.clamp(1, 100);
         let scale = resolution_scale.clamp(0.1, 1.0);
         Ok(Self {
-            monitor_id,
-            jpeg_quality: quality,
-            resolution_scale: scale,
+            settings: CaptureSettings {
+                monitor_id,
+                encode: EncodeOptions {
+                    format: OutputFormat::default(),
+                    quality,
+                    jpeg_subsampling: None,
+                    thumbnail_width: None,
+                    keep_alpha: false,
+                    optimize_jpeg: false,
+                },
+                resolution_scale: scale,
+                max_dimension: None,
+                region: None,
+                follow_cursor: None,
+                timeout: Duration::from_secs(5),
+                max_frame_bytes: None,
+                frame_interval: STREAM_FRAME_INTERVAL,
+                stitch: false,
+                on_monitor_error: MonitorErrorPolicy::default(),
+                color_space: ColorSpace::default(),
+                mock: None,
+            },
+            streams: Arc::new(Mutex::new(Vec::new())),
+            timeouts: Arc::new(AtomicU64::new(0)),
+            monitor_failures: Arc::new(Mutex::new(Vec::new())),
+            mock_frames: AtomicU64::new(0),
+            embed_metadata: false,
+            device_id: None,
+            audio_activity: false,
         })
     }
 
+    /// Restrict capture to a sub-region of the monitor.
+    pub fn with_region(mut self, region: Option<CaptureRegion>) -> Self {
+        self.settings.region = region;
+        self
+    }
+
+    /// Capture a fixed-size region centered on the cursor, on whichever
+    /// display it's on, instead of the configured monitor.
+    pub fn with_follow_cursor(mut self, follow: Option<CursorFollow>) -> Self {
+        self.settings.follow_cursor = follow;
+        self
+    }
+
+    /// Set the image format frames are encoded to.
+    pub fn with_output_format(mut self, format: OutputFormat) -> Self {
+        self.settings.encode.format = format;
+        self
+    }
+
+    /// Set the JPEG chroma subsampling ratio.
+    pub fn with_jpeg_subsampling(mut self, subsampling: Option<JpegSubsampling>) -> Self {
+        self.settings.encode.jpeg_subsampling = subsampling;
+        self
+    }
+
+    /// Downscale frames whose longest side exceeds `max_dimension`.
+    pub fn with_max_dimension(mut self, max_dimension: Option<u32>) -> Self {
+        self.settings.max_dimension = max_dimension;
+        self
+    }
+
+    /// Composite all monitors into a single frame when capturing all monitors.
+    pub fn with_stitch(mut self, stitch: bool) -> Self {
+        self.settings.stitch = stitch;
+        self
+    }
+
+    /// Set what happens when one monitor fails while capturing all monitors.
+    pub fn with_on_monitor_error(mut self, policy: MonitorErrorPolicy) -> Self {
+        self.settings.on_monitor_error = policy;
+        self
+    }
+
+    /// Convert captured pixels to this color space before encoding.
+    pub fn with_color_space(mut self, color_space: ColorSpace) -> Self {
+        self.settings.color_space = color_space;
+        self
+    }
+
+    /// Also produce a JPEG thumbnail of at most this width for each frame.
+    pub fn with_thumbnail_width(mut self, width: Option<u32>) -> Self {
+        self.settings.encode.thumbnail_width = width;
+        self
+    }
+
+    /// Keep the alpha channel in output formats that support it.
+    pub fn with_keep_alpha(mut self, keep_alpha: bool) -> Self {
+        self.settings.encode.keep_alpha = keep_alpha;
+        self
+    }
+
+    /// Re-encode JPEG frames with optimized Huffman tables.
+    pub fn with_optimize_jpeg(mut self, optimize: bool) -> Self {
+        self.settings.encode.optimize_jpeg = optimize;
+        self
+    }
+
+    /// Let streams deliver frames at least as often as captures happen.
+    pub fn with_capture_interval(mut self, interval: Duration) -> Self {
+        self.settings.frame_interval = interval.min(STREAM_FRAME_INTERVAL);
+        self
+    }
+
+    /// Capture synthetic frames from a mock display instead of the screen.
+    pub fn with_mock(mut self, mock: Option<MockDisplay>) -> Self {
+        self.settings.mock = mock;
+        self
+    }
+
+    /// Embed timestamp, monitor, device, and dimensions as EXIF in JPEG frames.
+    pub fn with_embedded_metadata(mut self, enabled: bool, device_id: Option<String>) -> Self {
+        self.embed_metadata = enabled;
+        self.device_id = device_id;
+        self
+    }
+
+    /// Record whether audio output is in use alongside each frame.
+    pub fn with_audio_activity(mut self, enabled: bool) -> Self {
+        self.audio_activity = enabled;
+        self
+    }
+
+    /// Set how long to wait for a frame before failing.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.settings.timeout = timeout;
+        self
+    }
+
+    /// Skip frames whose RGBA pixels would exceed `max_bytes` (0 = no limit).
+    pub fn with_max_frame_bytes(mut self, max_bytes: u64) -> Self {
+        self.settings.max_frame_bytes = (max_bytes > 0).then_some(max_bytes);
+        self
+    }
+
+    /// Change the encoding quality for subsequent captures.
+    pub fn set_quality(&mut self, quality: u8) {
+        self.settings.encode.quality = quality.clamp(1, 100);
+    }
+
+    /// Number of captures that timed out waiting for a frame.
+    pub fn timeout_count(&self) -> u64 {
+        self.timeouts.load(Ordering::Relaxed)
+    }
+
+    /// Take the monitor failures recorded since the last call.
+    pub fn take_monitor_failures(&self) -> Vec<MonitorFailure> {
+        self.monitor_failures
+            .lock()
+            .map(|mut failures| std::mem::take(&mut *failures))
+            .unwrap_or_default()
+    }
+
     /// List all available monitors.
     pub fn list_monitors() -> Result<Vec<MonitorInfo>> {
         let content = SCShareableContent::get()
@@ -70,6 +359,7 @@ impl ScreenCapture {
                 id: display.display_id(),
                 width: display.width() as u32,
                 height: display.height() as u32,
+                scale_factor: displays::backing_scale(display.display_id()).unwrap_or(1.0),
                 is_primary: idx == 0, // First display is typically primary
             });
         }
@@ -78,266 +368,671 @@ impl ScreenCapture {
     }
 
     /// Capture a single frame from the configured monitor.
-    /// If monitor_id is -1, captures all monitors and returns a Vec.
     pub async fn capture(&self) -> Result<CapturedFrame> {
         let start = Instant::now();
         let timestamp = Utc::now();
-        let quality = self.jpeg_quality;
-        let monitor_id = self.monitor_id;
-        let resolution_scale = self.resolution_scale;
+        let settings = self.settings;
+        let streams = self.streams.clone();
+        let timeouts = self.timeouts.clone();
+        let counter = self.mock_frames.fetch_add(1, Ordering::Relaxed);
 
         // Run the blocking capture in a separate thread
-        let result = tokio::task::spawn_blocking(move || {
-            capture_frame_blocking(monitor_id, quality, resolution_scale)
+        let result = tokio::task::spawn_blocking(move || match (settings.mock, settings.follow_cursor) {
+            (Some(mock), _) => capture_mock_blocking(&mock, &settings, counter),
+            (None, Some(follow)) => capture_cursor_blocking(&streams, &settings, &timeouts, follow),
+            (None, None) => capture_frame_blocking(&streams, &settings, &timeouts),
         })
         .await
         .context("Capture task panicked")?
         .context("Capture failed")?;
 
         let capture_duration_ms = start.elapsed().as_millis() as u64;
+        let audio_active = self.audio_active();
 
-        Ok(CapturedFrame {
-            data: result.0,
-            width: result.1,
-            height: result.2,
-            timestamp,
-            monitor_id: result.3,
-            capture_duration_ms,
-        })
+        let mut frame = self.with_metadata(result.into_captured(timestamp, capture_duration_ms));
+        frame.audio_active = audio_active;
+        Ok(frame)
     }
 
     /// Capture all monitors and return a Vec of frames.
     pub async fn capture_all(&self) -> Result<Vec<CapturedFrame>> {
         let start = Instant::now();
         let timestamp = Utc::now();
-        let quality = self.jpeg_quality;
-        let resolution_scale = self.resolution_scale;
+        let settings = self.settings;
+        let streams = self.streams.clone();
+        let timeouts = self.timeouts.clone();
+        let failures = self.monitor_failures.clone();
+        let counter = self.mock_frames.fetch_add(1, Ordering::Relaxed);
 
         // Run the blocking capture in a separate thread
         let results = tokio::task::spawn_blocking(move || {
-            capture_all_monitors_blocking(quality, resolution_scale)
+            if let Some(mock) = settings.mock {
+                capture_mock_blocking(&mock, &settings, counter).map(|frame| vec![frame])
+            } else if settings.stitch {
+                capture_stitched_blocking(&streams, &settings, &timeouts, &failures).map(|frame| vec![frame])
+            } else {
+                capture_all_monitors_blocking(&streams, &settings, &timeouts, &failures)
+            }
         })
         .await
         .context("Capture task panicked")?
         .context("Capture failed")?;
 
         let capture_duration_ms = start.elapsed().as_millis() as u64;
+        let audio_active = self.audio_active();
 
         Ok(results
             .into_iter()
-            .map(|(data, width, height, monitor_id)| CapturedFrame {
-                data,
-                width,
-                height,
-                timestamp,
-                monitor_id,
-                capture_duration_ms,
+            .map(|frame| {
+                let mut frame = self.with_metadata(frame.into_captured(timestamp, capture_duration_ms));
+                frame.audio_active = audio_active;
+                frame
             })
             .collect())
     }
 
+    /// Current audio output state, if recording it is enabled.
+    fn audio_active(&self) -> Option<bool> {
+        if !self.audio_activity || self.settings.mock.is_some() {
+            return None;
+        }
+        audio::output_active()
+    }
+
+    /// Embed the frame's capture metadata if enabled (JPEG only).
+    fn with_metadata(&self, mut frame: CapturedFrame) -> CapturedFrame {
+        if self.embed_metadata && frame.format == OutputFormat::Jpeg {
+            let meta = FrameMetadata {
+                timestamp: frame.timestamp,
+                monitor_id: frame.monitor_id,
+                device_id: self.device_id.as_deref(),
+                width: frame.width,
+                height: frame.height,
+            };
+            frame.data = exif::embed(std::mem::take(&mut frame.data), &meta);
+        }
+        frame
+    }
+
+    /// The mock display frames are synthesized for, if mocking.
+    pub fn mock(&self) -> Option<&MockDisplay> {
+        self.settings.mock.as_ref()
+    }
+
     /// Returns true if configured to capture all monitors.
     pub fn captures_all_monitors(&self) -> bool {
-        self.monitor_id < 0
+        self.settings.monitor_id < 0 && self.settings.follow_cursor.is_none()
+    }
+
+    /// Returns false if no display is attached to capture from. Mock capture
+    /// always has one, and a failed query assumes there is one.
+    pub fn has_display(&self) -> bool {
+        self.settings.mock.is_some() || displays::active_display_count().is_none_or(|n| n > 0)
+    }
+
+    /// Abandon the current capture streams without waiting on them, so a
+    /// stream wedged inside a blocking capture can't stall later captures.
+    /// Fresh streams are started on the next capture.
+    pub fn restart(&mut self) {
+        self.streams = Arc::new(Mutex::new(Vec::new()));
+    }
+
+    /// Stop all running capture streams so they are re-created against the
+    /// current display layout on the next capture.
+    pub fn reset_streams(&self) {
+        if let Ok(mut streams) = self.streams.lock() {
+            streams.clear();
+        }
+    }
+
+    /// Stop all running capture streams.
+    pub fn shutdown(&self) {
+        if let Ok(mut streams) = self.streams.lock() {
+            streams.clear();
+        }
     }
 }
 
-/// Frame handler that stores captured frame data
-struct FrameHandler {
-    frame_data: Arc<Mutex<Option<Vec<u8>>>>,
-    captured: Arc<AtomicBool>,
-    quality: u8,
+/// Render and encode a synthetic frame at the size a real capture would have.
+fn capture_mock_blocking(
+    mock: &MockDisplay,
+    settings: &CaptureSettings,
+    counter: u64,
+) -> Result<EncodedFrame> {
+    // A mock display has no cursor, so a followed region sits at its center
+    let cursor_region = settings.follow_cursor.map(|follow| {
+        let center = (mock.width as f64 / 2.0, mock.height as f64 / 2.0);
+        cursor::centered_region(center, follow.width, follow.height, (mock.width, mock.height))
+    });
+    let (source_width, source_height) = match cursor_region.or(settings.region) {
+        Some(r) => (r.width, r.height),
+        None => (mock.width, mock.height),
+    };
+    let (width, height) = output_size(source_width, source_height, settings, settings.max_dimension);
+    check_frame_size(width as usize, height as usize, settings)?;
+
+    let raw = synthetic_frame(width, height, counter);
+    let image = encode_frame(&raw, settings.encode)?;
+
+    Ok(EncodedFrame {
+        image,
+        width: raw.width as u32,
+        height: raw.height as u32,
+        monitor_id: mock.monitor_id(),
+        region: cursor_region,
+        scale_factor: None,
+        color_space: None,
+    })
 }
 
-impl SCStreamOutputTrait for FrameHandler {
-    fn did_output_sample_buffer(&self, sample: CMSampleBuffer, output_type: SCStreamOutputType) {
-        if output_type != SCStreamOutputType::Screen {
-            return;
+/// Blocking capture implementation for a single monitor
+fn capture_frame_blocking(
+    streams: &Mutex<Vec<DisplayStream>>,
+    settings: &CaptureSettings,
+    timeouts: &AtomicU64,
+) -> Result<EncodedFrame> {
+    let mut streams = streams.lock().map_err(|_| anyhow::anyhow!("Lock poisoned"))?;
+
+    // Start the stream on first use
+    if streams.is_empty() {
+        let content = SCShareableContent::get()
+            .map_err(|e| anyhow::anyhow!("Failed to get shareable content: {:?}", e))?;
+
+        let displays = content.displays();
+        if displays.is_empty() {
+            anyhow::bail!("No displays available for capture");
         }
 
-        // Only capture one frame
-        if self.captured.swap(true, Ordering::SeqCst) {
-            return;
+        // Find the requested monitor (use first if monitor_id < 0 or not found)
+        let display = if settings.monitor_id >= 0 {
+            displays
+                .iter()
+                .find(|d| d.display_id() == settings.monitor_id as u32)
+                .or_else(|| displays.first())
+        } else {
+            displays.first()
         }
+        .ok_or_else(|| anyhow::anyhow!("No monitor found"))?;
 
-        // Try to extract pixel buffer and encode to JPEG
-        if let Some(pixel_buffer) = sample.image_buffer() {
-            if let Some(jpeg_data) = encode_pixel_buffer_to_jpeg(&pixel_buffer, self.quality) {
-                if let Ok(mut guard) = self.frame_data.lock() {
-                    *guard = Some(jpeg_data);
-                }
+        let geometry = stream_geometry(display, settings, settings.region)?;
+        streams.push(DisplayStream::start(display, geometry, settings.frame_interval, settings.max_frame_bytes)?);
+    }
+
+    capture_from_stream(&streams[0].reader(), settings, timeouts)
+}
+
+/// Blocking capture of a fixed-size region centered on the cursor.
+///
+/// Streams cover whole displays and the region is cropped in software, since
+/// moving a stream's source rect every interval would mean restarting it.
+fn capture_cursor_blocking(
+    streams: &Mutex<Vec<DisplayStream>>,
+    settings: &CaptureSettings,
+    timeouts: &AtomicU64,
+    follow: CursorFollow,
+) -> Result<EncodedFrame> {
+    let mut streams = streams.lock().map_err(|_| anyhow::anyhow!("Lock poisoned"))?;
+    start_all_streams(&mut streams, settings)?;
+
+    // Capture whichever display the cursor is on, centering on it if unknown
+    let cursor = cursor::position();
+    let stream = cursor
+        .and_then(|point| streams.iter().find(|s| s.contains(point)))
+        .or_else(|| streams.first())
+        .ok_or_else(|| anyhow::anyhow!("No displays available for capture"))?;
+    let (origin_x, origin_y) = stream.origin();
+    let (display_width, display_height) = stream.size();
+    let center = match cursor {
+        Some((x, y)) => (x - origin_x, y - origin_y),
+        None => (display_width / 2.0, display_height / 2.0),
+    };
+    let region = cursor::centered_region(
+        center,
+        follow.width,
+        follow.height,
+        (display_width as u32, display_height as u32),
+    );
+
+    // The region is in points; the stream delivers scaled pixels
+    let raw = latest_raw_frame(&stream.reader(), settings, timeouts)?;
+    let scale = raw.width as f64 / display_width;
+    let px = |points: u32| ((points as f64 * scale).round() as usize).max(1);
+    let raw = raw.crop(px(region.x), px(region.y), px(region.width), px(region.height));
+    let image = encode_frame(&raw, settings.encode)?;
+
+    Ok(EncodedFrame {
+        image,
+        width: raw.width as u32,
+        height: raw.height as u32,
+        monitor_id: stream.display_id(),
+        region: Some(region),
+        scale_factor: Some(stream.scale_factor()),
+        color_space: color_conversion(settings, Some(stream.display_id())),
+    })
+}
+
+/// Start a stream per display if none are running.
+fn start_all_streams(streams: &mut Vec<DisplayStream>, settings: &CaptureSettings) -> Result<()> {
+    if streams.is_empty() {
+        let content = SCShareableContent::get()
+            .map_err(|e| anyhow::anyhow!("Failed to get shareable content: {:?}", e))?;
+
+        let displays = content.displays();
+        if displays.is_empty() {
+            anyhow::bail!("No displays available for capture");
+        }
+
+        for display in displays.iter() {
+            let display_id = display.display_id();
+            let started = stream_geometry(display, settings, None)
+                .and_then(|geometry| {
+                    DisplayStream::start(display, geometry, settings.frame_interval, settings.max_frame_bytes)
+                });
+            match started {
+                Ok(stream) => streams.push(stream),
+                Err(e) => warn!("Failed to start capture for display {}: {}", display_id, e),
             }
         }
     }
+    Ok(())
 }
 
-/// Blocking capture implementation for a single monitor
-fn capture_frame_blocking(monitor_id: i32, quality: u8, resolution_scale: f32) -> Result<(Vec<u8>, u32, u32, u32)> {
-    // Get shareable content
-    let content = SCShareableContent::get()
-        .map_err(|e| anyhow::anyhow!("Failed to get shareable content: {:?}", e))?;
+/// Blocking capture implementation for all monitors
+fn capture_all_monitors_blocking(
+    streams: &Mutex<Vec<DisplayStream>>,
+    settings: &CaptureSettings,
+    timeouts: &AtomicU64,
+    failures: &Mutex<Vec<MonitorFailure>>,
+) -> Result<Vec<EncodedFrame>> {
+    let readers: Vec<FrameReader> = {
+        let mut streams = streams.lock().map_err(|_| anyhow::anyhow!("Lock poisoned"))?;
+        start_all_streams(&mut streams, settings)?;
+        streams.iter().map(DisplayStream::reader).collect()
+    };
+
+    let results: Vec<EncodedFrame> = capture_concurrently(&readers, settings, failures, |reader| {
+        capture_from_stream(reader, settings, timeouts)
+    })?
+    .into_iter()
+    .flatten()
+    .collect();
 
-    let displays = content.displays();
-    if displays.is_empty() {
-        anyhow::bail!("No displays available for capture");
+    if results.is_empty() {
+        anyhow::bail!("Failed to capture any display");
     }
 
-    // Find the requested monitor (use first if monitor_id < 0 or not found)
-    let display = if monitor_id >= 0 {
-        displays
-            .iter()
-            .find(|d| d.display_id() == monitor_id as u32)
-            .or_else(|| displays.first())
+    Ok(results)
+}
+
+/// Blocking capture of all monitors composited into one frame.
+///
+/// Displays are placed according to their position in the virtual desktop,
+/// so the canvas is the bounding box of all displays; gaps (e.g. monitors of
+/// differing heights) are filled with black.
+fn capture_stitched_blocking(
+    streams: &Mutex<Vec<DisplayStream>>,
+    settings: &CaptureSettings,
+    timeouts: &AtomicU64,
+    failures: &Mutex<Vec<MonitorFailure>>,
+) -> Result<EncodedFrame> {
+    let displays: Vec<(FrameReader, (f64, f64))> = {
+        let mut streams = streams.lock().map_err(|_| anyhow::anyhow!("Lock poisoned"))?;
+        start_all_streams(&mut streams, settings)?;
+        streams.iter().map(|s| (s.reader(), s.origin())).collect()
+    };
+
+    let canvas: DynamicImage = if settings.encode.needs_alpha() {
+        stitch_displays(&displays, settings, timeouts, failures, to_rgba, STITCH_BACKGROUND)?.into()
     } else {
-        displays.first()
-    }
-    .ok_or_else(|| anyhow::anyhow!("No monitor found"))?;
+        stitch_displays(&displays, settings, timeouts, failures, to_rgb, STITCH_BACKGROUND_RGB)?.into()
+    };
+    // Streams aren't capped when stitching, so the cap applies to the composite
+    let canvas = match settings.max_dimension {
+        Some(max) if canvas.width().max(canvas.height()) > max => {
+            let (width, height) = fit_within(canvas.width(), canvas.height(), max);
+            canvas.resize_exact(width, height, imageops::FilterType::Triangle)
+        }
+        _ => canvas,
+    };
+    let image = encode_image(&canvas, settings.encode)?;
 
-    capture_single_display(display, quality, resolution_scale)
+    Ok(EncodedFrame {
+        image,
+        width: canvas.width(),
+        height: canvas.height(),
+        monitor_id: STITCHED_MONITOR_ID,
+        region: None,
+        scale_factor: None,
+        color_space: color_conversion(settings, None),
+    })
 }
 
-/// Blocking capture implementation for all monitors
-fn capture_all_monitors_blocking(quality: u8, resolution_scale: f32) -> Result<Vec<(Vec<u8>, u32, u32, u32)>> {
-    let content = SCShareableContent::get()
-        .map_err(|e| anyhow::anyhow!("Failed to get shareable content: {:?}", e))?;
+/// Capture each display and composite them with pixel type `P`, placing
+/// each at its desktop origin.
+fn stitch_displays<P: Pixel<Subpixel = u8> + Send>(
+    displays: &[(FrameReader, (f64, f64))],
+    settings: &CaptureSettings,
+    timeouts: &AtomicU64,
+    failures: &Mutex<Vec<MonitorFailure>>,
+    convert: fn(&RawFrame) -> Result<ImageBuffer<P, Vec<u8>>>,
+    background: P,
+) -> Result<ImageBuffer<P, Vec<u8>>> {
+    let readers: Vec<FrameReader> = displays.iter().map(|(reader, _)| reader.clone()).collect();
+    let images = capture_concurrently(&readers, settings, failures, |reader| {
+        latest_raw_frame(reader, settings, timeouts).and_then(|raw| convert(&raw))
+    })?;
+    let tiles: Vec<_> = displays
+        .iter()
+        .zip(images)
+        .filter_map(|((_, origin), img)| Some((*origin, img?)))
+        .collect();
 
-    let displays = content.displays();
-    if displays.is_empty() {
-        anyhow::bail!("No displays available for capture");
+    if tiles.is_empty() {
+        anyhow::bail!("Failed to capture any display");
     }
 
-    let mut results = Vec::with_capacity(displays.len());
-    for display in displays.iter() {
-        let display_id = display.display_id();
-        match capture_single_display(display, quality, resolution_scale) {
-            Ok(result) => results.push(result),
-            Err(e) => tracing::warn!("Failed to capture display {}: {}", display_id, e),
-        }
+    // Desktop coordinates are in points; scale them to match the stream output
+    let min_x = tiles.iter().map(|((x, _), _)| *x).fold(f64::INFINITY, f64::min);
+    let min_y = tiles.iter().map(|((_, y), _)| *y).fold(f64::INFINITY, f64::min);
+    let scale = settings.resolution_scale as f64;
+    let placed: Vec<_> = tiles
+        .into_iter()
+        .map(|((x, y), img)| {
+            let offset_x = ((x - min_x) * scale).round() as i64;
+            let offset_y = ((y - min_y) * scale).round() as i64;
+            (offset_x, offset_y, img)
+        })
+        .collect();
+
+    let width = placed
+        .iter()
+        .map(|(x, _, img)| x + img.width() as i64)
+        .max()
+        .unwrap_or(0) as u32;
+    let height = placed
+        .iter()
+        .map(|(_, y, img)| y + img.height() as i64)
+        .max()
+        .unwrap_or(0) as u32;
+
+    check_frame_size(width as usize, height as usize, settings)?;
+    let mut canvas = ImageBuffer::from_pixel(width, height, background);
+    for (x, y, img) in &placed {
+        imageops::overlay(&mut canvas, img, *x, *y);
     }
+    Ok(canvas)
+}
 
-    if results.is_empty() {
-        anyhow::bail!("Failed to capture any display");
+/// Capture every display at once, one thread each, so a tick takes about as
+/// long as the slowest display rather than the sum of all of them. Results
+/// are in display order, with None for displays left out under
+/// `on_monitor_error`. This already runs on a `spawn_blocking` thread, so
+/// the displays get scoped threads that borrow the tick's state instead of
+/// further blocking tasks.
+fn capture_concurrently<T: Send>(
+    readers: &[FrameReader],
+    settings: &CaptureSettings,
+    failures: &Mutex<Vec<MonitorFailure>>,
+    capture: impl Fn(&FrameReader) -> Result<T> + Sync,
+) -> Result<Vec<Option<T>>> {
+    let capture = &capture;
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = readers
+            .iter()
+            .map(|reader| {
+                let handle = std::thread::Builder::new()
+                    .name(format!("capture-{}", reader.display_id()))
+                    .spawn_scoped(scope, move || {
+                        capture_monitor(reader.display_id(), settings, failures, || capture(reader))
+                    });
+                (reader.display_id(), handle)
+            })
+            .collect();
+
+        // Join every thread before reporting, so none outlives an aborted tick
+        let results: Vec<_> = handles
+            .into_iter()
+            .map(|(display_id, handle)| match handle {
+                Ok(handle) => handle
+                    .join()
+                    .unwrap_or_else(|_| Err(anyhow::anyhow!("Capture of display {} panicked", display_id))),
+                Err(e) => Err(anyhow::anyhow!("Failed to start capture of display {}: {}", display_id, e)),
+            })
+            .collect();
+        results.into_iter().collect()
+    })
+}
+
+/// Capture one display of an all-monitors tick under `on_monitor_error`.
+/// Returns None to leave the display out of this tick, or an error to fail
+/// the whole tick. Each failure is recorded for the event log.
+fn capture_monitor<T>(
+    display_id: u32,
+    settings: &CaptureSettings,
+    failures: &Mutex<Vec<MonitorFailure>>,
+    capture: impl Fn() -> Result<T>,
+) -> Result<Option<T>> {
+    let mut result = capture();
+    // An oversized frame would be just as large on a second try
+    let retried = result.as_ref().is_err_and(|e| !FrameTooLarge::is_cause_of(e))
+        && settings.on_monitor_error == MonitorErrorPolicy::RetryOnce;
+    if retried {
+        result = capture();
     }
 
-    Ok(results)
+    let e = match result {
+        Ok(value) => return Ok(Some(value)),
+        Err(e) => e,
+    };
+    warn!(
+        "Failed to capture display {}{}: {:#}",
+        display_id,
+        if retried { " (after retry)" } else { "" },
+        e
+    );
+    if let Ok(mut failures) = failures.lock() {
+        failures.push(MonitorFailure {
+            monitor_id: display_id,
+            error: format!("{:#}", e),
+            retried,
+        });
+    }
+    if settings.on_monitor_error == MonitorErrorPolicy::AbortTick {
+        return Err(e.context(format!("Display {} failed, discarding this tick", display_id)));
+    }
+    Ok(None)
 }
 
-/// Capture a single display
-fn capture_single_display(
+/// Compute the stream output size and source area for a display.
+fn stream_geometry(
     display: &SCDisplay,
-    quality: u8,
-    resolution_scale: f32,
-) -> Result<(Vec<u8>, u32, u32, u32)> {
+    settings: &CaptureSettings,
+    region: Option<CaptureRegion>,
+) -> Result<StreamGeometry> {
     let display_id = display.display_id();
     let native_width = display.width() as u32;
     let native_height = display.height() as u32;
 
-    // Apply resolution scaling
-    let scaled_width = ((native_width as f32) * resolution_scale).round() as u32;
-    let scaled_height = ((native_height as f32) * resolution_scale).round() as u32;
-
-    // Create content filter and configuration
-    let filter = SCContentFilter::create()
-        .with_display(display)
-        .with_excluding_windows(&[])
-        .build();
-
-    let config = SCStreamConfiguration::new()
-        .with_width(scaled_width)
-        .with_height(scaled_height)
-        .with_pixel_format(PixelFormat::BGRA);
-
-    // Create shared state for frame capture
-    let frame_data: Arc<Mutex<Option<Vec<u8>>>> = Arc::new(Mutex::new(None));
-    let captured = Arc::new(AtomicBool::new(false));
-
-    let handler = FrameHandler {
-        frame_data: frame_data.clone(),
-        captured: captured.clone(),
-        quality,
+    // Restrict to the configured region, which must lie within the display
+    let (source_width, source_height) = match region {
+        Some(r) => {
+            if r.x.saturating_add(r.width) > native_width
+                || r.y.saturating_add(r.height) > native_height
+            {
+                anyhow::bail!(
+                    "Capture region {}x{} at ({}, {}) exceeds monitor {} bounds {}x{}",
+                    r.width, r.height, r.x, r.y, display_id, native_width, native_height
+                );
+            }
+            (r.width, r.height)
+        }
+        None => (native_width, native_height),
     };
 
-    // Create and start stream
-    let mut stream = SCStream::new(&filter, &config);
-    stream.add_output_handler(handler, SCStreamOutputType::Screen);
-
-    stream
-        .start_capture()
-        .map_err(|e| anyhow::anyhow!("Failed to start capture: {:?}", e))?;
+    // Displays report their size in points, so capture at the display's own
+    // pixel density to keep Retina frames sharp. Stitched displays stay in
+    // points so screens of mixed density line up on one canvas.
+    let scale_factor = if settings.stitch {
+        1.0
+    } else {
+        displays::backing_scale(display_id).unwrap_or(1.0)
+    };
+    let source_width = ((source_width as f64 * scale_factor).round() as u32).max(1);
+    let source_height = ((source_height as f64 * scale_factor).round() as u32).max(1);
 
-    // Wait for frame with polling
-    let timeout = std::time::Duration::from_secs(5);
-    let start = std::time::Instant::now();
+    // ScreenCaptureKit scales to the stream size on the GPU, so frames arrive
+    // already at the output resolution and are never downscaled in software.
+    // Stitched displays are capped as a whole after compositing instead.
+    let max_dimension = if settings.stitch { None } else { settings.max_dimension };
+    let (width, height) = output_size(source_width, source_height, settings, max_dimension);
+    check_frame_size(width as usize, height as usize, settings)?;
+    Ok(StreamGeometry { width, height, region, scale_factor })
+}
 
-    while start.elapsed() < timeout {
-        if captured.load(Ordering::SeqCst) {
-            break;
-        }
-        std::thread::sleep(std::time::Duration::from_millis(50));
+/// Scale a source size by `resolution_scale`, then shrink it to fit `max_dimension`.
+fn output_size(
+    source_width: u32,
+    source_height: u32,
+    settings: &CaptureSettings,
+    max_dimension: Option<u32>,
+) -> (u32, u32) {
+    let width = (((source_width as f32) * settings.resolution_scale).round() as u32).max(1);
+    let height = (((source_height as f32) * settings.resolution_scale).round() as u32).max(1);
+    match max_dimension {
+        Some(max) if width.max(height) > max => fit_within(width, height, max),
+        _ => (width, height),
     }
+}
+
+/// Shrink a size so its longest side is `max`, preserving aspect ratio.
+fn fit_within(width: u32, height: u32, max: u32) -> (u32, u32) {
+    let ratio = max as f64 / width.max(height) as f64;
+    (
+        ((width as f64 * ratio).round() as u32).clamp(1, max),
+        ((height as f64 * ratio).round() as u32).clamp(1, max),
+    )
+}
 
-    // Stop capture
-    let _ = stream.stop_capture();
+/// Grab and encode the latest frame from a running stream.
+fn capture_from_stream(
+    stream: &FrameReader,
+    settings: &CaptureSettings,
+    timeouts: &AtomicU64,
+) -> Result<EncodedFrame> {
+    let raw = latest_raw_frame(stream, settings, timeouts)?;
+    let image = encode_frame(&raw, settings.encode)?;
 
-    // Get the captured frame
-    let data = frame_data
-        .lock()
-        .map_err(|_| anyhow::anyhow!("Lock poisoned"))?
-        .take()
-        .ok_or_else(|| anyhow::anyhow!("No frame captured - check Screen Recording permission"))?;
+    Ok(EncodedFrame {
+        image,
+        width: raw.width as u32,
+        height: raw.height as u32,
+        monitor_id: stream.display_id(),
+        region: None,
+        scale_factor: Some(stream.scale_factor()),
+        color_space: color_conversion(settings, Some(stream.display_id())),
+    })
+}
 
-    Ok((data, scaled_width, scaled_height, display_id))
+/// A frame skipped because its pixels would exceed `max_frame_bytes`.
+///
+/// This isn't a capture failure: the display works, and backing off or
+/// restarting streams wouldn't make its frames any smaller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameTooLarge {
+    pub width: usize,
+    pub height: usize,
+    pub max_bytes: u64,
 }
 
-/// Encode a pixel buffer to JPEG format.
-fn encode_pixel_buffer_to_jpeg(
-    pixel_buffer: &screencapturekit::cv::CVPixelBuffer,
-    quality: u8,
-) -> Option<Vec<u8>> {
-    // Lock the pixel buffer for reading
-    let guard = pixel_buffer.lock(CVPixelBufferLockFlags::READ_ONLY).ok()?;
-
-    let width = guard.width();
-    let height = guard.height();
-    let bytes_per_row = guard.bytes_per_row();
-
-    // Get the raw pixel data
-    let pixel_data = guard.as_slice();
-    if pixel_data.is_empty() {
-        return None;
-    }
-
-    // Convert BGRA to RGBA
-    let mut rgba_data = Vec::with_capacity(width * height * 4);
-    for y in 0..height {
-        let row_start = y * bytes_per_row;
-        for x in 0..width {
-            let pixel_start = row_start + x * 4;
-            if pixel_start + 3 < pixel_data.len() {
-                // BGRA -> RGBA
-                rgba_data.push(pixel_data[pixel_start + 2]); // R
-                rgba_data.push(pixel_data[pixel_start + 1]); // G
-                rgba_data.push(pixel_data[pixel_start]); // B
-                rgba_data.push(pixel_data[pixel_start + 3]); // A
-            }
+impl FrameTooLarge {
+    /// Refuse a `width`×`height` frame whose RGBA pixels would exceed
+    /// `max_bytes`, before any buffer of that size is allocated.
+    pub fn check(width: usize, height: usize, max_bytes: Option<u64>) -> std::result::Result<(), Self> {
+        match max_bytes {
+            Some(max_bytes) if pixel_bytes(width, height) > max_bytes => Err(Self { width, height, max_bytes }),
+            _ => Ok(()),
         }
     }
 
-    // Guard is dropped here, unlocking the buffer
+    /// Returns true if `error` was caused by an oversized frame.
+    pub fn is_cause_of(error: &anyhow::Error) -> bool {
+        error.chain().any(|cause| cause.is::<Self>())
+    }
+}
 
-    // Create image buffer
-    let img: ImageBuffer<Rgba<u8>, Vec<u8>> =
-        ImageBuffer::from_raw(width as u32, height as u32, rgba_data)?;
+impl std::fmt::Display for FrameTooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Skipping {}x{} frame: {} bytes of pixels exceeds max_frame_bytes ({})",
+            self.width,
+            self.height,
+            pixel_bytes(self.width, self.height),
+            self.max_bytes
+        )
+    }
+}
 
-    // Encode to JPEG
-    let mut jpeg_buffer = Cursor::new(Vec::new());
-    let mut encoder = JpegEncoder::new_with_quality(&mut jpeg_buffer, quality);
+impl std::error::Error for FrameTooLarge {}
 
-    if encoder.encode_image(&img).is_err() {
-        return None;
-    }
+/// Size of a `width`×`height` frame's RGBA pixels.
+fn pixel_bytes(width: usize, height: usize) -> u64 {
+    (width as u64).saturating_mul(height as u64).saturating_mul(4)
+}
 
-    Some(jpeg_buffer.into_inner())
+/// Refuse a frame under the configured `max_frame_bytes`, so a bogus display
+/// geometry skips the frame instead of exhausting memory.
+fn check_frame_size(width: usize, height: usize, settings: &CaptureSettings) -> Result<()> {
+    Ok(FrameTooLarge::check(width, height, settings.max_frame_bytes)?)
+}
+
+/// How frames from `display_id` are color matched under `settings`, if at
+/// all. Stitched frames have no single source display.
+fn color_conversion(settings: &CaptureSettings, display_id: Option<u32>) -> Option<ColorConversion> {
+    (settings.color_space == ColorSpace::Srgb).then(|| ColorConversion {
+        source: display_id.and_then(color::display_color_space),
+        target: color::SRGB.to_string(),
+    })
+}
+
+/// Grab the latest raw frame from a running stream, counting timeouts and
+/// converting it to the configured color space.
+fn latest_raw_frame(
+    stream: &FrameReader,
+    settings: &CaptureSettings,
+    timeouts: &AtomicU64,
+) -> Result<Arc<RawFrame>> {
+    match stream.latest_frame(settings.timeout) {
+        Ok(raw) if settings.color_space == ColorSpace::Srgb => {
+            Ok(Arc::new(color::to_srgb(&raw, stream.display_id())?))
+        }
+        Ok(raw) => Ok(raw),
+        Err(FrameUnavailable::TooLarge { width, height }) => Err(FrameTooLarge {
+            width,
+            height,
+            max_bytes: settings.max_frame_bytes.unwrap_or_default(),
+        }
+        .into()),
+        Err(unavailable) => {
+            timeouts.fetch_add(1, Ordering::Relaxed);
+            let reason = if !has_screen_capture_access() {
+                format!("Screen Recording permission not granted ({})", permission::SETTINGS_PATH)
+            } else {
+                match unavailable {
+                    FrameUnavailable::Unreadable => "frames arrived but could not be read",
+                    FrameUnavailable::Stalled => "stream stopped delivering frames",
+                    FrameUnavailable::NoSamples | FrameUnavailable::TooLarge { .. } => "capture stalled",
+                }
+                .to_string()
+            };
+            anyhow::bail!(
+                "No frame captured from display {} within {}ms: {}",
+                stream.display_id(),
+                settings.timeout.as_millis(),
+                reason
+            );
+        }
+    }
 }
 
 impl CapturedFrame {
@@ -346,13 +1041,49 @@ impl CapturedFrame {
         self.timestamp.format("%Y%m%d-%H%M%S%3f").to_string()
     }
 
+    /// Hex SHA-256 of the encoded frame bytes.
+    pub fn content_hash(&self) -> String {
+        format!("{:x}", Sha256::digest(&self.data))
+    }
+
     /// Generate S3 key path for this frame.
-    pub fn s3_key(&self, prefix: Option<&str>) -> String {
-        let date_path = self.timestamp.format("%Y/%m/%d/%H").to_string();
-        let filename = format!("frame-{}.jpg", self.timestamp.timestamp_millis());
-        match prefix {
-            Some(p) if !p.is_empty() => format!("{}/{}/{}", p.trim_end_matches('/'), date_path, filename),
-            _ => format!("{}/{}", date_path, filename),
+    pub fn s3_key(&self, layout: &KeyLayout) -> String {
+        if layout.is_content_addressed() {
+            return layout.content(&self.content_hash(), self.format.extension(), None);
+        }
+        layout.render(self, self.format.extension(), None)
+    }
+
+    /// Generate the S3 key for this frame's thumbnail, under a parallel `thumbs/` tree.
+    pub fn thumbnail_key(&self, layout: &KeyLayout) -> String {
+        // Named after the full frame, since identical frames have identical thumbnails
+        if layout.is_content_addressed() {
+            return layout.content(&self.content_hash(), OutputFormat::Jpeg.extension(), Some("thumbs"));
+        }
+        layout.render(self, OutputFormat::Jpeg.extension(), Some("thumbs"))
+    }
+
+    /// A JPEG frame of monitor 0 holding `data`, captured now.
+    #[cfg(test)]
+    pub fn for_test(data: Vec<u8>) -> Self {
+        Self {
+            data,
+            format: OutputFormat::Jpeg,
+            width: 2,
+            height: 2,
+            timestamp: Utc::now(),
+            monitor_id: 0,
+            capture_duration_ms: 0,
+            fingerprint: Vec::new(),
+            thumbnail: None,
+            manual: false,
+            reason: None,
+            audio_active: None,
+            input_events: None,
+            region: None,
+            unoptimized_size: None,
+            scale_factor: None,
+            color_space: None,
         }
     }
 }
@@ -0,0 +1,100 @@
+//! The interface the capture loop uses to produce frames.
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use super::screen::{CapturedFrame, MonitorFailure, MonitorInfo, ScreenCapture};
+
+/// Something that produces captured frames, so the capture loop can run
+/// against a scripted source instead of a real display.
+#[async_trait]
+pub trait FrameSource: Send + Sync {
+    /// Capture a single frame from the configured monitor.
+    async fn capture(&self) -> Result<CapturedFrame>;
+
+    /// Capture every monitor (or one stitched frame of all of them).
+    async fn capture_all(&self) -> Result<Vec<CapturedFrame>>;
+
+    /// Returns true if `capture_all` should be used instead of `capture`.
+    fn captures_all_monitors(&self) -> bool;
+
+    /// List the monitors currently available.
+    fn list_monitors(&self) -> Result<Vec<MonitorInfo>>;
+
+    /// Returns false if no display is attached to capture from.
+    fn has_display(&self) -> bool {
+        true
+    }
+
+    /// Change the encoding quality for subsequent captures.
+    fn set_quality(&mut self, _quality: u8) {}
+
+    /// Number of captures that timed out waiting for a frame.
+    fn timeout_count(&self) -> u64 {
+        0
+    }
+
+    /// Monitors that failed during `capture_all` calls since the last call.
+    fn take_monitor_failures(&self) -> Vec<MonitorFailure> {
+        Vec::new()
+    }
+
+    /// Abandon any wedged capture state; called after stalls.
+    fn restart(&mut self) {}
+
+    /// Pick up a changed display layout on the next capture.
+    fn reset_streams(&self) {}
+
+    /// Release capture resources before exit.
+    fn shutdown(&self) {}
+}
+
+#[async_trait]
+impl FrameSource for ScreenCapture {
+    async fn capture(&self) -> Result<CapturedFrame> {
+        ScreenCapture::capture(self).await
+    }
+
+    async fn capture_all(&self) -> Result<Vec<CapturedFrame>> {
+        ScreenCapture::capture_all(self).await
+    }
+
+    fn captures_all_monitors(&self) -> bool {
+        ScreenCapture::captures_all_monitors(self)
+    }
+
+    fn list_monitors(&self) -> Result<Vec<MonitorInfo>> {
+        match self.mock() {
+            Some(mock) => Ok(mock.monitors()),
+            None => ScreenCapture::list_monitors(),
+        }
+    }
+
+    fn has_display(&self) -> bool {
+        ScreenCapture::has_display(self)
+    }
+
+    fn set_quality(&mut self, quality: u8) {
+        ScreenCapture::set_quality(self, quality)
+    }
+
+    fn timeout_count(&self) -> u64 {
+        ScreenCapture::timeout_count(self)
+    }
+
+    fn take_monitor_failures(&self) -> Vec<MonitorFailure> {
+        ScreenCapture::take_monitor_failures(self)
+    }
+
+    fn restart(&mut self) {
+        ScreenCapture::restart(self)
+    }
+
+    fn reset_streams(&self) {
+        ScreenCapture::reset_streams(self)
+    }
+
+    fn shutdown(&self) {
+        ScreenCapture::shutdown(self)
+    }
+}
@@ -0,0 +1,389 @@
+//! Long-lived ScreenCaptureKit streams that keep the latest frame per display.
+
+use anyhow::Result;
+use screencapturekit::cg::CGRect;
+use screencapturekit::cv::CVPixelBufferLockFlags;
+use screencapturekit::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+use super::encode::RawFrame;
+use super::screen::FrameTooLarge;
+use crate::config::CaptureRegion;
+
+/// How often to check whether the first frame has arrived.
+const FRAME_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Latest frame delivered by a stream.
+#[derive(Default)]
+struct FrameSlot {
+    latest: Mutex<Option<Arc<RawFrame>>>,
+    /// Set once a sample buffer arrived, even if it couldn't be read.
+    received: AtomicBool,
+    /// When the stream last delivered any sample, including the status-only
+    /// ones sent while the screen is unchanged.
+    last_sample: Mutex<Option<Instant>>,
+    /// Size of the last frame, if it was too large to copy.
+    oversized: Mutex<Option<(usize, usize)>>,
+}
+
+impl FrameSlot {
+    /// Note that the stream delivered a sample just now.
+    fn record_sample(&self) {
+        self.received.store(true, Ordering::SeqCst);
+        if let Ok(mut last) = self.last_sample.lock() {
+            *last = Some(Instant::now());
+        }
+    }
+
+    /// Returns true if a sample arrived within `window`.
+    fn sampled_within(&self, window: Duration) -> bool {
+        self.last_sample
+            .lock()
+            .ok()
+            .and_then(|last| *last)
+            .is_some_and(|at| at.elapsed() <= window)
+    }
+}
+
+/// Why a stream had no usable frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameUnavailable {
+    /// Nothing at all arrived from the stream.
+    NoSamples,
+    /// Samples arrived, but none could be read.
+    Unreadable,
+    /// The stream delivered frames, then went silent: the cached frame is stale.
+    Stalled,
+    /// Frames arrived, but exceeded `max_frame_bytes` and weren't copied.
+    TooLarge { width: usize, height: usize },
+}
+
+/// Frame handler that keeps a copy of the most recent frame.
+struct FrameHandler {
+    slot: Arc<FrameSlot>,
+    max_frame_bytes: Option<u64>,
+    /// Oversized frames arrive at the stream's frame rate, so warn once.
+    warned_oversized: AtomicBool,
+}
+
+impl SCStreamOutputTrait for FrameHandler {
+    fn did_output_sample_buffer(&self, sample: CMSampleBuffer, output_type: SCStreamOutputType) {
+        if output_type != SCStreamOutputType::Screen {
+            return;
+        }
+
+        self.slot.record_sample();
+
+        // Status-only samples (no screen change) carry no image buffer
+        let Some(pixel_buffer) = sample.image_buffer() else {
+            return;
+        };
+
+        match copy_pixel_buffer(&pixel_buffer, self.max_frame_bytes) {
+            Ok(Some(raw)) => {
+                if let Ok(mut oversized) = self.slot.oversized.lock() {
+                    *oversized = None;
+                }
+                if let Ok(mut guard) = self.slot.latest.lock() {
+                    *guard = Some(Arc::new(raw));
+                }
+            }
+            Ok(None) => {}
+            Err(too_large) => {
+                if !self.warned_oversized.swap(true, Ordering::Relaxed) {
+                    warn!("{}", too_large);
+                }
+                if let Ok(mut oversized) = self.slot.oversized.lock() {
+                    *oversized = Some((too_large.width, too_large.height));
+                }
+                if let Ok(mut guard) = self.slot.latest.lock() {
+                    *guard = None;
+                }
+            }
+        }
+    }
+}
+
+/// Output dimensions and source area for a display stream.
+#[derive(Debug, Clone, Copy)]
+pub struct StreamGeometry {
+    pub width: u32,
+    pub height: u32,
+    pub region: Option<CaptureRegion>,
+    /// Pixels per point the source area is captured at, before `resolution_scale`.
+    pub scale_factor: f64,
+}
+
+/// A running capture stream for one display.
+pub struct DisplayStream {
+    stream: SCStream,
+    slot: Arc<FrameSlot>,
+    display_id: u32,
+    origin: (f64, f64),
+    size: (f64, f64),
+    scale_factor: f64,
+}
+
+impl DisplayStream {
+    /// Start a stream for the display, delivering frames no faster than
+    /// `frame_interval` and dropping any larger than `max_frame_bytes`.
+    pub fn start(
+        display: &SCDisplay,
+        geometry: StreamGeometry,
+        frame_interval: Duration,
+        max_frame_bytes: Option<u64>,
+    ) -> Result<Self> {
+        let display_id = display.display_id();
+        let frame = display.frame();
+
+        let filter = SCContentFilter::create()
+            .with_display(display)
+            .with_excluding_windows(&[])
+            .build();
+
+        let frame_interval_ms = frame_interval.as_millis().max(1) as i64;
+        let mut config = SCStreamConfiguration::new()
+            .with_width(geometry.width)
+            .with_height(geometry.height)
+            .with_pixel_format(PixelFormat::BGRA)
+            .with_minimum_frame_interval(&CMTime::new(frame_interval_ms, 1000));
+
+        if let Some(r) = geometry.region {
+            config = config.with_source_rect(CGRect::new(
+                r.x as f64,
+                r.y as f64,
+                r.width as f64,
+                r.height as f64,
+            ));
+        }
+
+        let slot = Arc::new(FrameSlot::default());
+        let handler = FrameHandler {
+            slot: slot.clone(),
+            max_frame_bytes,
+            warned_oversized: AtomicBool::new(false),
+        };
+
+        let mut stream = SCStream::new(&filter, &config);
+        stream.add_output_handler(handler, SCStreamOutputType::Screen);
+
+        stream
+            .start_capture()
+            .map_err(|e| anyhow::anyhow!("Failed to start capture: {:?}", e))?;
+
+        debug!(
+            "Started capture stream for display {} at {}x{} (scale {})",
+            display_id, geometry.width, geometry.height, geometry.scale_factor
+        );
+
+        Ok(Self {
+            stream,
+            slot,
+            display_id,
+            origin: (frame.origin.x, frame.origin.y),
+            size: (frame.size.width, frame.size.height),
+            scale_factor: geometry.scale_factor,
+        })
+    }
+
+    /// Returns the display this stream captures.
+    pub fn display_id(&self) -> u32 {
+        self.display_id
+    }
+
+    /// Returns the display's top-left corner in global desktop coordinates (points).
+    pub fn origin(&self) -> (f64, f64) {
+        self.origin
+    }
+
+    /// Returns the display's size in points.
+    pub fn size(&self) -> (f64, f64) {
+        self.size
+    }
+
+    /// Returns the pixels per point this stream captures at.
+    pub fn scale_factor(&self) -> f64 {
+        self.scale_factor
+    }
+
+    /// Returns a handle for reading this stream's frames from other threads.
+    pub fn reader(&self) -> FrameReader {
+        FrameReader {
+            slot: self.slot.clone(),
+            display_id: self.display_id,
+            scale_factor: self.scale_factor,
+        }
+    }
+
+    /// Returns true if the global desktop point lies on this display.
+    pub fn contains(&self, (x, y): (f64, f64)) -> bool {
+        let (left, top) = self.origin;
+        x >= left && x < left + self.size.0 && y >= top && y < top + self.size.1
+    }
+}
+
+/// Thread-safe handle to a stream's latest frame, so several displays can be
+/// read and encoded at once without sharing the streams themselves.
+#[derive(Clone)]
+pub struct FrameReader {
+    slot: Arc<FrameSlot>,
+    display_id: u32,
+    scale_factor: f64,
+}
+
+impl FrameReader {
+    /// Returns the display this reader's stream captures.
+    pub fn display_id(&self) -> u32 {
+        self.display_id
+    }
+
+    /// Returns the pixels per point the stream captures at.
+    pub fn scale_factor(&self) -> f64 {
+        self.scale_factor
+    }
+
+    /// Get the latest frame, waiting up to `timeout` for one to arrive. A
+    /// cached frame only counts while the stream is still delivering samples;
+    /// once nothing has arrived for `timeout`, the stream is treated as wedged
+    /// rather than handing out the same stale pixels forever.
+    pub fn latest_frame(&self, timeout: Duration) -> std::result::Result<Arc<RawFrame>, FrameUnavailable> {
+        let start = Instant::now();
+        loop {
+            let frame = self.slot.latest.lock().ok().and_then(|g| g.clone());
+            if let Some(frame) = frame.filter(|_| self.slot.sampled_within(timeout)) {
+                return Ok(frame);
+            }
+            if start.elapsed() >= timeout {
+                let oversized = self.slot.oversized.lock().ok().and_then(|o| *o);
+                return Err(if let Some((width, height)) = oversized {
+                    FrameUnavailable::TooLarge { width, height }
+                } else if self.slot.latest.lock().is_ok_and(|g| g.is_some()) {
+                    FrameUnavailable::Stalled
+                } else if self.slot.received.load(Ordering::SeqCst) {
+                    FrameUnavailable::Unreadable
+                } else {
+                    FrameUnavailable::NoSamples
+                });
+            }
+            std::thread::sleep(FRAME_POLL_INTERVAL);
+        }
+    }
+}
+
+impl Drop for DisplayStream {
+    fn drop(&mut self) {
+        let _ = self.stream.stop_capture();
+        debug!("Stopped capture stream for display {}", self.display_id);
+    }
+}
+
+/// Copy the BGRA contents of a pixel buffer, unless it exceeds `max_bytes`.
+fn copy_pixel_buffer(
+    pixel_buffer: &screencapturekit::cv::CVPixelBuffer,
+    max_bytes: Option<u64>,
+) -> std::result::Result<Option<RawFrame>, FrameTooLarge> {
+    // Lock the pixel buffer for reading; the guard unlocks it on drop
+    let Ok(guard) = pixel_buffer.lock(CVPixelBufferLockFlags::READ_ONLY) else {
+        return Ok(None);
+    };
+    FrameTooLarge::check(guard.width(), guard.height(), max_bytes)?;
+
+    let data = guard.as_slice();
+    if data.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(RawFrame {
+        width: guard.width(),
+        height: guard.height(),
+        bytes_per_row: guard.bytes_per_row(),
+        data: data.to_vec(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_millis(20);
+
+    fn reader(slot: &Arc<FrameSlot>) -> FrameReader {
+        FrameReader {
+            slot: slot.clone(),
+            display_id: 1,
+            scale_factor: 1.0,
+        }
+    }
+
+    fn slot_with_frame(sampled_ago: Duration) -> Arc<FrameSlot> {
+        let slot = Arc::new(FrameSlot::default());
+        *slot.latest.lock().unwrap() = Some(Arc::new(RawFrame {
+            width: 1,
+            height: 1,
+            bytes_per_row: 4,
+            data: vec![0; 4],
+        }));
+        slot.received.store(true, Ordering::SeqCst);
+        *slot.last_sample.lock().unwrap() = Instant::now().checked_sub(sampled_ago);
+        slot
+    }
+
+    #[test]
+    fn returns_frame_while_samples_arrive() {
+        let slot = slot_with_frame(Duration::ZERO);
+        assert!(reader(&slot).latest_frame(TIMEOUT).is_ok());
+    }
+
+    #[test]
+    fn stale_frame_is_a_stall() {
+        let slot = slot_with_frame(Duration::from_secs(60));
+        assert_eq!(reader(&slot).latest_frame(TIMEOUT).err(), Some(FrameUnavailable::Stalled));
+    }
+
+    #[test]
+    fn status_only_sample_keeps_frame_fresh() {
+        let slot = slot_with_frame(Duration::from_secs(60));
+        // An unchanged screen delivers samples without an image buffer
+        slot.record_sample();
+        assert!(reader(&slot).latest_frame(TIMEOUT).is_ok());
+    }
+
+    #[test]
+    fn distinguishes_silent_and_unreadable_streams() {
+        let slot = Arc::new(FrameSlot::default());
+        assert_eq!(reader(&slot).latest_frame(TIMEOUT).err(), Some(FrameUnavailable::NoSamples));
+        slot.record_sample();
+        assert_eq!(reader(&slot).latest_frame(TIMEOUT).err(), Some(FrameUnavailable::Unreadable));
+    }
+
+    #[test]
+    fn reports_oversized_frames() {
+        let slot = Arc::new(FrameSlot::default());
+        slot.record_sample();
+        *slot.oversized.lock().unwrap() = Some((100_000, 100_000));
+        assert_eq!(
+            reader(&slot).latest_frame(TIMEOUT).err(),
+            Some(FrameUnavailable::TooLarge { width: 100_000, height: 100_000 })
+        );
+    }
+
+    #[test]
+    fn checks_frame_size_before_copying() {
+        assert!(FrameTooLarge::check(100, 100, Some(40_000)).is_ok());
+        assert!(FrameTooLarge::check(100, 100, None).is_ok());
+        let too_large = FrameTooLarge::check(100, 101, Some(40_000)).unwrap_err();
+        assert_eq!((too_large.width, too_large.height), (100, 101));
+        assert!(FrameTooLarge::check(usize::MAX, usize::MAX, Some(u64::MAX - 1)).is_err());
+    }
+
+    #[test]
+    fn oversized_frames_are_recognized_through_context() {
+        let error = anyhow::Error::from(FrameTooLarge::check(10, 10, Some(1)).unwrap_err())
+            .context("Display 1 failed, discarding this tick");
+        assert!(FrameTooLarge::is_cause_of(&error));
+        assert!(!FrameTooLarge::is_cause_of(&anyhow::anyhow!("capture stalled")));
+    }
+}
@@ -0,0 +1,338 @@
+//! Command line argument parsing.
+
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Default timelapse frame rate.
+const DEFAULT_TIMELAPSE_FPS: u16 = 10;
+
+/// Default maximum timelapse width in pixels.
+const DEFAULT_TIMELAPSE_WIDTH: u32 = 640;
+
+/// Default number of days in the usage report.
+const DEFAULT_USAGE_DAYS: u32 = 7;
+
+/// Usage text printed by `--help`.
+pub const USAGE: &str = "\
+Usage: preprompter [OPTIONS] [CONFIG]
+       preprompter [OPTIONS] ctl <pause [DURATION]|resume|capture|status|quit>
+       preprompter decrypt <KEY> <INPUT> [OUTPUT]
+       preprompter [OPTIONS] timelapse --since <DURATION> --out <FILE> [--fps N] [--width PX] [--monitor ID]
+       preprompter [OPTIONS] export --date <YYYY-MM-DD> --out <DIR>
+       preprompter [OPTIONS] usage [--days N]
+
+Arguments:
+  [CONFIG]             Path to the config file (same as --config)
+
+Commands:
+  ctl <COMMAND>        Send a command to the running daemon's control socket
+  decrypt              Decrypt a downloaded frame with a hex key (to OUTPUT or stdout)
+  timelapse            Build an animated GIF from frames uploaded within DURATION
+                       (e.g. 30m, 2h, 1d); defaults: --fps 10 --width 640
+  export               Download a day's frames (local date) into DIR, in capture
+                       order, with a manifest.json listing each frame
+  usage                Report frames and bytes uploaded per day over the last
+                       N days (default 7)
+
+Options:
+  -c, --config <PATH>  Path to the config file
+      --force          Start even if a PID file says another instance is running
+      --no-menu        Run headless without a menu bar item (e.g. under launchd or SSH)
+      --print-config[=json]
+                       Print the effective config (after env overrides) as TOML
+                       or JSON and exit; secrets are redacted
+  -h, --help           Print help
+  -V, --version        Print version
+";
+
+/// What the process should do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Run the capture daemon.
+    Run,
+    /// Print usage and exit.
+    Help,
+    /// Print the version and exit.
+    Version,
+    /// Send a command to a running daemon.
+    Ctl(String),
+    /// Decrypt an encrypted frame file.
+    Decrypt {
+        key: String,
+        input: PathBuf,
+        output: Option<PathBuf>,
+    },
+    /// Assemble a timelapse from recent frames.
+    Timelapse(TimelapseOptions),
+    /// Download a day's frames to a local directory.
+    Export(ExportOptions),
+    /// Report uploaded bytes per day.
+    Usage(UsageOptions),
+    /// Print the effective configuration and exit.
+    PrintConfig(ConfigFormat),
+}
+
+/// Output format for `--print-config`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Toml,
+    Json,
+}
+
+/// Options for the `timelapse` subcommand.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimelapseOptions {
+    /// How far back to include frames.
+    pub since: Duration,
+    /// Output GIF path.
+    pub out: PathBuf,
+    /// Playback frames per second.
+    pub fps: u16,
+    /// Maximum output width in pixels.
+    pub width: u32,
+    /// Monitor to include (None = monitor of the earliest frame).
+    pub monitor_id: Option<u32>,
+}
+
+/// Options for the `export` subcommand.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportOptions {
+    /// Local calendar day to export.
+    pub date: NaiveDate,
+    /// Output directory.
+    pub out: PathBuf,
+}
+
+/// Options for the `usage` subcommand.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsageOptions {
+    /// Number of days to report, ending today.
+    pub days: u32,
+}
+
+/// Parsed command line arguments.
+#[derive(Debug, Clone)]
+pub struct Args {
+    pub command: Command,
+    /// Config file path (None = search default locations).
+    pub config_path: Option<PathBuf>,
+    /// Override an existing PID file.
+    pub force: bool,
+    /// Skip the menu bar and run the capture loop on the main thread.
+    pub no_menu: bool,
+}
+
+impl Args {
+    /// Parse the process arguments.
+    pub fn parse() -> Result<Self> {
+        Self::parse_from(std::env::args().skip(1))
+    }
+
+    /// Parse arguments, excluding the program name.
+    pub fn parse_from(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut parsed = Self {
+            command: Command::Run,
+            config_path: None,
+            force: false,
+            no_menu: false,
+        };
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => parsed.command = Command::Help,
+                "-V" | "--version" => parsed.command = Command::Version,
+                "--force" => parsed.force = true,
+                "--no-menu" => parsed.no_menu = true,
+                "--print-config" => parsed.command = Command::PrintConfig(ConfigFormat::Toml),
+                _ if arg.starts_with("--print-config=") => {
+                    let format = match &arg["--print-config=".len()..] {
+                        "toml" => ConfigFormat::Toml,
+                        "json" => ConfigFormat::Json,
+                        other => anyhow::bail!("Unknown config format '{}' (expected toml or json)", other),
+                    };
+                    parsed.command = Command::PrintConfig(format);
+                }
+                "-c" | "--config" => {
+                    let path = args.next().context("--config requires a path")?;
+                    parsed.set_config_path(path)?;
+                }
+                _ if arg.starts_with("--config=") => {
+                    parsed.set_config_path(arg["--config=".len()..].to_string())?;
+                }
+                _ if arg.starts_with('-') => {
+                    anyhow::bail!("Unknown option '{}' (see --help)", arg);
+                }
+                "ctl" if parsed.command == Command::Run => {
+                    let command = args.next().context("ctl requires a command (see --help)")?;
+                    parsed.command = Command::Ctl(command);
+                }
+                "decrypt" if parsed.command == Command::Run => {
+                    let key = args.next().context("decrypt requires a key (see --help)")?;
+                    let input = args.next().context("decrypt requires an input file (see --help)")?;
+                    parsed.command = Command::Decrypt {
+                        key,
+                        input: PathBuf::from(input),
+                        output: args.next().map(PathBuf::from),
+                    };
+                }
+                "timelapse" if parsed.command == Command::Run => {
+                    parsed.command = Command::Timelapse(parsed.parse_timelapse(&mut args)?);
+                }
+                "export" if parsed.command == Command::Run => {
+                    parsed.command = Command::Export(parsed.parse_export(&mut args)?);
+                }
+                "usage" if parsed.command == Command::Run => {
+                    parsed.command = Command::Usage(parsed.parse_usage(&mut args)?);
+                }
+                // A bare positional path is the config file, as before
+                _ => parsed.set_config_path(arg)?,
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    /// Parse the remaining arguments as `timelapse` options.
+    fn parse_timelapse(&mut self, args: &mut impl Iterator<Item = String>) -> Result<TimelapseOptions> {
+        let mut since = None;
+        let mut out = None;
+        let mut fps = DEFAULT_TIMELAPSE_FPS;
+        let mut width = DEFAULT_TIMELAPSE_WIDTH;
+        let mut monitor_id = None;
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().with_context(|| format!("{} requires a value", name));
+            match arg.as_str() {
+                "--since" => since = Some(parse_duration(&value("--since")?)?),
+                "--out" => out = Some(PathBuf::from(value("--out")?)),
+                "--fps" => fps = value("--fps")?.parse().context("--fps must be a positive number")?,
+                "--width" => width = value("--width")?.parse().context("--width must be a positive number")?,
+                "--monitor" => monitor_id = Some(value("--monitor")?.parse().context("--monitor must be a display id")?),
+                "-c" | "--config" => {
+                    let path = value("--config")?;
+                    self.set_config_path(path)?;
+                }
+                _ => anyhow::bail!("Unknown timelapse option '{}' (see --help)", arg),
+            }
+        }
+
+        if fps == 0 || width == 0 {
+            anyhow::bail!("--fps and --width must be greater than 0");
+        }
+
+        Ok(TimelapseOptions {
+            since: since.context("timelapse requires --since (see --help)")?,
+            out: out.context("timelapse requires --out (see --help)")?,
+            fps,
+            width,
+            monitor_id,
+        })
+    }
+
+    /// Parse the remaining arguments as `export` options.
+    fn parse_export(&mut self, args: &mut impl Iterator<Item = String>) -> Result<ExportOptions> {
+        let mut date = None;
+        let mut out = None;
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().with_context(|| format!("{} requires a value", name));
+            match arg.as_str() {
+                "--date" => {
+                    let value = value("--date")?;
+                    date = Some(
+                        NaiveDate::parse_from_str(&value, "%Y-%m-%d")
+                            .with_context(|| format!("--date must be YYYY-MM-DD, got '{}'", value))?,
+                    );
+                }
+                "--out" => out = Some(PathBuf::from(value("--out")?)),
+                "-c" | "--config" => {
+                    let path = value("--config")?;
+                    self.set_config_path(path)?;
+                }
+                _ => anyhow::bail!("Unknown export option '{}' (see --help)", arg),
+            }
+        }
+
+        Ok(ExportOptions {
+            date: date.context("export requires --date (see --help)")?,
+            out: out.context("export requires --out (see --help)")?,
+        })
+    }
+
+    /// Parse the remaining arguments as `usage` options.
+    fn parse_usage(&mut self, args: &mut impl Iterator<Item = String>) -> Result<UsageOptions> {
+        let mut days = DEFAULT_USAGE_DAYS;
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().with_context(|| format!("{} requires a value", name));
+            match arg.as_str() {
+                "--days" => days = value("--days")?.parse().context("--days must be a positive number")?,
+                "-c" | "--config" => {
+                    let path = value("--config")?;
+                    self.set_config_path(path)?;
+                }
+                _ => anyhow::bail!("Unknown usage option '{}' (see --help)", arg),
+            }
+        }
+
+        if days == 0 {
+            anyhow::bail!("--days must be greater than 0");
+        }
+
+        Ok(UsageOptions { days })
+    }
+
+    fn set_config_path(&mut self, path: String) -> Result<()> {
+        if self.config_path.is_some() {
+            anyhow::bail!("Config path given more than once (see --help)");
+        }
+        self.config_path = Some(PathBuf::from(path));
+        Ok(())
+    }
+}
+
+/// Parse a duration like `90s`, `30m`, `2h`, or `1d` (bare numbers are seconds).
+pub fn parse_duration(value: &str) -> Result<Duration> {
+    let value = value.trim();
+    let (number, unit_secs) = match value.char_indices().last() {
+        Some((i, 's')) => (&value[..i], 1),
+        Some((i, 'm')) => (&value[..i], 60),
+        Some((i, 'h')) => (&value[..i], 60 * 60),
+        Some((i, 'd')) => (&value[..i], 24 * 60 * 60),
+        _ => (value, 1),
+    };
+    let number: u64 = number
+        .parse()
+        .with_context(|| format!("Invalid duration '{}' (expected e.g. 30m, 2h, 1d)", value))?;
+    let secs = number
+        .checked_mul(unit_secs)
+        .with_context(|| format!("Duration '{}' is too long", value))?;
+    Ok(Duration::from_secs(secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations_with_units() {
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("30m").unwrap(), Duration::from_secs(30 * 60));
+        assert_eq!(parse_duration(" 2h ").unwrap(), Duration::from_secs(2 * 60 * 60));
+        assert_eq!(parse_duration("1d").unwrap(), Duration::from_secs(24 * 60 * 60));
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("m").is_err());
+        assert!(parse_duration("-1h").is_err());
+    }
+
+    #[test]
+    fn overflowing_durations_are_rejected() {
+        assert_eq!(parse_duration(&u64::MAX.to_string()).unwrap(), Duration::from_secs(u64::MAX));
+        let err = parse_duration(&format!("{}d", u64::MAX)).unwrap_err();
+        assert!(err.to_string().contains("too long"));
+    }
+}
@@ -0,0 +1,127 @@
+//! Unix domain control socket for runtime commands.
+//!
+//! Each connection sends one command per line (`pause`, `pause <DURATION>`,
+//! `resume`, `capture`, `status`, `quit`) and receives a one-line reply.
+
+use anyhow::{Context, Result};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, info, warn};
+
+use crate::cli::parse_duration;
+use crate::MenuCommand;
+
+/// Listens on the control socket and forwards commands to the capture loop.
+pub struct ControlServer {
+    path: PathBuf,
+    handle: tokio::task::JoinHandle<()>,
+}
+
+impl ControlServer {
+    /// Bind the control socket at `path` and start accepting connections.
+    pub fn start(path: &Path, cmd_tx: mpsc::Sender<MenuCommand>) -> Result<Self> {
+        // A leftover socket from a crashed run would make bind fail; the PID
+        // file already guarantees no other instance owns it
+        if path.exists() {
+            std::fs::remove_file(path)
+                .with_context(|| format!("Failed to remove stale control socket: {:?}", path))?;
+        }
+
+        let listener = UnixListener::bind(path)
+            .with_context(|| format!("Failed to bind control socket: {:?}", path))?;
+        info!("Control socket listening on {:?}", path);
+
+        let handle = tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        let cmd_tx = cmd_tx.clone();
+                        tokio::spawn(async move {
+                            if let Err(e) = handle_connection(stream, cmd_tx).await {
+                                debug!("Control connection error: {}", e);
+                            }
+                        });
+                    }
+                    Err(e) => warn!("Failed to accept control connection: {}", e),
+                }
+            }
+        });
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            handle,
+        })
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+async fn handle_connection(stream: UnixStream, cmd_tx: mpsc::Sender<MenuCommand>) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = tokio::io::BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        let reply = match line.trim() {
+            "" => continue,
+            command => dispatch(&cmd_tx, command)
+                .await
+                .unwrap_or_else(|e| format!("error: {}", e)),
+        };
+
+        writer.write_all(reply.as_bytes()).await?;
+        writer.write_all(b"\n").await?;
+    }
+
+    Ok(())
+}
+
+/// Forward a command to the capture loop and return the reply text.
+async fn dispatch(cmd_tx: &mpsc::Sender<MenuCommand>, command: &str) -> Result<String> {
+    let cmd = match command {
+        "pause" => MenuCommand::Pause,
+        "resume" => MenuCommand::Resume,
+        "capture" => MenuCommand::CaptureNow,
+        "quit" => MenuCommand::Quit,
+        _ if command.starts_with("pause ") => MenuCommand::PauseFor(parse_duration(&command["pause ".len()..])?),
+        "status" => {
+            let (reply_tx, reply_rx) = oneshot::channel();
+            forward(cmd_tx, MenuCommand::Status(reply_tx)).await?;
+            return reply_rx.await.context("No status reply from capture loop");
+        }
+        other => anyhow::bail!(
+            "unknown command '{}' (expected pause [DURATION], resume, capture, status, quit)",
+            other
+        ),
+    };
+
+    forward(cmd_tx, cmd).await?;
+    Ok("ok".to_string())
+}
+
+async fn forward(cmd_tx: &mpsc::Sender<MenuCommand>, cmd: MenuCommand) -> Result<()> {
+    cmd_tx
+        .send(cmd)
+        .await
+        .map_err(|_| anyhow::anyhow!("capture loop is not running"))
+}
+
+/// Send a command to a running daemon and return its reply.
+pub fn send_command(path: &Path, command: &str) -> Result<String> {
+    let mut stream = std::os::unix::net::UnixStream::connect(path)
+        .with_context(|| format!("Failed to connect to control socket {:?}; is preprompter running?", path))?;
+
+    writeln!(stream, "{}", command)?;
+    stream.shutdown(std::net::Shutdown::Write)?;
+
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    Ok(reply.trim_end().to_string())
+}
@@ -0,0 +1,85 @@
+//! Client-side AES-256-GCM encryption of frames before upload.
+//!
+//! Encrypted objects are laid out as `nonce (12 bytes) || ciphertext || tag`.
+
+use anyhow::{Context, Result};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::config::CryptoConfig;
+
+/// Name of the encryption scheme, recorded in object metadata.
+pub const ALGORITHM: &str = "aes-256-gcm";
+
+/// Encrypts and decrypts frame bytes with a fixed key.
+pub struct FrameCipher {
+    key: LessSafeKey,
+    rng: SystemRandom,
+}
+
+impl FrameCipher {
+    /// Create a cipher from a hex-encoded 256-bit key.
+    pub fn from_hex(key: &str) -> Result<Self> {
+        let bytes = hex::decode(key.trim()).context("Encryption key must be hex-encoded")?;
+        if bytes.len() != 32 {
+            anyhow::bail!(
+                "Encryption key must be 32 bytes (64 hex characters), got {} bytes",
+                bytes.len()
+            );
+        }
+
+        let key = UnboundKey::new(&AES_256_GCM, &bytes)
+            .map_err(|_| anyhow::anyhow!("Invalid AES-256-GCM key"))?;
+
+        Ok(Self {
+            key: LessSafeKey::new(key),
+            rng: SystemRandom::new(),
+        })
+    }
+
+    /// Create the configured cipher, or `None` if encryption is disabled.
+    pub fn from_config(config: &CryptoConfig) -> Result<Option<Self>> {
+        if !config.enabled {
+            return Ok(None);
+        }
+        config.key.as_deref().map(Self::from_hex).transpose()
+    }
+
+    /// Encrypt `plaintext` under a fresh random nonce.
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| anyhow::anyhow!("Failed to generate nonce"))?;
+
+        let mut in_out = plaintext.to_vec();
+        self.key
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut in_out)
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt frame"))?;
+
+        let mut out = Vec::with_capacity(NONCE_LEN + in_out.len());
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&in_out);
+        Ok(out)
+    }
+
+    /// Decrypt data produced by [`FrameCipher::encrypt`].
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+        if data.len() < NONCE_LEN + AES_256_GCM.tag_len() {
+            anyhow::bail!("Encrypted data is too short");
+        }
+
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce)
+            .map_err(|_| anyhow::anyhow!("Invalid nonce"))?;
+
+        let mut in_out = ciphertext.to_vec();
+        let plaintext_len = self
+            .key
+            .open_in_place(nonce, Aad::empty(), &mut in_out)
+            .map_err(|_| anyhow::anyhow!("Decryption failed: wrong key or corrupted data"))?
+            .len();
+        in_out.truncate(plaintext_len);
+        Ok(in_out)
+    }
+}
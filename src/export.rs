@@ -0,0 +1,123 @@
+//! Export a day's uploaded frames to a local directory.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveTime, TimeZone, Utc};
+use serde::Serialize;
+use std::path::Path;
+use tracing::{info, warn};
+
+use crate::cli::ExportOptions;
+use crate::config::{Compression, Config};
+use crate::logging::read_frames_since;
+use crate::storage;
+
+/// Manifest file written alongside the exported frames.
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// One exported frame in the manifest.
+#[derive(Debug, Serialize)]
+struct ManifestEntry {
+    timestamp: DateTime<Utc>,
+    frame_id: String,
+    monitor_id: u32,
+    s3_key: String,
+    /// File name within the export directory (None if the download failed).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<String>,
+    /// Why the frame could not be exported.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Download every frame logged on `options.date` (local time) into `options.out`.
+pub async fn run(config: &Config, options: &ExportOptions) -> Result<()> {
+    let start = local_midnight(options.date)?;
+    let end = local_midnight(options.date.succ_opt().context("Date out of range")?)?;
+
+    let entries: Vec<_> = read_frames_since(&config.logging, start)?
+        .into_iter()
+        .filter(|e| e.timestamp < end)
+        .collect();
+    if entries.is_empty() {
+        anyhow::bail!("No frames logged on {}", options.date);
+    }
+
+    std::fs::create_dir_all(&options.out)
+        .with_context(|| format!("Failed to create export directory: {:?}", options.out))?;
+    info!("Exporting {} frames from {} to {:?}", entries.len(), options.date, options.out);
+
+    let storage = storage::create_backend(config).await?;
+
+    let mut manifest = Vec::with_capacity(entries.len());
+    let mut exported = 0;
+    for (index, entry) in entries.iter().enumerate() {
+        // Numbered so a plain directory listing sorts in capture order
+        let file_name = format!(
+            "{:05}-{}-mon{}.{}",
+            index + 1,
+            entry.frame_id,
+            entry.monitor_id,
+            frame_extension(&entry.s3_key)
+        );
+
+        let result = match storage.download_bytes(&entry.s3_key).await {
+            Ok(data) => std::fs::write(options.out.join(&file_name), data)
+                .with_context(|| format!("Failed to write {}", file_name)),
+            Err(e) => Err(e),
+        };
+
+        let (file, error) = match result {
+            Ok(()) => {
+                exported += 1;
+                (Some(file_name), None)
+            }
+            Err(e) => {
+                warn!("Skipping frame {}: {:#}", entry.frame_id, e);
+                (None, Some(format!("{:#}", e)))
+            }
+        };
+        manifest.push(ManifestEntry {
+            timestamp: entry.timestamp,
+            frame_id: entry.frame_id.clone(),
+            monitor_id: entry.monitor_id,
+            s3_key: entry.s3_key.clone(),
+            file,
+            error,
+        });
+    }
+
+    let manifest_path = options.out.join(MANIFEST_FILE);
+    std::fs::write(&manifest_path, serde_json::to_vec_pretty(&manifest)?)
+        .with_context(|| format!("Failed to write manifest: {:?}", manifest_path))?;
+
+    info!(
+        "Exported {} of {} frames ({} missing), manifest at {:?}",
+        exported,
+        entries.len(),
+        entries.len() - exported,
+        manifest_path
+    );
+    Ok(())
+}
+
+/// Start of the given local day, in UTC.
+pub fn local_midnight(date: chrono::NaiveDate) -> Result<DateTime<Utc>> {
+    Local
+        .from_local_datetime(&date.and_time(NaiveTime::MIN))
+        .earliest()
+        .map(|t| t.with_timezone(&Utc))
+        .with_context(|| format!("Invalid local date: {}", date))
+}
+
+/// Image file extension for a stored key, ignoring any compression suffix
+/// (downloads are decompressed).
+fn frame_extension(key: &str) -> &str {
+    let key = match Compression::from_key(key) {
+        Some(c) => &key[..key.len() - c.extension().len() - 1],
+        None => key,
+    };
+    Path::new(key)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("bin")
+}
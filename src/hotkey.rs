@@ -0,0 +1,130 @@
+//! Global "capture now" hotkey, observed with a listen-only CGEventTap.
+
+use anyhow::{Context, Result};
+use core_foundation::runloop::{kCFRunLoopCommonModes, CFRunLoop};
+use core_graphics::event::{
+    CGEventFlags, CGEventTap, CGEventTapLocation, CGEventTapOptions, CGEventTapPlacement,
+    CGEventType, EventField,
+};
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::thread;
+use tracing::info;
+
+/// Modifier flags compared when matching a hotkey; others (caps lock, fn) are ignored.
+const MODIFIER_MASK: CGEventFlags = CGEventFlags::CGEventFlagCommand
+    .union(CGEventFlags::CGEventFlagShift)
+    .union(CGEventFlags::CGEventFlagControl)
+    .union(CGEventFlags::CGEventFlagAlternate);
+
+/// A key plus modifiers, parsed from strings like `cmd+shift+5`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hotkey {
+    keycode: i64,
+    modifiers: CGEventFlags,
+}
+
+impl FromStr for Hotkey {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let mut modifiers = CGEventFlags::empty();
+        let mut keycode = None;
+
+        for part in value.split('+').map(|p| p.trim().to_ascii_lowercase()) {
+            match part.as_str() {
+                "cmd" | "command" => modifiers |= CGEventFlags::CGEventFlagCommand,
+                "shift" => modifiers |= CGEventFlags::CGEventFlagShift,
+                "ctrl" | "control" => modifiers |= CGEventFlags::CGEventFlagControl,
+                "alt" | "opt" | "option" => modifiers |= CGEventFlags::CGEventFlagAlternate,
+                key if keycode.is_none() => {
+                    keycode = Some(
+                        keycode_for(key)
+                            .with_context(|| format!("Unknown key '{}' in hotkey '{}'", key, value))?,
+                    );
+                }
+                _ => anyhow::bail!("Hotkey '{}' has more than one non-modifier key", value),
+            }
+        }
+
+        let keycode = keycode.with_context(|| format!("Hotkey '{}' has no key", value))?;
+        if modifiers.is_empty() {
+            anyhow::bail!("Hotkey '{}' needs at least one modifier (cmd, shift, ctrl, alt)", value);
+        }
+        Ok(Self { keycode, modifiers })
+    }
+}
+
+/// macOS virtual keycode for a key name (ANSI layout).
+fn keycode_for(key: &str) -> Option<i64> {
+    const LETTERS: [(char, i64); 26] = [
+        ('a', 0), ('b', 11), ('c', 8), ('d', 2), ('e', 14), ('f', 3), ('g', 5),
+        ('h', 4), ('i', 34), ('j', 38), ('k', 40), ('l', 37), ('m', 46), ('n', 45),
+        ('o', 31), ('p', 35), ('q', 12), ('r', 15), ('s', 1), ('t', 17), ('u', 32),
+        ('v', 9), ('w', 13), ('x', 7), ('y', 16), ('z', 6),
+    ];
+    const DIGITS: [i64; 10] = [29, 18, 19, 20, 21, 23, 22, 26, 28, 25];
+    const FUNCTION_KEYS: [i64; 12] = [122, 120, 99, 118, 96, 97, 98, 100, 101, 109, 103, 111];
+
+    let mut chars = key.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        if let Some(digit) = c.to_digit(10) {
+            return Some(DIGITS[digit as usize]);
+        }
+        return LETTERS.iter().find(|(l, _)| *l == c).map(|&(_, code)| code);
+    }
+    match key {
+        "space" => Some(49),
+        _ => {
+            let n: usize = key.strip_prefix('f')?.parse().ok()?;
+            FUNCTION_KEYS.get(n.checked_sub(1)?).copied()
+        }
+    }
+}
+
+/// Call `on_press` from a background thread whenever `hotkey` is pressed.
+/// Fails if the event tap can't be created (Accessibility permission missing).
+pub fn listen(hotkey: Hotkey, on_press: impl Fn() + Send + 'static) -> Result<()> {
+    let (ready_tx, ready_rx) = mpsc::channel();
+
+    thread::Builder::new()
+        .name("hotkey".to_string())
+        .spawn(move || {
+            let tap = CGEventTap::new(
+                CGEventTapLocation::Session,
+                CGEventTapPlacement::TailAppendEventTap,
+                CGEventTapOptions::ListenOnly,
+                vec![CGEventType::KeyDown],
+                move |_, _, event| {
+                    let repeat = event.get_integer_value_field(EventField::KEYBOARD_EVENT_AUTOREPEAT) != 0;
+                    let keycode = event.get_integer_value_field(EventField::KEYBOARD_EVENT_KEYCODE);
+                    if !repeat
+                        && keycode == hotkey.keycode
+                        && event.get_flags() & MODIFIER_MASK == hotkey.modifiers
+                    {
+                        on_press();
+                    }
+                    None
+                },
+            );
+            let Some((tap, source)) = tap
+                .ok()
+                .and_then(|tap| tap.mach_port.create_runloop_source(0).ok().map(|s| (tap, s)))
+            else {
+                let _ = ready_tx.send(false);
+                return;
+            };
+
+            // SAFETY: kCFRunLoopCommonModes is an immutable CFString constant
+            CFRunLoop::get_current().add_source(&source, unsafe { kCFRunLoopCommonModes });
+            tap.enable();
+            let _ = ready_tx.send(true);
+            CFRunLoop::run_current();
+        })?;
+
+    if !ready_rx.recv().unwrap_or(false) {
+        anyhow::bail!("Could not create event tap (Accessibility permission needed)");
+    }
+    info!("Capture hotkey registered");
+    Ok(())
+}
@@ -1,16 +1,29 @@
-//! Idle detection using IOKit HIDIdleTime for system-wide idle monitoring.
+//! Idle detection using IOKit HIDIdleTime or a CGEventTap for system-wide idle monitoring.
 
 use anyhow::Result;
 use chrono::{DateTime, Utc};
 use core_foundation::base::TCFType;
+use core_foundation::mach_port::CFMachPortInvalidate;
 use core_foundation::number::CFNumber;
+use core_foundation::runloop::{kCFRunLoopCommonModes, kCFRunLoopDefaultMode, CFRunLoop};
 use core_foundation::string::CFString;
+use core_graphics::event::{
+    CGEventTap, CGEventTapLocation, CGEventTapOptions, CGEventTapPlacement, CGEventType,
+};
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use std::sync::Arc;
+use std::sync::{mpsc, Arc};
 use std::thread::{self, JoinHandle};
 use std::time::Duration;
 use tokio::sync::broadcast;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
+
+use crate::config::IdleMethod;
+
+/// Default poll and check interval.
+const DEFAULT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long the event tap run loop runs before rechecking whether to stop.
+const EVENT_TAP_RUN_SLICE: Duration = Duration::from_millis(500);
 
 /// User activity state.
 #[derive(Debug, Clone, PartialEq)]
@@ -29,6 +42,8 @@ struct IdleState {
     running: AtomicBool,
     /// Current idle state.
     is_idle: AtomicBool,
+    /// Input events seen since the count was last taken.
+    input_events: AtomicU64,
 }
 
 impl IdleState {
@@ -38,12 +53,22 @@ impl IdleState {
             last_activity_ms: AtomicU64::new(now_ms),
             running: AtomicBool::new(false),
             is_idle: AtomicBool::new(false),
+            input_events: AtomicU64::new(0),
         }
     }
 
-    fn update_activity(&self) {
+    /// Record user input happening now.
+    fn record_activity(&self) {
         let now_ms = Utc::now().timestamp_millis() as u64;
         self.last_activity_ms.store(now_ms, Ordering::SeqCst);
+        self.input_events.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record the last activity time from the system idle counter.
+    fn update_from_idle_time(&self, idle_time: Duration) {
+        let now_ms = Utc::now().timestamp_millis() as u64;
+        let last_ms = now_ms.saturating_sub(idle_time.as_millis() as u64);
+        self.last_activity_ms.store(last_ms, Ordering::SeqCst);
     }
 
     fn idle_duration(&self) -> Duration {
@@ -54,16 +79,20 @@ impl IdleState {
     }
 }
 
-/// Idle detector using CGEventTap for system-wide event monitoring.
+/// Idle detector using HIDIdleTime polling or a CGEventTap for system-wide event monitoring.
 pub struct IdleDetector {
     /// Idle threshold duration.
     threshold: Duration,
+    /// Activity source.
+    method: IdleMethod,
+    /// How often HIDIdleTime is polled.
+    poll_interval: Duration,
+    /// How often idle transitions are checked.
+    check_interval: Duration,
     /// Shared state.
     state: Arc<IdleState>,
     /// Broadcast sender for state changes.
     state_tx: broadcast::Sender<ActivityState>,
-    /// Event tap thread handle.
-    event_tap_handle: Option<JoinHandle<()>>,
     /// Checker thread handle.
     checker_handle: Option<JoinHandle<()>>,
 }
@@ -75,18 +104,40 @@ impl IdleDetector {
 
         Ok(Self {
             threshold,
+            method: IdleMethod::default(),
+            poll_interval: DEFAULT_INTERVAL,
+            check_interval: DEFAULT_INTERVAL,
             state: Arc::new(IdleState::new()),
             state_tx,
-            event_tap_handle: None,
             checker_handle: None,
         })
     }
 
+    /// Set how user activity is detected.
+    pub fn with_method(mut self, method: IdleMethod) -> Self {
+        self.method = method;
+        self
+    }
+
+    /// Set how often HIDIdleTime is polled and idle transitions are checked.
+    pub fn with_intervals(mut self, poll_interval: Duration, check_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self.check_interval = check_interval;
+        self
+    }
+
     /// Subscribe to activity state changes.
     pub fn subscribe(&self) -> broadcast::Receiver<ActivityState> {
         self.state_tx.subscribe()
     }
 
+    /// Number of input events since the last call. With the event tap every
+    /// key press, click, and mouse move counts; with HIDIdleTime polling only
+    /// polls that saw the idle counter reset do, so it's a lower bound.
+    pub fn take_input_events(&self) -> u64 {
+        self.state.input_events.swap(0, Ordering::Relaxed)
+    }
+
     /// Get the current activity state.
     pub fn state(&self) -> ActivityState {
         if self.state.is_idle.load(Ordering::SeqCst) {
@@ -107,28 +158,38 @@ impl IdleDetector {
 
         info!("Starting idle detector with threshold {:?}", self.threshold);
 
+        let tap_started = match self.method {
+            IdleMethod::EventTap => start_event_tap(self.state.clone())?,
+            IdleMethod::HidIdleTime => false,
+        };
+
         // Start idle monitor thread (polls IOKit HIDIdleTime)
-        let state_clone = self.state.clone();
-        let _monitor_handle = thread::Builder::new()
-            .name("idle-monitor".to_string())
-            .spawn(move || {
-                run_idle_monitor(state_clone);
-            })?;
+        if !tap_started {
+            let state_clone = self.state.clone();
+            let poll_interval = self.poll_interval;
+            let _monitor_handle = thread::Builder::new()
+                .name("idle-monitor".to_string())
+                .spawn(move || {
+                    run_idle_monitor(state_clone, poll_interval);
+                })?;
+        }
 
         // Start checker thread (broadcasts state changes)
         let state_clone = self.state.clone();
         let threshold = self.threshold;
+        let check_interval = self.check_interval;
         let state_tx = self.state_tx.clone();
         let _checker_handle = thread::Builder::new()
             .name("idle-checker".to_string())
             .spawn(move || {
-                run_idle_checker(state_clone, threshold, state_tx);
+                run_idle_checker(state_clone, threshold, check_interval, state_tx);
             })?;
 
         Ok(())
     }
 
-    /// Stop the idle detector.
+    /// Stop the idle detector. Its threads exit, removing the event tap if
+    /// one was installed, the next time they wake.
     pub fn stop(&self) {
         self.state.running.store(false, Ordering::SeqCst);
         info!("Idle detector stopped");
@@ -191,33 +252,112 @@ fn get_system_idle_time() -> Option<Duration> {
 }
 
 /// Run the idle detection loop using IOKit HIDIdleTime polling.
-fn run_idle_monitor(state: Arc<IdleState>) {
-    info!("Starting idle monitor using IOKit HIDIdleTime");
-
-    let poll_interval = Duration::from_millis(500);
+fn run_idle_monitor(state: Arc<IdleState>, poll_interval: Duration) {
+    info!("Starting idle monitor using IOKit HIDIdleTime (every {:?})", poll_interval);
 
+    let mut last_idle_time = None;
     while state.running.load(Ordering::SeqCst) {
         thread::sleep(poll_interval);
 
-        // Update the last activity time based on system idle time
+        // Derive the last activity time directly from the system idle counter,
+        // so missed polls can't skew the computed idle duration
         if let Some(idle_time) = get_system_idle_time() {
-            // If idle time is very small, user just did something
-            if idle_time < poll_interval {
-                state.update_activity();
+            state.update_from_idle_time(idle_time);
+
+            // The counter only goes backwards when there was input since the
+            // last poll, which is as close to an event count as it gets
+            if last_idle_time.is_some_and(|last| idle_time < last) {
+                state.input_events.fetch_add(1, Ordering::Relaxed);
             }
+            last_idle_time = Some(idle_time);
         }
     }
 
     debug!("Idle monitor thread exiting");
 }
 
+/// Start a thread observing key and mouse events through a listen-only event tap.
+/// Returns false if the tap couldn't be created (usually missing Accessibility
+/// permission), in which case the caller should fall back to HIDIdleTime.
+fn start_event_tap(state: Arc<IdleState>) -> Result<bool> {
+    let (ready_tx, ready_rx) = mpsc::channel();
+
+    let _tap_handle = thread::Builder::new()
+        .name("idle-event-tap".to_string())
+        .spawn(move || run_event_tap(state, ready_tx))?;
+
+    let started = ready_rx.recv().unwrap_or(false);
+    if !started {
+        warn!("Could not create event tap (Accessibility permission needed), falling back to HIDIdleTime");
+    }
+    Ok(started)
+}
+
+/// Run the event tap on this thread's run loop until the detector stops.
+fn run_event_tap(state: Arc<IdleState>, ready_tx: mpsc::Sender<bool>) {
+    let events = vec![
+        CGEventType::KeyDown,
+        CGEventType::FlagsChanged,
+        CGEventType::MouseMoved,
+        CGEventType::LeftMouseDown,
+        CGEventType::RightMouseDown,
+        CGEventType::OtherMouseDown,
+        CGEventType::LeftMouseDragged,
+        CGEventType::RightMouseDragged,
+        CGEventType::OtherMouseDragged,
+        CGEventType::ScrollWheel,
+    ];
+
+    let tap_state = state.clone();
+    let tap = CGEventTap::new(
+        CGEventTapLocation::Session,
+        CGEventTapPlacement::TailAppendEventTap,
+        CGEventTapOptions::ListenOnly,
+        events,
+        move |_, _, _| {
+            tap_state.record_activity();
+            None
+        },
+    );
+    let Ok(tap) = tap else {
+        let _ = ready_tx.send(false);
+        return;
+    };
+    let Ok(source) = tap.mach_port.create_runloop_source(0) else {
+        let _ = ready_tx.send(false);
+        return;
+    };
+
+    // SAFETY: kCFRunLoopCommonModes is an immutable CFString constant
+    CFRunLoop::get_current().add_source(&source, unsafe { kCFRunLoopCommonModes });
+    tap.enable();
+    let _ = ready_tx.send(true);
+    info!("Starting idle monitor using CGEventTap");
+
+    while state.running.load(Ordering::SeqCst) {
+        // SAFETY: kCFRunLoopDefaultMode is an immutable CFString constant
+        CFRunLoop::run_in_mode(unsafe { kCFRunLoopDefaultMode }, EVENT_TAP_RUN_SLICE, false);
+        // The system disables taps whose callbacks are slow; turn it back on
+        tap.enable();
+    }
+
+    // Remove the tap now rather than whenever the thread's run loop is torn down
+    // SAFETY: kCFRunLoopCommonModes is an immutable CFString constant, and the
+    // port is invalidated only after the loop that services it has stopped
+    unsafe {
+        CFRunLoop::get_current().remove_source(&source, kCFRunLoopCommonModes);
+        CFMachPortInvalidate(tap.mach_port.as_concrete_TypeRef());
+    }
+    debug!("Event tap thread exiting");
+}
+
 /// Run the idle state checker thread.
 fn run_idle_checker(
     state: Arc<IdleState>,
     threshold: Duration,
+    check_interval: Duration,
     state_tx: broadcast::Sender<ActivityState>,
 ) {
-    let check_interval = Duration::from_millis(500);
     let mut was_idle = false;
 
     while state.running.load(Ordering::SeqCst) {
@@ -0,0 +1,64 @@
+//! Screen lock detection from the login session's CoreGraphics dictionary.
+
+use anyhow::Result;
+use core_foundation::base::{CFType, TCFType};
+use core_foundation::boolean::CFBoolean;
+use core_foundation::dictionary::{CFDictionary, CFDictionaryRef};
+use core_foundation::string::CFString;
+use std::thread;
+use std::time::Duration;
+use tracing::debug;
+
+/// How often the lock state is checked.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Session dictionary key present (and true) while the screen is locked.
+const SCREEN_IS_LOCKED_KEY: &str = "CGSSessionScreenIsLocked";
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGSessionCopyCurrentDictionary() -> CFDictionaryRef;
+}
+
+/// Returns true if the screen is locked. None if there's no session to ask
+/// (e.g. running outside a GUI login).
+fn is_screen_locked() -> Option<bool> {
+    // SAFETY: the copied dictionary is checked for null and owned by the wrapper
+    let session: CFDictionary<CFString, CFType> = unsafe {
+        let dict = CGSessionCopyCurrentDictionary();
+        if dict.is_null() {
+            return None;
+        }
+        CFDictionary::wrap_under_create_rule(dict)
+    };
+    let locked = session
+        .find(CFString::from_static_string(SCREEN_IS_LOCKED_KEY))
+        .and_then(|value| value.downcast::<CFBoolean>())
+        .is_some_and(bool::from);
+    Some(locked)
+}
+
+/// Start a thread calling `on_unlock` each time the screen goes from locked
+/// to unlocked. The thread exits once `on_unlock` returns false.
+pub fn watch_unlocks(on_unlock: impl Fn() -> bool + Send + 'static) -> Result<()> {
+    thread::Builder::new()
+        .name("screen-lock".to_string())
+        .spawn(move || {
+            let mut locked = is_screen_locked().unwrap_or(false);
+
+            loop {
+                thread::sleep(POLL_INTERVAL);
+                let Some(now_locked) = is_screen_locked() else { continue };
+                if now_locked == locked {
+                    continue;
+                }
+
+                locked = now_locked;
+                debug!("Screen {}", if locked { "locked" } else { "unlocked" });
+                if !locked && !on_unlock() {
+                    break;
+                }
+            }
+        })?;
+    Ok(())
+}
@@ -1,6 +1,8 @@
 //! Idle detection module using CGEventTap.
 
 mod detector;
+mod lock;
 
 pub use detector::{ActivityState, IdleDetector};
+pub use lock::watch_unlocks;
 
@@ -2,73 +2,19 @@
 
 use anyhow::{Context, Result};
 use chrono::{DateTime, Local, Utc};
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 use std::fs::{File, OpenOptions};
-use std::io::{BufWriter, Write};
-use std::path::PathBuf;
-use tracing::{debug, info};
-
-use crate::capture::CapturedFrame;
-
-/// Log entry for a captured frame.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct FrameLogEntry {
-    /// Capture timestamp.
-    pub timestamp: DateTime<Utc>,
-    /// Unique frame identifier.
-    pub frame_id: String,
-    /// S3 key where the frame was uploaded.
-    pub s3_key: String,
-    /// S3 bucket name.
-    pub s3_bucket: String,
-    /// Frame width in pixels.
-    pub width: u32,
-    /// Frame height in pixels.
-    pub height: u32,
-    /// Monitor ID that was captured.
-    pub monitor_id: u32,
-    /// File size in bytes.
-    pub file_size_bytes: usize,
-    /// Time to capture the frame in milliseconds.
-    pub capture_duration_ms: u64,
-    /// Time to upload the frame in milliseconds.
-    pub upload_duration_ms: u64,
-    /// Seconds idle before this capture (0 if not idle).
-    pub idle_seconds_before: u64,
-}
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tracing::debug;
 
-/// Session event types for JSONL logging.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(tag = "event")]
-pub enum SessionEvent {
-    #[serde(rename = "session_start")]
-    SessionStart {
-        timestamp: DateTime<Utc>,
-        version: String,
-    },
-    #[serde(rename = "session_end")]
-    SessionEnd {
-        timestamp: DateTime<Utc>,
-        frames_captured: u64,
-    },
-    #[serde(rename = "idle_start")]
-    IdleStart {
-        timestamp: DateTime<Utc>,
-        idle_after_seconds: u64,
-    },
-    #[serde(rename = "idle_end")]
-    IdleEnd {
-        timestamp: DateTime<Utc>,
-        idle_duration_seconds: u64,
-    },
-}
+use super::logger::{FrameLogEntry, LogSink, SessionEvent};
 
 /// JSONL logger for frame metadata.
 pub struct JsonlLogger {
     logs_dir: PathBuf,
     current_file: Option<BufWriter<File>>,
     current_date: Option<String>,
-    idle_start_time: Option<DateTime<Utc>>,
 }
 
 impl JsonlLogger {
@@ -81,7 +27,6 @@ impl JsonlLogger {
             logs_dir,
             current_file: None,
             current_date: None,
-            idle_start_time: None,
         })
     }
 
@@ -118,83 +63,52 @@ impl JsonlLogger {
         writer.flush()?;
         Ok(())
     }
+}
 
-    /// Log a captured frame.
-    pub fn log_frame(
-        &mut self,
-        frame: &CapturedFrame,
-        s3_key: &str,
-        s3_bucket: &str,
-        upload_duration_ms: u64,
-        idle_seconds_before: u64,
-    ) -> Result<()> {
-        let entry = FrameLogEntry {
-            timestamp: frame.timestamp,
-            frame_id: frame.frame_id(),
-            s3_key: s3_key.to_string(),
-            s3_bucket: s3_bucket.to_string(),
-            width: frame.width,
-            height: frame.height,
-            monitor_id: frame.monitor_id,
-            file_size_bytes: frame.data.len(),
-            capture_duration_ms: frame.capture_duration_ms,
-            upload_duration_ms,
-            idle_seconds_before,
-        };
-
-        self.write_line(&entry)
-    }
-
-    /// Log session start event.
-    pub fn log_session_start(&mut self, version: &str) -> Result<()> {
-        let event = SessionEvent::SessionStart {
-            timestamp: Utc::now(),
-            version: version.to_string(),
-        };
-        info!("Session started");
-        self.write_line(&event)
+impl LogSink for JsonlLogger {
+    fn write_frame(&mut self, entry: &FrameLogEntry) -> Result<()> {
+        self.write_line(entry)
     }
 
-    /// Log session end event.
-    pub fn log_session_end(&mut self, frames_captured: u64) -> Result<()> {
-        let event = SessionEvent::SessionEnd {
-            timestamp: Utc::now(),
-            frames_captured,
-        };
-        info!("Session ended, {} frames captured", frames_captured);
-        self.write_line(&event)
+    fn write_event(&mut self, event: &SessionEvent) -> Result<()> {
+        self.write_line(event)
     }
+}
 
-    /// Log idle start event.
-    pub fn log_idle_start(&mut self, idle_after_seconds: u64) -> Result<()> {
-        self.idle_start_time = Some(Utc::now());
-        let event = SessionEvent::IdleStart {
-            timestamp: Utc::now(),
-            idle_after_seconds,
-        };
-        self.write_line(&event)
+/// Read frame entries captured at or after `since` from daily JSONL files.
+pub fn read_frames(logs_dir: &Path, since: DateTime<Utc>) -> Result<Vec<FrameLogEntry>> {
+    let first_day = since.with_timezone(&Local).format("%Y-%m-%d").to_string();
+
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(logs_dir)
+        .with_context(|| format!("Failed to read logs directory: {:?}", logs_dir))?
+    {
+        let path = entry?.path();
+        let is_recent_log = path.extension().is_some_and(|e| e == "jsonl")
+            && path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .is_some_and(|day| day >= first_day.as_str());
+        if is_recent_log {
+            paths.push(path);
+        }
     }
-
-    /// Log idle end event.
-    pub fn log_idle_end(&mut self) -> Result<()> {
-        let idle_duration = self
-            .idle_start_time
-            .map(|start| (Utc::now() - start).num_seconds().max(0) as u64)
-            .unwrap_or(0);
-
-        self.idle_start_time = None;
-
-        let event = SessionEvent::IdleEnd {
-            timestamp: Utc::now(),
-            idle_duration_seconds: idle_duration,
-        };
-        self.write_line(&event)
+    paths.sort();
+
+    let mut frames = Vec::new();
+    for path in paths {
+        let file = File::open(&path).with_context(|| format!("Failed to open log file: {:?}", path))?;
+        for line in BufReader::new(file).lines() {
+            // Session events share the file and don't parse as frames
+            if let Ok(entry) = serde_json::from_str::<FrameLogEntry>(&line?) {
+                if entry.timestamp >= since {
+                    frames.push(entry);
+                }
+            }
+        }
     }
 
-    /// Get the current idle start time.
-    pub fn idle_start_time(&self) -> Option<DateTime<Utc>> {
-        self.idle_start_time
-    }
+    Ok(frames)
 }
 
 impl Drop for JsonlLogger {
@@ -0,0 +1,606 @@
+//! Frame and session event logging with pluggable storage backends.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tracing::{info, warn};
+
+use super::jsonl::JsonlLogger;
+use super::sqlite::SqliteLogger;
+use super::summary::{SessionStats, SessionSummary};
+use crate::capture::{CaptureReason, CapturedFrame, ColorConversion, MonitorFailure, MonitorInfo};
+use crate::config::{CaptureRegion, LogFormat, LoggingConfig};
+use crate::storage::{MirrorResult, Retention, UploadResult};
+
+/// Log entry for a captured frame.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FrameLogEntry {
+    /// Capture timestamp.
+    pub timestamp: DateTime<Utc>,
+    /// Unique frame identifier.
+    pub frame_id: String,
+    /// S3 key where the frame was uploaded.
+    pub s3_key: String,
+    /// S3 bucket name.
+    pub s3_bucket: String,
+    /// Frame width in pixels.
+    pub width: u32,
+    /// Frame height in pixels.
+    pub height: u32,
+    /// Monitor ID that was captured.
+    pub monitor_id: u32,
+    /// File size in bytes.
+    pub file_size_bytes: usize,
+    /// Time to capture the frame in milliseconds.
+    pub capture_duration_ms: u64,
+    /// Time to upload the frame in milliseconds.
+    pub upload_duration_ms: u64,
+    /// Seconds idle before this capture (0 if not idle).
+    pub idle_seconds_before: u64,
+    /// Capture interval in effect for this frame (adaptive mode only).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval_ms: Option<u64>,
+    /// Hex SHA-256 of the encoded frame, before any compression or encryption
+    /// (when `log_frame_hash` is enabled).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+    /// Set when the frame was captured but not uploaded due to sampling.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub sampled_out: bool,
+    /// Machine that captured the frame.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_id: Option<String>,
+    /// Daemon run that captured the frame.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// Key of the frame's thumbnail, when thumbnails are enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnail_key: Option<String>,
+    /// Set when the frame was captured on demand rather than on schedule.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub manual: bool,
+    /// Why the frame was captured between scheduled ticks, e.g. `app_switch`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<CaptureReason>,
+    /// Whether audio output was in use, when `capture.audio_activity` is on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_active: Option<bool>,
+    /// Keyboard and mouse events since the previous capture, when
+    /// `idle.count_input` is on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_events: Option<u64>,
+    /// Encoded size before Huffman optimization, when `capture.optimize_jpeg` is on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unoptimized_bytes: Option<usize>,
+    /// Area captured around the cursor, in follow-cursor mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<CaptureRegion>,
+    /// Pixels per point of the captured display (e.g. 2.0 on Retina).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scale_factor: Option<f64>,
+    /// Source and target color space, when frames are normalized to sRGB.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color_space: Option<ColorConversion>,
+    /// Where else the frame was stored, and whether each mirror succeeded.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<MirrorResult>,
+    /// Object Lock retention applied to the uploaded object.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retention: Option<Retention>,
+    /// Stored locally in an archive that hasn't been uploaded yet.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub upload_pending: bool,
+}
+
+/// Session event types for JSONL logging.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event")]
+pub enum SessionEvent {
+    #[serde(rename = "session_start")]
+    SessionStart {
+        timestamp: DateTime<Utc>,
+        version: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        session_id: Option<String>,
+    },
+    #[serde(rename = "session_end")]
+    SessionEnd {
+        timestamp: DateTime<Utc>,
+        frames_captured: u64,
+        #[serde(default)]
+        frames_sampled_out: u64,
+        #[serde(default)]
+        bytes_uploaded: u64,
+    },
+    #[serde(rename = "idle_start")]
+    IdleStart {
+        timestamp: DateTime<Utc>,
+        idle_after_seconds: u64,
+    },
+    #[serde(rename = "idle_end")]
+    IdleEnd {
+        timestamp: DateTime<Utc>,
+        idle_duration_seconds: u64,
+    },
+    #[serde(rename = "low_disk")]
+    LowDisk {
+        timestamp: DateTime<Utc>,
+        free_bytes: u64,
+        min_free_bytes: u64,
+    },
+    #[serde(rename = "heartbeat")]
+    Heartbeat {
+        timestamp: DateTime<Utc>,
+        idle: bool,
+        paused: bool,
+        frames_captured: u64,
+        queued: usize,
+    },
+    #[serde(rename = "data_cleanup")]
+    DataCleanup {
+        timestamp: DateTime<Utc>,
+        files_removed: u64,
+        bytes_removed: u64,
+    },
+    #[serde(rename = "schedule_resumed")]
+    ScheduleResumed {
+        timestamp: DateTime<Utc>,
+        paused_seconds: u64,
+    },
+    #[serde(rename = "frame_skipped_app")]
+    FrameSkippedApp {
+        timestamp: DateTime<Utc>,
+        /// Frontmost app at the time, if it could be determined.
+        app: Option<String>,
+    },
+    #[serde(rename = "fullscreen_start")]
+    FullscreenStart {
+        timestamp: DateTime<Utc>,
+        /// App that went full screen.
+        app: String,
+    },
+    #[serde(rename = "fullscreen_end")]
+    FullscreenEnd {
+        timestamp: DateTime<Utc>,
+        fullscreen_seconds: u64,
+    },
+    #[serde(rename = "monitors_unchanged")]
+    MonitorsUnchanged {
+        timestamp: DateTime<Utc>,
+        /// Monitors whose frames were uploaded this tick.
+        uploaded: Vec<u32>,
+        /// Monitors skipped because their content hadn't changed.
+        unchanged: Vec<u32>,
+    },
+    #[serde(rename = "frame_dropped")]
+    FrameDropped {
+        timestamp: DateTime<Utc>,
+        frame_id: String,
+        monitor_id: u32,
+        reason: String,
+    },
+    #[serde(rename = "display_changed")]
+    DisplayChanged {
+        timestamp: DateTime<Utc>,
+        monitors: Vec<MonitorInfo>,
+    },
+    #[serde(rename = "display_wait_start")]
+    DisplayWaitStart {
+        timestamp: DateTime<Utc>,
+    },
+    #[serde(rename = "display_wait_end")]
+    DisplayWaitEnd {
+        timestamp: DateTime<Utc>,
+        waited_seconds: u64,
+    },
+    #[serde(rename = "capture_restart")]
+    CaptureRestart {
+        timestamp: DateTime<Utc>,
+        reason: String,
+        consecutive_failures: u32,
+    },
+    /// One monitor failed while capturing all of them.
+    #[serde(rename = "monitor_capture_failed")]
+    MonitorCaptureFailed {
+        timestamp: DateTime<Utc>,
+        monitor_id: u32,
+        error: String,
+        /// Whether the capture was retried before giving up.
+        retried: bool,
+    },
+    /// A capture tick produced no frames at all.
+    #[serde(rename = "capture_failed")]
+    CaptureFailed {
+        timestamp: DateTime<Utc>,
+        error: String,
+        consecutive_failures: u32,
+    },
+}
+
+/// Read logged frames captured at or after `since`, oldest first.
+/// Frames that were sampled out (never uploaded) are skipped.
+pub fn read_frames_since(config: &LoggingConfig, since: DateTime<Utc>) -> Result<Vec<FrameLogEntry>> {
+    let mut frames = match config.format {
+        LogFormat::Jsonl => super::jsonl::read_frames(&config.logs_dir(), since)?,
+        LogFormat::Sqlite => super::sqlite::read_frames(&config.sqlite_path(), since)?,
+    };
+    frames.retain(|f| !f.sampled_out);
+    frames.sort_by_key(|f| f.timestamp);
+    Ok(frames)
+}
+
+/// Storage backend for log entries.
+pub trait LogSink {
+    /// Write a frame entry.
+    fn write_frame(&mut self, entry: &FrameLogEntry) -> Result<()>;
+    /// Write a session event.
+    fn write_event(&mut self, event: &SessionEvent) -> Result<()>;
+}
+
+/// Logger for frame metadata and session events.
+pub struct EventLogger {
+    sink: Box<dyn LogSink>,
+    idle_start_time: Option<DateTime<Utc>>,
+    hash_frames: bool,
+    device_id: Option<String>,
+    session_id: Option<String>,
+    /// Totals for the session summary written at shutdown.
+    stats: SessionStats,
+    sessions_dir: PathBuf,
+}
+
+impl EventLogger {
+    /// Create a new logger using the configured backend.
+    pub fn new(config: &LoggingConfig) -> Result<Self> {
+        let sink: Box<dyn LogSink> = match config.format {
+            LogFormat::Jsonl => Box::new(JsonlLogger::new(config.logs_dir())?),
+            LogFormat::Sqlite => Box::new(SqliteLogger::new(&config.sqlite_path())?),
+        };
+
+        Ok(Self {
+            sink,
+            idle_start_time: None,
+            hash_frames: config.log_frame_hash,
+            device_id: None,
+            session_id: None,
+            stats: SessionStats::default(),
+            sessions_dir: config.sessions_dir(),
+        })
+    }
+
+    /// Record this device ID on every frame entry.
+    pub fn with_device_id(mut self, device_id: Option<String>) -> Self {
+        self.device_id = device_id;
+        self
+    }
+
+    /// Record each frame's content hash even if `log_frame_hash` is off,
+    /// e.g. when frames are stored under their hash.
+    pub fn with_frame_hashes(mut self, enabled: bool) -> Self {
+        self.hash_frames |= enabled;
+        self
+    }
+
+    /// Record this session ID on every frame entry and the session start.
+    pub fn with_session_id(mut self, session_id: Option<String>) -> Self {
+        self.session_id = session_id;
+        self
+    }
+
+    /// Log a captured frame.
+    pub fn log_frame(
+        &mut self,
+        frame: &CapturedFrame,
+        upload: &UploadResult,
+        s3_bucket: &str,
+        idle_seconds_before: u64,
+        interval_ms: Option<u64>,
+    ) -> Result<()> {
+        let entry = FrameLogEntry {
+            s3_key: upload.key.clone(),
+            s3_bucket: s3_bucket.to_string(),
+            upload_duration_ms: upload.upload_duration_ms,
+            idle_seconds_before,
+            thumbnail_key: upload.thumbnail_key.clone(),
+            mirrors: upload.mirrors.clone(),
+            retention: upload.retention,
+            upload_pending: upload.pending,
+            ..self.frame_entry(frame, interval_ms)
+        };
+        // An archived frame's upload time is only the local append
+        let upload_ms = (!upload.pending).then_some(upload.upload_duration_ms);
+        self.stats.record_frame(frame.monitor_id, frame.capture_duration_ms, upload_ms);
+
+        self.sink.write_frame(&entry)
+    }
+
+    /// Log a captured frame that was sampled out and not uploaded.
+    pub fn log_sampled_out(&mut self, frame: &CapturedFrame, interval_ms: Option<u64>) -> Result<()> {
+        let entry = FrameLogEntry {
+            sampled_out: true,
+            ..self.frame_entry(frame, interval_ms)
+        };
+
+        self.sink.write_frame(&entry)
+    }
+
+    /// Build a log entry with the frame's own fields and no upload details.
+    fn frame_entry(&self, frame: &CapturedFrame, interval_ms: Option<u64>) -> FrameLogEntry {
+        FrameLogEntry {
+            timestamp: frame.timestamp,
+            frame_id: frame.frame_id(),
+            s3_key: String::new(),
+            s3_bucket: String::new(),
+            width: frame.width,
+            height: frame.height,
+            monitor_id: frame.monitor_id,
+            file_size_bytes: frame.data.len(),
+            capture_duration_ms: frame.capture_duration_ms,
+            upload_duration_ms: 0,
+            idle_seconds_before: 0,
+            interval_ms,
+            content_hash: self.hash_frames.then(|| frame.content_hash()),
+            sampled_out: false,
+            device_id: self.device_id.clone(),
+            session_id: self.session_id.clone(),
+            thumbnail_key: None,
+            manual: frame.manual,
+            reason: frame.reason,
+            audio_active: frame.audio_active,
+            input_events: frame.input_events,
+            unoptimized_bytes: frame.unoptimized_size,
+            region: frame.region,
+            scale_factor: frame.scale_factor,
+            color_space: frame.color_space.clone(),
+            mirrors: Vec::new(),
+            retention: None,
+            upload_pending: false,
+        }
+    }
+
+    /// Log session start event.
+    pub fn log_session_start(&mut self, version: &str) -> Result<()> {
+        self.stats.start(version);
+        let event = SessionEvent::SessionStart {
+            timestamp: Utc::now(),
+            version: version.to_string(),
+            session_id: self.session_id.clone(),
+        };
+        info!("Session {} started", self.session_id.as_deref().unwrap_or("-"));
+        self.sink.write_event(&event)
+    }
+
+    /// Count a failed upload for the session summary.
+    pub fn record_upload_failure(&mut self) {
+        self.stats.record_upload_failure();
+    }
+
+    /// Log session end event and write the session summary to
+    /// `sessions/<session_id>.json` in the data directory.
+    pub fn log_session_end(
+        &mut self,
+        frames_captured: u64,
+        frames_sampled_out: u64,
+        bytes_uploaded: u64,
+    ) -> Result<SessionSummary> {
+        // An idle period still running at shutdown counts too
+        if let Some(start) = self.idle_start_time.take() {
+            self.stats.record_idle((Utc::now() - start).num_seconds().max(0) as u64);
+        }
+
+        let event = SessionEvent::SessionEnd {
+            timestamp: Utc::now(),
+            frames_captured,
+            frames_sampled_out,
+            bytes_uploaded,
+        };
+        info!(
+            "Session ended, {} frames captured ({} sampled out), {} bytes uploaded",
+            frames_captured, frames_sampled_out, bytes_uploaded
+        );
+        self.sink.write_event(&event)?;
+
+        let summary = self.stats.summarize(
+            self.session_id.clone(),
+            self.device_id.clone(),
+            frames_captured,
+            frames_sampled_out,
+            bytes_uploaded,
+        );
+        if let Err(e) = self.write_summary(&summary) {
+            warn!("Failed to write session summary: {:#}", e);
+        }
+        Ok(summary)
+    }
+
+    /// Save the summary as pretty-printed JSON in the sessions directory.
+    fn write_summary(&self, summary: &SessionSummary) -> Result<()> {
+        std::fs::create_dir_all(&self.sessions_dir)?;
+        let name = format!("{}.json", self.session_id.as_deref().unwrap_or("session"));
+        std::fs::write(self.sessions_dir.join(name), serde_json::to_vec_pretty(summary)?)?;
+        Ok(())
+    }
+
+    /// Log idle start event.
+    pub fn log_idle_start(&mut self, idle_after_seconds: u64) -> Result<()> {
+        self.idle_start_time = Some(Utc::now());
+        let event = SessionEvent::IdleStart {
+            timestamp: Utc::now(),
+            idle_after_seconds,
+        };
+        self.sink.write_event(&event)
+    }
+
+    /// Log idle end event.
+    pub fn log_idle_end(&mut self) -> Result<()> {
+        let idle_duration = self
+            .idle_start_time
+            .map(|start| (Utc::now() - start).num_seconds().max(0) as u64)
+            .unwrap_or(0);
+
+        self.idle_start_time = None;
+        self.stats.record_idle(idle_duration);
+
+        let event = SessionEvent::IdleEnd {
+            timestamp: Utc::now(),
+            idle_duration_seconds: idle_duration,
+        };
+        self.sink.write_event(&event)
+    }
+
+    /// Log low disk space event.
+    pub fn log_low_disk(&mut self, free_bytes: u64, min_free_bytes: u64) -> Result<()> {
+        let event = SessionEvent::LowDisk {
+            timestamp: Utc::now(),
+            free_bytes,
+            min_free_bytes,
+        };
+        self.sink.write_event(&event)
+    }
+
+    /// Log a periodic liveness event.
+    pub fn log_heartbeat(
+        &mut self,
+        idle: bool,
+        paused: bool,
+        frames_captured: u64,
+        queued: usize,
+    ) -> Result<()> {
+        let event = SessionEvent::Heartbeat {
+            timestamp: Utc::now(),
+            idle,
+            paused,
+            frames_captured,
+            queued,
+        };
+        self.sink.write_event(&event)
+    }
+
+    /// Log expired local files removed by retention cleanup.
+    pub fn log_data_cleanup(&mut self, files_removed: u64, bytes_removed: u64) -> Result<()> {
+        let event = SessionEvent::DataCleanup {
+            timestamp: Utc::now(),
+            files_removed,
+            bytes_removed,
+        };
+        self.sink.write_event(&event)
+    }
+
+    /// Log capture resuming automatically after a timed pause.
+    pub fn log_schedule_resumed(&mut self, paused_seconds: u64) -> Result<()> {
+        let event = SessionEvent::ScheduleResumed {
+            timestamp: Utc::now(),
+            paused_seconds,
+        };
+        self.sink.write_event(&event)
+    }
+
+    /// Log a capture skipped because the frontmost app isn't allowlisted.
+    pub fn log_frame_skipped_app(&mut self, app: Option<String>) -> Result<()> {
+        let event = SessionEvent::FrameSkippedApp {
+            timestamp: Utc::now(),
+            app,
+        };
+        self.sink.write_event(&event)
+    }
+
+    /// Log that captures paused because an app went full screen.
+    pub fn log_fullscreen_start(&mut self, app: String) -> Result<()> {
+        let event = SessionEvent::FullscreenStart {
+            timestamp: Utc::now(),
+            app,
+        };
+        self.sink.write_event(&event)
+    }
+
+    /// Log that no app is full screen any more and captures resumed.
+    pub fn log_fullscreen_end(&mut self, fullscreen_seconds: u64) -> Result<()> {
+        let event = SessionEvent::FullscreenEnd {
+            timestamp: Utc::now(),
+            fullscreen_seconds,
+        };
+        self.sink.write_event(&event)
+    }
+
+    /// Log which monitors were uploaded and which skipped as unchanged.
+    pub fn log_monitors_unchanged(&mut self, uploaded: Vec<u32>, unchanged: Vec<u32>) -> Result<()> {
+        let event = SessionEvent::MonitorsUnchanged {
+            timestamp: Utc::now(),
+            uploaded,
+            unchanged,
+        };
+        self.sink.write_event(&event)
+    }
+
+    /// Log a captured frame that was discarded without being uploaded.
+    pub fn log_frame_dropped(&mut self, frame: &CapturedFrame, reason: &str) -> Result<()> {
+        let event = SessionEvent::FrameDropped {
+            timestamp: Utc::now(),
+            frame_id: frame.frame_id(),
+            monitor_id: frame.monitor_id,
+            reason: reason.to_string(),
+        };
+        self.sink.write_event(&event)
+    }
+
+    /// Log display configuration change event.
+    pub fn log_display_changed(&mut self, monitors: Vec<MonitorInfo>) -> Result<()> {
+        let event = SessionEvent::DisplayChanged {
+            timestamp: Utc::now(),
+            monitors,
+        };
+        self.sink.write_event(&event)
+    }
+
+    /// Log that capture paused because no display is attached.
+    pub fn log_display_wait_start(&mut self) -> Result<()> {
+        let event = SessionEvent::DisplayWaitStart { timestamp: Utc::now() };
+        self.sink.write_event(&event)
+    }
+
+    /// Log that a display appeared and capture resumed.
+    pub fn log_display_wait_end(&mut self, waited_seconds: u64) -> Result<()> {
+        let event = SessionEvent::DisplayWaitEnd {
+            timestamp: Utc::now(),
+            waited_seconds,
+        };
+        self.sink.write_event(&event)
+    }
+
+    /// Log a watchdog restart of the capture subsystem.
+    pub fn log_capture_restart(&mut self, reason: &str, consecutive_failures: u32) -> Result<()> {
+        let event = SessionEvent::CaptureRestart {
+            timestamp: Utc::now(),
+            reason: reason.to_string(),
+            consecutive_failures,
+        };
+        self.sink.write_event(&event)
+    }
+
+    /// Log one monitor failing during an all-monitors capture.
+    pub fn log_monitor_capture_failed(&mut self, failure: &MonitorFailure) -> Result<()> {
+        let event = SessionEvent::MonitorCaptureFailed {
+            timestamp: Utc::now(),
+            monitor_id: failure.monitor_id,
+            error: failure.error.clone(),
+            retried: failure.retried,
+        };
+        self.sink.write_event(&event)
+    }
+
+    /// Log a capture tick that failed as a whole.
+    pub fn log_capture_failed(&mut self, error: &str, consecutive_failures: u32) -> Result<()> {
+        let event = SessionEvent::CaptureFailed {
+            timestamp: Utc::now(),
+            error: error.to_string(),
+            consecutive_failures,
+        };
+        self.sink.write_event(&event)
+    }
+
+    /// Get the current idle start time.
+    pub fn idle_start_time(&self) -> Option<DateTime<Utc>> {
+        self.idle_start_time
+    }
+}
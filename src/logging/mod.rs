@@ -1,6 +1,10 @@
-//! JSONL logging module for frame metadata.
+//! Logging module for frame metadata (JSONL or SQLite).
 
 mod jsonl;
+mod logger;
+mod retention;
+mod sqlite;
+mod summary;
 
-pub use jsonl::JsonlLogger;
-
+pub use logger::{read_frames_since, EventLogger};
+pub use retention::clean_data_dir;
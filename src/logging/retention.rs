@@ -0,0 +1,116 @@
+//! Deletes old daily logs and leftover staging files from the data directory.
+
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use tracing::{debug, warn};
+
+use crate::config::LoggingConfig;
+
+/// Extension of archives still being written, which are never deleted.
+const OPEN_ARCHIVE_EXTENSION: &str = "part";
+
+/// Extension of finished archives, which only stay in staging until uploaded.
+const DONE_ARCHIVE_EXTENSION: &str = "tar";
+
+/// Files and bytes removed by a cleanup pass.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Reclaimed {
+    pub files: u64,
+    pub bytes: u64,
+}
+
+/// Delete daily JSONL logs, session summaries, and staging files last
+/// modified more than `max_age` ago. Files still being written (today's log,
+/// open archives) are kept, as is the SQLite log, which holds every day in
+/// one file.
+pub fn clean_data_dir(logging: &LoggingConfig, max_age: Duration) -> Reclaimed {
+    let mut reclaimed = Reclaimed::default();
+    let Some(cutoff) = SystemTime::now().checked_sub(max_age) else {
+        return reclaimed;
+    };
+
+    let today = format!("{}.jsonl", chrono::Local::now().format("%Y-%m-%d"));
+    remove_old(&logging.logs_dir(), cutoff, false, &mut reclaimed, &|path| {
+        path.extension().is_some_and(|e| e == "jsonl") && !path.ends_with(&today)
+    });
+    remove_old(&logging.staging_dir(), cutoff, true, &mut reclaimed, &|path| {
+        path.extension().is_none_or(|e| e != OPEN_ARCHIVE_EXTENSION)
+    });
+    remove_old(&logging.sessions_dir(), cutoff, false, &mut reclaimed, &|path| {
+        path.extension().is_some_and(|e| e == "json")
+    });
+    reclaimed
+}
+
+/// Remove files under `dir` older than `cutoff` that `eligible` accepts,
+/// descending into subdirectories if `recursive`.
+fn remove_old(
+    dir: &Path,
+    cutoff: SystemTime,
+    recursive: bool,
+    reclaimed: &mut Reclaimed,
+    eligible: &dyn Fn(&Path) -> bool,
+) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(meta) = entry.metadata() else { continue };
+        if meta.is_dir() {
+            if recursive {
+                remove_old(&path, cutoff, recursive, reclaimed, eligible);
+            }
+            continue;
+        }
+        if !eligible(&path) || meta.modified().map_or(true, |m| m >= cutoff) {
+            continue;
+        }
+        match std::fs::remove_file(&path) {
+            Ok(()) => {
+                // Its frames are logged but now stored nowhere
+                if path.extension().is_some_and(|e| e == DONE_ARCHIVE_EXTENSION) {
+                    warn!("Removed expired archive {:?} that was never uploaded", path);
+                } else {
+                    debug!("Removed expired file {:?}", path);
+                }
+                reclaimed.files += 1;
+                reclaimed.bytes += meta.len();
+            }
+            Err(e) => warn!("Failed to remove expired file {:?}: {}", path, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_old(path: &Path) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let file = std::fs::File::create(path).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(2 * 60 * 60)).unwrap();
+    }
+
+    #[test]
+    fn removes_expired_files_but_keeps_open_archives() {
+        let dir = tempfile::tempdir().unwrap();
+        let logging = LoggingConfig {
+            data_dir: dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        let archives = logging.staging_dir().join("archives");
+        write_old(&logging.logs_dir().join("2020-01-01.jsonl"));
+        write_old(&archives.join("unuploaded.tar"));
+        write_old(&archives.join("current.tar.part"));
+        write_old(&logging.sessions_dir().join("notes.txt"));
+
+        let reclaimed = clean_data_dir(&logging, Duration::from_secs(60 * 60));
+
+        assert_eq!(reclaimed.files, 2);
+        assert!(!logging.logs_dir().join("2020-01-01.jsonl").exists());
+        assert!(!archives.join("unuploaded.tar").exists());
+        assert!(archives.join("current.tar.part").exists());
+        assert!(logging.sessions_dir().join("notes.txt").exists());
+    }
+}
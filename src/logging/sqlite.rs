@@ -0,0 +1,168 @@
+//! SQLite metadata writer for captured frames.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection};
+use std::path::Path;
+use tracing::debug;
+
+use super::logger::{FrameLogEntry, LogSink, SessionEvent};
+
+/// Schema for frame and event tables. The full entry is kept as JSON in `data`
+/// so fields added later remain queryable via `json_extract`.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS frames (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    timestamp TEXT NOT NULL,
+    frame_id TEXT NOT NULL,
+    s3_key TEXT NOT NULL,
+    s3_bucket TEXT NOT NULL,
+    monitor_id INTEGER NOT NULL,
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    file_size_bytes INTEGER NOT NULL,
+    data TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_frames_timestamp ON frames (timestamp);
+CREATE INDEX IF NOT EXISTS idx_frames_monitor_id ON frames (monitor_id);
+CREATE INDEX IF NOT EXISTS idx_frames_s3_key ON frames (s3_key);
+
+CREATE TABLE IF NOT EXISTS events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    timestamp TEXT NOT NULL,
+    event TEXT NOT NULL,
+    data TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_events_timestamp ON events (timestamp);
+";
+
+/// SQLite logger for frame metadata.
+pub struct SqliteLogger {
+    conn: Connection,
+}
+
+impl SqliteLogger {
+    /// Open (or create) the SQLite database at the given path.
+    pub fn new(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create logs directory: {:?}", parent))?;
+        }
+
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to open SQLite log: {:?}", path))?;
+        conn.execute_batch(SCHEMA)
+            .context("Failed to initialize SQLite log schema")?;
+
+        debug!("Opened SQLite log: {:?}", path);
+
+        Ok(Self { conn })
+    }
+}
+
+/// Read frame entries captured at or after `since` from the SQLite log.
+pub fn read_frames(path: &Path, since: DateTime<Utc>) -> Result<Vec<FrameLogEntry>> {
+    let conn = Connection::open(path)
+        .with_context(|| format!("Failed to open SQLite log: {:?}", path))?;
+    // UTC RFC 3339 strings compare as text even when their fractions differ
+    // in length, since the `+` after the seconds sorts before any digit
+    let mut stmt = conn.prepare("SELECT data FROM frames WHERE timestamp >= ?1 ORDER BY timestamp")?;
+
+    let rows = stmt.query_map(params![since.to_rfc3339()], |row| row.get::<_, String>(0))?;
+    let mut frames = Vec::new();
+    for data in rows {
+        frames.push(serde_json::from_str(&data?)?);
+    }
+
+    Ok(frames)
+}
+
+impl LogSink for SqliteLogger {
+    fn write_frame(&mut self, entry: &FrameLogEntry) -> Result<()> {
+        let data = serde_json::to_string(entry)?;
+        self.conn.execute(
+            "INSERT INTO frames (timestamp, frame_id, s3_key, s3_bucket, monitor_id, width, height, file_size_bytes, data)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                entry.timestamp.to_rfc3339(),
+                entry.frame_id,
+                entry.s3_key,
+                entry.s3_bucket,
+                entry.monitor_id,
+                entry.width,
+                entry.height,
+                entry.file_size_bytes as i64,
+                data,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn write_event(&mut self, event: &SessionEvent) -> Result<()> {
+        let value = serde_json::to_value(event)?;
+        let name = value["event"].as_str().unwrap_or_default().to_string();
+        let timestamp = value["timestamp"].as_str().unwrap_or_default().to_string();
+
+        self.conn.execute(
+            "INSERT INTO events (timestamp, event, data) VALUES (?1, ?2, ?3)",
+            params![timestamp, name, value.to_string()],
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::CapturedFrame;
+    use crate::config::{LogFormat, LoggingConfig};
+    use crate::logging::{read_frames_since, EventLogger};
+    use crate::storage::UploadResult;
+    use chrono::Duration;
+
+    fn sqlite_config(dir: &Path) -> LoggingConfig {
+        LoggingConfig {
+            data_dir: dir.to_path_buf(),
+            format: LogFormat::Sqlite,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn frames_are_read_back_from_a_sub_second_boundary() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = sqlite_config(dir.path());
+        let mut logger = EventLogger::new(&config).unwrap();
+
+        // Timestamps format with no, three, or six fractional digits
+        let start = DateTime::parse_from_rfc3339("2026-03-01T12:00:00Z").unwrap().with_timezone(&Utc);
+        let offsets_us = [0, 250_000, 499_999, 500_000, 500_001, 1_000_000];
+        for (i, us) in offsets_us.into_iter().enumerate() {
+            let mut frame = CapturedFrame::for_test(vec![i as u8]);
+            frame.timestamp = start + Duration::microseconds(us);
+            logger.log_frame(&frame, &UploadResult::stored(&format!("frame-{}", i)), "bucket", 0, None).unwrap();
+        }
+
+        let keys = |since| -> Vec<String> {
+            read_frames_since(&config, since).unwrap().into_iter().map(|f| f.s3_key).collect()
+        };
+        assert_eq!(keys(start + Duration::milliseconds(500)), ["frame-3", "frame-4", "frame-5"]);
+        assert_eq!(keys(start + Duration::microseconds(500_001)), ["frame-4", "frame-5"]);
+        assert_eq!(keys(start + Duration::seconds(1)), ["frame-5"]);
+        assert_eq!(keys(start).len(), offsets_us.len());
+    }
+
+    #[test]
+    fn schema_creates_the_lookup_indexes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log.sqlite3");
+        let logger = SqliteLogger::new(&path).unwrap();
+
+        let mut stmt = logger
+            .conn
+            .prepare("SELECT name FROM sqlite_master WHERE type = 'index' AND tbl_name = 'frames' ORDER BY name")
+            .unwrap();
+        let indexes: Vec<String> = stmt.query_map([], |row| row.get(0)).unwrap().map(|r| r.unwrap()).collect();
+        assert_eq!(indexes, ["idx_frames_monitor_id", "idx_frames_s3_key", "idx_frames_timestamp"]);
+    }
+}
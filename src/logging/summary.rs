@@ -0,0 +1,213 @@
+//! Per-session totals, written as one JSON report when the daemon exits.
+
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+/// Most durations kept per session for percentiles, so a long session's
+/// memory stays bounded.
+const RESERVOIR_SIZE: usize = 4096;
+
+/// Distribution of a set of durations.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct DurationStats {
+    pub avg_ms: u64,
+    pub p50_ms: u64,
+    pub p95_ms: u64,
+    pub max_ms: u64,
+}
+
+impl DurationStats {
+    /// Summarize durations in milliseconds, or None if there are none.
+    fn from_samples(samples: &[u64]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let mut sorted = samples.to_vec();
+        sorted.sort_unstable();
+        // Nearest-rank percentile
+        let percentile = |p: usize| sorted[(sorted.len() * p).div_ceil(100).saturating_sub(1)];
+        Some(Self {
+            avg_ms: sorted.iter().sum::<u64>() / sorted.len() as u64,
+            p50_ms: percentile(50),
+            p95_ms: percentile(95),
+            max_ms: sorted[sorted.len() - 1],
+        })
+    }
+}
+
+/// Durations recorded over a session: exact count, total and maximum, and a
+/// uniform random sample of at most RESERVOIR_SIZE of them for percentiles.
+#[derive(Debug, Default)]
+struct DurationSamples {
+    count: u64,
+    total_ms: u64,
+    max_ms: u64,
+    reservoir: Vec<u64>,
+}
+
+impl DurationSamples {
+    fn record(&mut self, ms: u64) {
+        self.count += 1;
+        self.total_ms = self.total_ms.saturating_add(ms);
+        self.max_ms = self.max_ms.max(ms);
+        if self.reservoir.len() < RESERVOIR_SIZE {
+            self.reservoir.push(ms);
+            return;
+        }
+        // Reservoir sampling: the n-th duration replaces a kept one with probability RESERVOIR_SIZE / n
+        let slot = rand::thread_rng().gen_range(0..self.count);
+        if let Some(kept) = usize::try_from(slot).ok().and_then(|i| self.reservoir.get_mut(i)) {
+            *kept = ms;
+        }
+    }
+
+    /// Summarize, with percentiles estimated from the sample once it's full.
+    fn stats(&self) -> Option<DurationStats> {
+        DurationStats::from_samples(&self.reservoir).map(|stats| DurationStats {
+            avg_ms: self.total_ms / self.count,
+            max_ms: self.max_ms,
+            ..stats
+        })
+    }
+}
+
+/// At-a-glance report of one daemon run.
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionSummary {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device_id: Option<String>,
+    pub version: String,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    pub duration_seconds: u64,
+    pub frames_captured: u64,
+    pub frames_sampled_out: u64,
+    pub bytes_uploaded: u64,
+    pub upload_failures: u64,
+    pub idle_seconds: u64,
+    /// Monitors that had at least one frame stored.
+    pub monitors: Vec<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capture_duration: Option<DurationStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upload_duration: Option<DurationStats>,
+}
+
+/// Running totals for the current session.
+#[derive(Debug)]
+pub struct SessionStats {
+    version: String,
+    started_at: DateTime<Utc>,
+    capture_ms: DurationSamples,
+    upload_ms: DurationSamples,
+    upload_failures: u64,
+    idle_seconds: u64,
+    monitors: BTreeSet<u32>,
+}
+
+impl Default for SessionStats {
+    fn default() -> Self {
+        Self {
+            version: String::new(),
+            started_at: Utc::now(),
+            capture_ms: DurationSamples::default(),
+            upload_ms: DurationSamples::default(),
+            upload_failures: 0,
+            idle_seconds: 0,
+            monitors: BTreeSet::new(),
+        }
+    }
+}
+
+impl SessionStats {
+    /// Restart the totals for a session starting now.
+    pub fn start(&mut self, version: &str) {
+        *self = Self {
+            version: version.to_string(),
+            ..Self::default()
+        };
+    }
+
+    /// Count a stored frame, without an upload time if it is still pending.
+    pub fn record_frame(&mut self, monitor_id: u32, capture_ms: u64, upload_ms: Option<u64>) {
+        self.capture_ms.record(capture_ms);
+        if let Some(upload_ms) = upload_ms {
+            self.upload_ms.record(upload_ms);
+        }
+        self.monitors.insert(monitor_id);
+    }
+
+    /// Count a failed upload attempt.
+    pub fn record_upload_failure(&mut self) {
+        self.upload_failures += 1;
+    }
+
+    /// Add a finished idle period.
+    pub fn record_idle(&mut self, seconds: u64) {
+        self.idle_seconds += seconds;
+    }
+
+    /// Build the report from these totals and the capture loop's counts.
+    pub fn summarize(
+        &self,
+        session_id: Option<String>,
+        device_id: Option<String>,
+        frames_captured: u64,
+        frames_sampled_out: u64,
+        bytes_uploaded: u64,
+    ) -> SessionSummary {
+        let ended_at = Utc::now();
+        SessionSummary {
+            session_id,
+            device_id,
+            version: self.version.clone(),
+            started_at: self.started_at,
+            ended_at,
+            duration_seconds: (ended_at - self.started_at).num_seconds().max(0) as u64,
+            frames_captured,
+            frames_sampled_out,
+            bytes_uploaded,
+            upload_failures: self.upload_failures,
+            idle_seconds: self.idle_seconds,
+            monitors: self.monitors.iter().copied().collect(),
+            capture_duration: self.capture_ms.stats(),
+            upload_duration: self.upload_ms.stats(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nearest_rank_percentiles() {
+        let samples: Vec<u64> = (1..=100).rev().collect();
+        let stats = DurationStats::from_samples(&samples).unwrap();
+        assert_eq!((stats.avg_ms, stats.p50_ms, stats.p95_ms, stats.max_ms), (50, 50, 95, 100));
+
+        let stats = DurationStats::from_samples(&[7]).unwrap();
+        assert_eq!((stats.avg_ms, stats.p50_ms, stats.p95_ms, stats.max_ms), (7, 7, 7, 7));
+        assert!(DurationStats::from_samples(&[]).is_none());
+    }
+
+    #[test]
+    fn long_sessions_keep_a_bounded_sample() {
+        let mut samples = DurationSamples::default();
+        for ms in 1..=10 * RESERVOIR_SIZE as u64 {
+            samples.record(ms);
+        }
+        assert_eq!(samples.reservoir.len(), RESERVOIR_SIZE);
+
+        // Average and maximum stay exact; percentiles are estimates
+        let stats = samples.stats().unwrap();
+        let count = 10 * RESERVOIR_SIZE as u64;
+        assert_eq!(stats.avg_ms, (1..=count).sum::<u64>() / count);
+        assert_eq!(stats.max_ms, count);
+        assert!(stats.p50_ms > count / 4 && stats.p50_ms < count * 3 / 4);
+    }
+}
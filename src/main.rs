@@ -4,78 +4,263 @@
 //! detects user inactivity, and uploads to S3-compatible storage.
 //! Includes a menu bar icon for status and control.
 
+mod breaker;
 mod capture;
+mod cli;
 mod config;
+mod control;
+mod crypto;
+mod export;
+mod hotkey;
 mod idle;
 mod logging;
+mod notify;
+mod pidfile;
+mod queue;
+mod session;
+mod state;
 mod storage;
+mod telemetry;
+mod timelapse;
+mod usage;
+mod webhook;
 
-use anyhow::Result;
-use std::path::PathBuf;
+use anyhow::{Context, Result};
+use chrono::Utc;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 use system_status_bar_macos::{Menu, MenuItem, StatusItem};
-use tokio::sync::mpsc;
-use tracing::{error, info, warn};
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tracing::{debug, error, info, info_span, warn, Instrument};
 
-use crate::capture::ScreenCapture;
-use crate::config::Config;
+use crate::breaker::{BreakerState, CircuitBreaker};
+use crate::capture::{
+    AdaptiveInterval, AdaptiveQuality, CaptureBackoff, CaptureReason, CapturedFrame, DisplayWatcher,
+    ForegroundApp, FrameSource, FrameTooLarge, Jitter, MockDisplay, MonitorInfo, ScreenCapture,
+};
+use crate::cli::{Args, Command, ConfigFormat};
+use crate::config::{CaptureConfig, Config, FullscreenPolicy, MIN_INTERVAL_MS};
+use crate::control::ControlServer;
+use crate::crypto::FrameCipher;
 use crate::idle::{ActivityState, IdleDetector};
-use crate::logging::JsonlLogger;
-use crate::storage::S3Uploader;
+use crate::logging::EventLogger;
+use crate::notify::FailureNotifier;
+use crate::pidfile::PidFile;
+use crate::queue::{QueuedFrame, UploadQueue};
+use crate::state::CaptureState;
+use crate::storage::{DiskCheck, DiskGuard, KeyLayout, StorageBackend, UploadError};
+use crate::usage::UsageTracker;
+use crate::webhook::WebhookNotifier;
 
 /// Application version.
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
-/// Commands from menu bar to capture loop
-#[derive(Debug, Clone)]
+/// Delay before the first startup retry; doubles on each attempt.
+const INIT_RETRY_BASE_DELAY: Duration = Duration::from_secs(2);
+
+/// Upper bound on the delay between startup retries.
+const INIT_RETRY_MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Skip the resume capture if a frame was captured this recently.
+const RESUME_CAPTURE_DEBOUNCE: Duration = Duration::from_secs(1);
+
+/// How often expired local logs and staging files are removed.
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Menu bar title while uploads are flowing.
+const TRAY_TITLE: &str = "📷";
+
+/// Menu bar title while the upload circuit breaker is open or probing.
+const TRAY_TITLE_UPLOADS_SUSPENDED: &str = "📷⚠️";
+
+/// Menu bar changes sent from the capture loop, applied on the main thread.
+#[derive(Debug, Clone, Copy)]
+enum TrayUpdate {
+    /// Show this status item title.
+    Title(&'static str),
+    /// Capture was paused or resumed, so the toggle item needs relabeling.
+    CaptureEnabled(bool),
+}
+
+/// Commands from the menu bar and control socket to the capture loop
+#[derive(Debug)]
 enum MenuCommand {
     ToggleCapture,
+    Pause,
+    /// Pause, then resume automatically after the duration.
+    PauseFor(Duration),
+    Resume,
+    /// Capture immediately, regardless of interval, idle state, or pause.
+    CaptureNow,
+    /// A different app came to the front; capture unless paused or skipping it.
+    AppSwitched(ForegroundApp),
+    /// The screen was unlocked; capture unless paused.
+    Unlocked,
+    Status(oneshot::Sender<String>),
     Quit,
 }
 
 fn main() -> Result<()> {
     // Parse command line arguments
-    let config_path = std::env::args()
-        .nth(1)
-        .map(PathBuf::from);
+    let args = Args::parse()?;
+    match args.command {
+        Command::Help => {
+            print!("{}", cli::USAGE);
+            return Ok(());
+        }
+        Command::Version => {
+            println!("preprompter {}", VERSION);
+            return Ok(());
+        }
+        Command::Decrypt { key, input, output } => {
+            return decrypt_file(&key, &input, output.as_deref());
+        }
+        Command::Run
+        | Command::Ctl(_)
+        | Command::Timelapse(_)
+        | Command::Export(_)
+        | Command::Usage(_)
+        | Command::PrintConfig(_) => {}
+    }
 
     // Load configuration
-    let config = Config::load(config_path.as_deref())?;
+    let config = Config::load(args.config_path.as_deref())?;
+
+    // Show what's in effect even if it doesn't validate, then report why
+    if let Command::PrintConfig(format) = args.command {
+        for warning in &config.load_warnings {
+            eprintln!("warning: {}", warning);
+        }
+        let redacted = config.redacted();
+        match format {
+            ConfigFormat::Toml => print!("{}", toml::to_string(&redacted)?),
+            ConfigFormat::Json => println!("{}", serde_json::to_string_pretty(&redacted)?),
+        }
+        return config.validate();
+    }
+
     config.validate()?;
 
+    if let Command::Ctl(command) = &args.command {
+        let reply = control::send_command(&config.logging.control_socket_path(), command)?;
+        println!("{}", reply);
+        if reply.starts_with("error:") {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     // Initialize tracing
-    init_tracing(&config.logging.level)?;
+    init_tracing(&config.logging.level, config.tracing.otlp_endpoint.as_deref())?;
+    for warning in &config.load_warnings {
+        warn!("{}", warning);
+    }
+
+    if let Command::Timelapse(options) = &args.command {
+        let runtime = tokio::runtime::Runtime::new()?;
+        return runtime.block_on(timelapse::run(&config, options));
+    }
+    if let Command::Export(options) = &args.command {
+        let runtime = tokio::runtime::Runtime::new()?;
+        return runtime.block_on(export::run(&config, options));
+    }
+    if let Command::Usage(options) = &args.command {
+        return usage::run(&config, options);
+    }
 
     info!("Starting preprompter v{}", VERSION);
 
+    // Refuse to run alongside another instance using the same data dir
+    let pid_file = PidFile::acquire(&config.logging.pid_path(), args.force)?;
+
+    if args.no_menu {
+        return run_headless(config, pid_file);
+    }
+
     // Channel for menu commands
     let (cmd_tx, cmd_rx) = mpsc::channel::<MenuCommand>(10);
 
-    // Shared state for capture status
-    let capture_enabled = Arc::new(AtomicBool::new(true));
+    // Shared state for capture status, restoring a pause from before a restart
+    let restored = CaptureState::load(&config.logging.state_path());
+    let capture_enabled = Arc::new(AtomicBool::new(restored.capture_enabled));
     let capture_enabled_clone = capture_enabled.clone();
     let running = Arc::new(AtomicBool::new(true));
     let running_clone = running.clone();
 
+    // Menu bar updates from the capture loop, applied on the main thread
+    let (tray_tx, tray_rx) = std::sync::mpsc::channel::<TrayUpdate>();
+
     // Spawn tokio runtime in a separate thread
     let config_clone = config.clone();
+    let cmd_tx_control = cmd_tx.clone();
     let capture_thread = std::thread::spawn(move || {
         let runtime = tokio::runtime::Runtime::new().expect("Failed to create tokio runtime");
-        runtime.block_on(async move {
-            if let Err(e) = run_capture_loop(config_clone, cmd_rx, capture_enabled_clone, running_clone).await {
-                error!("Capture loop error: {}", e);
-            }
+        let result = runtime.block_on(async {
+            let frame_source = create_frame_source(&config_clone).await?;
+            let services = LoopServices::start(&config_clone, cmd_tx_control).await?;
+            run_capture_loop(
+                config_clone,
+                frame_source,
+                services,
+                cmd_rx,
+                capture_enabled_clone,
+                running_clone,
+                restored.resume_at,
+                Some(tray_tx),
+            )
+            .await
         });
+        match result {
+            Ok(()) => {
+                drop(pid_file);
+                telemetry::shutdown();
+                // Exit the process to close the menu bar
+                std::process::exit(0);
+            }
+            Err(e) => error!("Capture loop error: {}", e),
+        }
+    });
+
+    // Create menu bar icon on main thread (required for macOS), reflecting
+    // a pause restored from before the restart
+    let menu = build_menu(&cmd_tx, &capture_enabled, restored.capture_enabled);
+    let status_item = RefCell::new(StatusItem::new(TRAY_TITLE, menu));
+
+    info!("Menu bar initialized - click 📷 to toggle/quit");
+
+    // Run macOS event loop on main thread (required for menu bar)
+    // Menu items handle their own events via callbacks; messages on the
+    // channel are updates from the capture loop
+    // This blocks until the app quits - runs the macOS event loop
+    system_status_bar_macos::sync_infinite_event_loop(tray_rx, move |update| match update {
+        TrayUpdate::Title(title) => status_item.borrow_mut().set_title(title),
+        // Menu items can't be relabeled once added, so swap in a new menu
+        TrayUpdate::CaptureEnabled(enabled) => status_item
+            .borrow_mut()
+            .set_menu(build_menu(&cmd_tx, &capture_enabled, enabled)),
     });
 
-    // Create menu bar icon on main thread (required for macOS)
+    // This is reached when event loop terminates
+    let _ = capture_thread.join();
+
+    info!("Preprompter shutdown complete");
+    Ok(())
+}
+
+/// Build the menu bar menu, with the toggle item offering to pause or
+/// resume depending on whether capture is `enabled`.
+fn build_menu(cmd_tx: &mpsc::Sender<MenuCommand>, capture_enabled: &Arc<AtomicBool>, enabled: bool) -> Menu {
     let cmd_tx_toggle = cmd_tx.clone();
     let cmd_tx_quit = cmd_tx.clone();
     let capture_enabled_menu = capture_enabled.clone();
 
     let toggle_item = MenuItem::new(
-        "Pause Capture",
+        if enabled { "Pause Capture" } else { "Resume Capture" },
         Some(Box::new(move || {
             let is_enabled = capture_enabled_menu.load(Ordering::SeqCst);
             capture_enabled_menu.store(!is_enabled, Ordering::SeqCst);
@@ -84,6 +269,29 @@ fn main() -> Result<()> {
         None,
     );
 
+    let pause_for = |title: &str, duration: Option<Duration>| {
+        let cmd_tx = cmd_tx.clone();
+        let capture_enabled = capture_enabled.clone();
+        MenuItem::new(
+            title,
+            Some(Box::new(move || {
+                capture_enabled.store(false, Ordering::SeqCst);
+                let cmd = duration.map_or(MenuCommand::Pause, MenuCommand::PauseFor);
+                let _ = cmd_tx.blocking_send(cmd);
+            })),
+            None,
+        )
+    };
+    let pause_menu_item = MenuItem::new(
+        "Pause For",
+        None,
+        Some(Menu::new(vec![
+            pause_for("Pause 15m", Some(Duration::from_secs(15 * 60))),
+            pause_for("Pause 1h", Some(Duration::from_secs(60 * 60))),
+            pause_for("Pause until I resume", None),
+        ])),
+    );
+
     let quit_item = MenuItem::new(
         "Quit Preprompter",
         Some(Box::new(move || {
@@ -92,146 +300,571 @@ fn main() -> Result<()> {
         None,
     );
 
-    let menu = Menu::new(vec![toggle_item, quit_item]);
-    let _status_item = StatusItem::new("📷", menu);
+    Menu::new(vec![toggle_item, pause_menu_item, quit_item])
+}
 
-    info!("Menu bar initialized - click 📷 to toggle/quit");
+/// Run the capture loop on the main thread without a menu bar, stopping on
+/// SIGINT/SIGTERM or a `quit` control command.
+fn run_headless(config: Config, pid_file: PidFile) -> Result<()> {
+    info!("Running without menu bar");
 
-    // Run macOS event loop on main thread (required for menu bar)
-    // The sync_infinite_event_loop needs a receiver for event loop messages
-    // But since our menu items handle events via callbacks, we just need a dummy channel
-    let (_event_sender, event_receiver) = std::sync::mpsc::channel::<()>();
+    let (cmd_tx, cmd_rx) = mpsc::channel::<MenuCommand>(10);
+    let restored = CaptureState::load(&config.logging.state_path());
+    let capture_enabled = Arc::new(AtomicBool::new(restored.capture_enabled));
+    let running = Arc::new(AtomicBool::new(true));
 
-    // This blocks until the app quits - runs the macOS event loop
-    system_status_bar_macos::sync_infinite_event_loop(event_receiver, |_| {
-        // No-op callback - menu items handle their own events
+    let runtime = tokio::runtime::Runtime::new()?;
+    let result = runtime.block_on(async {
+        tokio::spawn(forward_shutdown_signals(cmd_tx.clone()));
+        let frame_source = create_frame_source(&config).await?;
+        let services = LoopServices::start(&config, cmd_tx).await?;
+        run_capture_loop(
+            config,
+            frame_source,
+            services,
+            cmd_rx,
+            capture_enabled,
+            running,
+            restored.resume_at,
+            None,
+        )
+        .await
     });
 
-    // This is reached when event loop terminates
-    let _ = capture_thread.join();
-
+    drop(pid_file);
+    telemetry::shutdown();
     info!("Preprompter shutdown complete");
-    Ok(())
+    result
+}
+
+/// Translate SIGINT/SIGTERM into a quit command so the loop shuts down cleanly.
+async fn forward_shutdown_signals(cmd_tx: mpsc::Sender<MenuCommand>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(s) => s,
+        Err(e) => {
+            warn!("Failed to install SIGTERM handler: {}", e);
+            return;
+        }
+    };
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => info!("Interrupt received"),
+        _ = terminate.recv() => info!("Termination signal received"),
+    }
+    let _ = cmd_tx.send(MenuCommand::Quit).await;
+}
+
+/// What the capture loop works with besides its frame source: where frames
+/// go, the event log, and the system listeners that drive it. The real ones
+/// are set up by [`LoopServices::start`]; tests build them around fakes.
+struct LoopServices {
+    storage: Box<dyn StorageBackend>,
+    event_logger: EventLogger,
+    idle_detector: IdleDetector,
+    /// Subscribed before the detector starts, so no transition is missed.
+    activity_rx: broadcast::Receiver<ActivityState>,
+    display_watcher: Option<DisplayWatcher>,
+    /// Kept alive for as long as the loop runs.
+    _control_server: Option<ControlServer>,
+}
+
+impl LoopServices {
+    /// Create the configured storage and event log, and start the idle
+    /// detector, display watcher, control socket, and the hotkey, app switch,
+    /// and unlock listeners, which send their commands on `cmd_tx`.
+    async fn start(config: &Config, cmd_tx: mpsc::Sender<MenuCommand>) -> Result<Self> {
+        // Ensure data directories exist
+        std::fs::create_dir_all(config.logging.logs_dir())?;
+        std::fs::create_dir_all(config.logging.staging_dir())?;
+
+        let display_watcher = match DisplayWatcher::new() {
+            Ok(watcher) => Some(watcher),
+            Err(e) => {
+                warn!("Display change detection unavailable: {}", e);
+                None
+            }
+        };
+
+        let idle_detector = IdleDetector::new(config.idle.threshold())?
+            .with_method(config.idle.method)
+            .with_intervals(config.idle.poll_interval(), config.idle.check_interval());
+        let storage = storage::create_backend(config).await?;
+        let event_logger = EventLogger::new(&config.logging)?
+            .with_device_id(config.device_id())
+            .with_session_id(Some(session::id().to_string()))
+            .with_frame_hashes(config.s3.content_addressed);
+
+        // Global hotkey for manual captures
+        if let Some(hotkey) = &config.capture.hotkey {
+            let cmd_tx = cmd_tx.clone();
+            let registered = hotkey.parse().and_then(|hotkey| {
+                hotkey::listen(hotkey, move || {
+                    let _ = cmd_tx.blocking_send(MenuCommand::CaptureNow);
+                })
+            });
+            if let Err(e) = registered {
+                warn!("Capture hotkey unavailable: {:#}", e);
+            }
+        }
+
+        // Extra captures at each change of foreground app
+        if config.capture.capture_on_app_switch {
+            let cmd_tx = cmd_tx.clone();
+            let watched = capture::watch_app_switches(config.capture.app_switch_debounce(), move |app| {
+                cmd_tx.blocking_send(MenuCommand::AppSwitched(app)).is_ok()
+            });
+            if let Err(e) = watched {
+                warn!("App switch captures unavailable: {:#}", e);
+            }
+        }
+
+        // An extra capture marking the return to the desk
+        if config.idle.capture_on_unlock {
+            let cmd_tx = cmd_tx.clone();
+            if let Err(e) = idle::watch_unlocks(move || cmd_tx.blocking_send(MenuCommand::Unlocked).is_ok()) {
+                warn!("Unlock captures unavailable: {:#}", e);
+            }
+        }
+
+        // Control socket for scriptable pause/resume/capture/status/quit
+        let control_server = match ControlServer::start(&config.logging.control_socket_path(), cmd_tx) {
+            Ok(server) => Some(server),
+            Err(e) => {
+                warn!("Control socket unavailable: {}", e);
+                None
+            }
+        };
+
+        // Start idle detection
+        let activity_rx = idle_detector.subscribe();
+        idle_detector.start()?;
+
+        Ok(Self {
+            storage,
+            event_logger,
+            idle_detector,
+            activity_rx,
+            display_watcher,
+            _control_server: control_server,
+        })
+    }
 }
 
 /// Run the capture loop (runs in tokio runtime)
+#[allow(clippy::too_many_arguments)]
 async fn run_capture_loop(
     config: Config,
+    mut screen_capture: Box<dyn FrameSource>,
+    services: LoopServices,
     mut cmd_rx: mpsc::Receiver<MenuCommand>,
     capture_enabled: Arc<AtomicBool>,
     running: Arc<AtomicBool>,
+    restored_resume_at: Option<chrono::DateTime<Utc>>,
+    tray_tx: Option<std::sync::mpsc::Sender<TrayUpdate>>,
 ) -> Result<()> {
-    info!("Configuration loaded: capture interval={}s, idle threshold={}s",
-        config.capture.interval_seconds,
+    info!("Configuration loaded: capture interval={}ms, idle threshold={}s",
+        config.capture.interval().as_millis(),
         config.idle.threshold_seconds
     );
-
-    // Ensure data directories exist
-    std::fs::create_dir_all(config.logging.logs_dir())?;
-    std::fs::create_dir_all(config.logging.staging_dir())?;
-
-    // List available monitors
-    match ScreenCapture::list_monitors() {
-        Ok(monitors) => {
-            info!("Available monitors:");
-            for m in &monitors {
-                info!("  Monitor {}: {}x{}{}", m.id, m.width, m.height,
-                    if m.is_primary { " (primary)" } else { "" });
-            }
-        }
-        Err(e) => warn!("Could not list monitors: {}", e),
+    if config.capture.interval_clamped() {
+        warn!(
+            "Capture interval_ms is below the {}ms minimum; capturing every {}ms instead",
+            MIN_INTERVAL_MS,
+            MIN_INTERVAL_MS
+        );
     }
 
-    // Initialize components
-    let screen_capture = ScreenCapture::new(
-        config.capture.monitor_id,
-        config.capture.jpeg_quality,
-        config.capture.resolution_scale,
-    )?;
-
-    info!(
-        "Capture settings: monitor_id={}, resolution_scale={:.0}%",
-        if config.capture.monitor_id < 0 { "all".to_string() } else { config.capture.monitor_id.to_string() },
-        config.capture.resolution_scale * 100.0
+    let LoopServices {
+        storage,
+        mut event_logger,
+        idle_detector,
+        mut activity_rx,
+        display_watcher,
+        _control_server,
+    } = services;
+    let mut failure_notifier = FailureNotifier::new(
+        config.upload.notify_after_failures,
+        config.upload.notify_on_recovery,
     );
-
-    let idle_detector = IdleDetector::new(config.idle.threshold())?;
-    let s3_uploader = S3Uploader::new(&config.s3).await?;
-    let mut jsonl_logger = JsonlLogger::new(config.logging.logs_dir())?;
+    let mut breaker = CircuitBreaker::new(
+        config.upload.circuit_breaker_failures,
+        config.upload.circuit_breaker_cooldown(),
+    );
+    let mut tray_uploads_suspended = false;
+    let mut tray_capture_enabled = capture_enabled.load(Ordering::SeqCst);
+    let webhook = WebhookNotifier::new(&config.webhook)?;
+    let mut disk_guard = DiskGuard::new(
+        config.logging.data_dir.clone(),
+        config.logging.min_free_disk_bytes(),
+    );
+    let mut usage = UsageTracker::load(config.logging.usage_path(), config.upload.daily_budget_bytes());
 
     // Log session start
-    jsonl_logger.log_session_start(VERSION)?;
-
-    // Start idle detection
-    let mut activity_rx = idle_detector.subscribe();
-    idle_detector.start()?;
+    event_logger.log_session_start(VERSION)?;
 
     // Main capture loop
-    let mut interval = tokio::time::interval(config.capture.interval());
+    let mut adaptive = config
+        .capture
+        .adaptive
+        .as_ref()
+        .map(|a| AdaptiveInterval::new(a, config.capture.interval()));
+    let mut adaptive_quality = config
+        .capture
+        .adaptive_quality
+        .as_ref()
+        .map(|a| AdaptiveQuality::new(a, config.capture.jpeg_quality));
+    let mut jitter = Jitter::new(
+        config.capture.start_jitter,
+        config.capture.tick_jitter(),
+        config.capture.jitter_seed,
+    );
+    let period = adaptive.as_ref().map(|a| a.current()).unwrap_or_else(|| config.capture.interval());
+    // Let the screen settle after login before the first capture; idle
+    // detection and the session start event above don't wait
+    let start_offset = config.capture.startup_delay() + jitter.start_offset(period);
+    if !start_offset.is_zero() {
+        info!("Delaying first capture by {}ms", start_offset.as_millis());
+    }
+    let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + start_offset, period);
+    let mut last_fingerprints: HashMap<u32, Vec<u8>> = HashMap::new();
+    // Fingerprint of each monitor's last uploaded frame, for skipping unchanged monitors
+    let mut uploaded_fingerprints: HashMap<u32, Vec<u8>> = HashMap::new();
+    let skip_unchanged_below = config
+        .capture
+        .skip_unchanged_below
+        .filter(|_| screen_capture.captures_all_monitors() && !config.capture.stitch);
     let mut frames_captured: u64 = 0;
+    let mut frames_sampled_out: u64 = 0;
+    let mut capture_count: u64 = 0;
     let mut is_idle = false;
+    let mut last_capture_at: Option<tokio::time::Instant> = None;
+    // When a capture put off by tick jitter is due
+    let mut delayed_capture: Option<tokio::time::Instant> = None;
+    let mut capture_backoff = CaptureBackoff::new();
+    let mut waiting_for_display_since: Option<tokio::time::Instant> = None;
+    let mut fullscreen_since: Option<tokio::time::Instant> = None;
+    let mut upload_queue = UploadQueue::new(config.upload.queue_capacity, config.upload.overflow_policy);
+    let deadline = config
+        .capture
+        .max_duration()
+        .map(|d| tokio::time::Instant::now() + d);
+    // When a timed pause ends, and how long it was
+    let mut resume_at: Option<(tokio::time::Instant, Duration)> = restored_resume_at.map(|at| {
+        let remaining = (at - Utc::now()).to_std().unwrap_or_default();
+        (tokio::time::Instant::now() + remaining, remaining)
+    });
+    let state_path = config.logging.state_path();
+    if !capture_enabled.load(Ordering::SeqCst) {
+        match resume_at {
+            Some((_, remaining)) => info!("Restored timed pause, resuming in {:?}", remaining),
+            None => info!("Capture was paused before restart, staying paused"),
+        }
+    }
+
+    let mut heartbeat = config
+        .logging
+        .heartbeat_interval()
+        .map(|period| tokio::time::interval_at(tokio::time::Instant::now() + period, period));
+
+    // Expired logs and staging files are removed at startup, then periodically
+    let retention = config.logging.retention();
+    let mut cleanup = retention.map(|_| tokio::time::interval(CLEANUP_INTERVAL));
 
     info!("Entering main capture loop");
 
     while running.load(Ordering::SeqCst) {
+        // Flag suspended uploads in the menu bar
+        let uploads_suspended = breaker.state() != BreakerState::Closed;
+        if uploads_suspended != tray_uploads_suspended {
+            tray_uploads_suspended = uploads_suspended;
+            if let Some(tray_tx) = &tray_tx {
+                let title = if uploads_suspended { TRAY_TITLE_UPLOADS_SUSPENDED } else { TRAY_TITLE };
+                let _ = tray_tx.send(TrayUpdate::Title(title));
+            }
+        }
+
+        // Relabel the toggle item whenever capture is paused or resumed, by
+        // the menu, the control socket, or a timed pause running out
+        let enabled = capture_enabled.load(Ordering::SeqCst);
+        if enabled != tray_capture_enabled {
+            tray_capture_enabled = enabled;
+            if let Some(tray_tx) = &tray_tx {
+                let _ = tray_tx.send(TrayUpdate::CaptureEnabled(enabled));
+            }
+        }
+
+        let mut capture_now = false;
         tokio::select! {
             _ = interval.tick() => {
-                // Skip capture if paused or idle
-                if !capture_enabled.load(Ordering::SeqCst) || is_idle {
+                // Skip capture if paused, or if idle unless a slower idle capture is due
+                if !capture_enabled.load(Ordering::SeqCst) {
+                    continue;
+                }
+                if is_idle {
+                    let idle_capture_due = config.idle.idle_interval().is_some_and(|every| {
+                        last_capture_at.is_none_or(|at| at.elapsed() >= every)
+                    });
+                    if !idle_capture_due {
+                        continue;
+                    }
+                }
+
+                // Skip capture while the data directory is low on space
+                if !has_disk_space(&mut disk_guard, &mut event_logger, &config) {
                     continue;
                 }
 
-                // Capture frame(s) - multi-monitor or single
-                let frames_result = if screen_capture.captures_all_monitors() {
-                    screen_capture.capture_all().await
-                } else {
-                    screen_capture.capture().await.map(|f| vec![f])
-                };
+                // With no display attached (e.g. a closed laptop lid), wait
+                // for one instead of failing every capture
+                let has_display = screen_capture.has_display();
+                match (has_display, waiting_for_display_since) {
+                    (false, None) => {
+                        warn!("No display attached, pausing capture until one is");
+                        screen_capture.reset_streams();
+                        waiting_for_display_since = Some(tokio::time::Instant::now());
+                        let _ = event_logger.log_display_wait_start();
+                    }
+                    (true, Some(since)) => {
+                        info!("Display attached after {:?}, resuming capture", since.elapsed());
+                        waiting_for_display_since = None;
+                        let _ = event_logger.log_display_wait_end(since.elapsed().as_secs());
+                    }
+                    _ => {}
+                }
+                if !has_display {
+                    continue;
+                }
 
-                match frames_result {
-                    Ok(frames) => {
-                        for frame in frames {
-                            let frame_id = frame.frame_id();
-                            let file_size = frame.data.len();
-                            let capture_ms = frame.capture_duration_ms;
-
-                            // Upload to S3
-                            match s3_uploader.upload_frame(&frame).await {
-                                Ok(result) => {
-                                    frames_captured += 1;
-
-                                    // Log frame metadata
-                                    if let Err(e) = jsonl_logger.log_frame(
-                                        &frame,
-                                        &result.key,
-                                        &config.s3.bucket,
-                                        result.upload_duration_ms,
-                                        0, // idle_seconds_before
-                                    ) {
-                                        warn!("Failed to log frame: {}", e);
-                                    }
-
-                                    info!(
-                                        "Captured frame {} (mon:{}) -> {} ({} bytes, capture={}ms, upload={}ms)",
-                                        frame_id, frame.monitor_id, result.key, file_size, capture_ms, result.upload_duration_ms
-                                    );
-                                }
-                                Err(e) => {
-                                    error!("Failed to upload frame {}: {}", frame_id, e);
-                                }
+                // Re-enumerate displays after hotplug or resolution changes
+                if display_watcher.as_ref().is_some_and(|w| w.take_changed()) {
+                    screen_capture.reset_streams();
+                    match screen_capture.list_monitors() {
+                        Ok(monitors) => {
+                            info!("Display configuration changed: {} monitor(s)", monitors.len());
+                            for m in &monitors {
+                                info!("  Monitor {}: {}x{}", m.id, m.width, m.height);
                             }
+                            let _ = event_logger.log_display_changed(monitors);
                         }
+                        Err(e) => warn!("Display configuration changed, but listing monitors failed: {}", e),
                     }
-                    Err(e) => {
-                        error!("Failed to capture frame: {}", e);
+                }
+
+                // Retry frames left over from failed uploads; with the block
+                // policy, don't capture more until there's room
+                let upload_durations = drain_upload_queue(
+                    &mut upload_queue,
+                    storage.as_ref(),
+                    &mut event_logger,
+                    &mut failure_notifier,
+                    &mut breaker,
+                    webhook.as_ref(),
+                    &mut frames_captured,
+                    &mut usage,
+                )
+                .await;
+                adapt_quality(&mut adaptive_quality, screen_capture.as_mut(), &upload_durations);
+                if upload_queue.blocks_capture() {
+                    warn!("Upload queue full ({} frames), skipping capture", upload_queue.len());
+                    continue;
+                }
+
+                // Only capture an allowlisted app, and not a presentation or
+                // shared screen if so configured
+                if !frontmost_allowed(&mut fullscreen_since, &mut event_logger, &config) {
+                    continue;
+                }
+
+                // Back off while captures keep failing
+                if !capture_backoff.ready() {
+                    continue;
+                }
+
+                // Spread captures from many machines across the interval,
+                // waiting in a branch of its own so commands aren't held up
+                let tick_delay = jitter.tick_delay();
+                if tick_delay.is_zero() {
+                    capture_now = true;
+                } else {
+                    delayed_capture = Some(tokio::time::Instant::now() + tick_delay);
+                }
+            }
+            _ = sleep_until_deadline(delayed_capture) => {
+                delayed_capture = None;
+                // Pausing during the delay cancels the capture
+                capture_now = capture_enabled.load(Ordering::SeqCst);
+            }
+            _ = tick_optional(&mut cleanup) => {
+                let Some(max_age) = retention else { continue };
+                let logging = config.logging.clone();
+                match tokio::task::spawn_blocking(move || logging::clean_data_dir(&logging, max_age)).await {
+                    Ok(reclaimed) if reclaimed.files > 0 => {
+                        info!(
+                            "Removed {} expired local files ({} bytes)",
+                            reclaimed.files, reclaimed.bytes
+                        );
+                        let _ = event_logger.log_data_cleanup(reclaimed.files, reclaimed.bytes);
                     }
+                    Ok(_) => {}
+                    Err(e) => warn!("Local file cleanup failed: {}", e),
+                }
+            }
+            _ = tick_optional(&mut heartbeat) => {
+                let paused = !capture_enabled.load(Ordering::SeqCst);
+                if let Err(e) = event_logger.log_heartbeat(is_idle, paused, frames_captured, upload_queue.len()) {
+                    warn!("Failed to log heartbeat: {}", e);
                 }
             }
+            _ = sleep_until_deadline(resume_at.map(|(at, _)| at)) => {
+                let paused_for = resume_at.take().map(|(_, d)| d).unwrap_or_default();
+                capture_enabled.store(true, Ordering::SeqCst);
+                save_capture_state(&state_path, &capture_enabled, resume_at);
+                info!("Capture resumed after {:?} pause", paused_for);
+                let _ = event_logger.log_schedule_resumed(paused_for.as_secs());
+                delay_after_resume(&mut interval, &config.capture);
+            }
+            _ = sleep_until_deadline(deadline) => {
+                info!("Reached max duration, stopping");
+                running.store(false, Ordering::SeqCst);
+                break;
+            }
             Some(cmd) = cmd_rx.recv() => {
                 match cmd {
                     MenuCommand::ToggleCapture => {
+                        resume_at = None;
+                        save_capture_state(&state_path, &capture_enabled, resume_at);
                         let enabled = capture_enabled.load(Ordering::SeqCst);
                         info!("Capture {}", if enabled { "resumed" } else { "paused" });
+                        if enabled {
+                            delay_after_resume(&mut interval, &config.capture);
+                        }
+                    }
+                    MenuCommand::Pause => {
+                        resume_at = None;
+                        capture_enabled.store(false, Ordering::SeqCst);
+                        save_capture_state(&state_path, &capture_enabled, resume_at);
+                        info!("Capture paused");
+                    }
+                    MenuCommand::PauseFor(duration) => {
+                        resume_at = Some((tokio::time::Instant::now() + duration, duration));
+                        capture_enabled.store(false, Ordering::SeqCst);
+                        save_capture_state(&state_path, &capture_enabled, resume_at);
+                        info!("Capture paused for {:?}", duration);
+                    }
+                    MenuCommand::Resume => {
+                        resume_at = None;
+                        let was_paused = !capture_enabled.swap(true, Ordering::SeqCst);
+                        save_capture_state(&state_path, &capture_enabled, resume_at);
+                        info!("Capture resumed");
+                        if was_paused {
+                            delay_after_resume(&mut interval, &config.capture);
+                        }
+                    }
+                    cmd @ (MenuCommand::CaptureNow | MenuCommand::AppSwitched(_) | MenuCommand::Unlocked) => {
+                        let reason = match cmd {
+                            MenuCommand::AppSwitched(app) => {
+                                // A frame taken since the app settled in front already shows it
+                                let captured_since_switch = last_capture_at
+                                    .is_some_and(|t| t.elapsed() < config.capture.app_switch_debounce());
+                                if !capture_enabled.load(Ordering::SeqCst)
+                                    || waiting_for_display_since.is_some()
+                                    || captured_since_switch
+                                    || !may_capture_now(
+                                        &mut disk_guard,
+                                        &mut fullscreen_since,
+                                        &mut event_logger,
+                                        &upload_queue,
+                                        &capture_backoff,
+                                        &config,
+                                    )
+                                {
+                                    debug!("Not capturing switch to {}", app.name);
+                                    continue;
+                                }
+                                info!("Switched to {}, capturing", app.name);
+                                Some(CaptureReason::AppSwitch)
+                            }
+                            MenuCommand::Unlocked => {
+                                // A tick that just ran (e.g. on returning from idle) already marks it
+                                let captured_recently = last_capture_at
+                                    .is_some_and(|t| t.elapsed() < RESUME_CAPTURE_DEBOUNCE);
+                                if !capture_enabled.load(Ordering::SeqCst)
+                                    || waiting_for_display_since.is_some()
+                                    || captured_recently
+                                    || !may_capture_now(
+                                        &mut disk_guard,
+                                        &mut fullscreen_since,
+                                        &mut event_logger,
+                                        &upload_queue,
+                                        &capture_backoff,
+                                        &config,
+                                    )
+                                {
+                                    debug!("Not capturing unlock");
+                                    continue;
+                                }
+                                info!("Screen unlocked, capturing");
+                                // Keep the next scheduled tick a full interval away
+                                interval.reset();
+                                Some(CaptureReason::Unlock)
+                            }
+                            _ => {
+                                info!("Manual capture requested");
+                                None
+                            }
+                        };
+                        last_capture_at = Some(tokio::time::Instant::now());
+                        let result = capture_frames(screen_capture.as_ref()).await;
+                        for failure in screen_capture.take_monitor_failures() {
+                            let _ = event_logger.log_monitor_capture_failed(&failure);
+                        }
+                        match result {
+                            Ok(frames) => {
+                                capture_backoff.record_success();
+                                let input_events = config.idle.count_input.then(|| idle_detector.take_input_events());
+                                for mut frame in frames {
+                                    frame.manual = reason.is_none();
+                                    frame.reason = reason;
+                                    frame.input_events = input_events;
+                                    last_fingerprints.insert(frame.monitor_id, frame.fingerprint.clone());
+                                    uploaded_fingerprints.insert(frame.monitor_id, frame.fingerprint.clone());
+                                    let queued = QueuedFrame { frame, idle_seconds_before: 0, interval_ms: None };
+                                    enqueue_frame(&mut upload_queue, &mut event_logger, queued);
+                                }
+                                let upload_durations = drain_upload_queue(
+                                    &mut upload_queue,
+                                    storage.as_ref(),
+                                    &mut event_logger,
+                                    &mut failure_notifier,
+                                    &mut breaker,
+                                    webhook.as_ref(),
+                                    &mut frames_captured,
+                                    &mut usage,
+                                )
+                                .await;
+                                adapt_quality(&mut adaptive_quality, screen_capture.as_mut(), &upload_durations);
+                            }
+                            Err(e) => error!("Capture failed: {:#}", e),
+                        }
+                    }
+                    MenuCommand::Status(reply) => {
+                        let state = if !capture_enabled.load(Ordering::SeqCst) {
+                            "paused"
+                        } else if is_idle {
+                            "idle"
+                        } else {
+                            "capturing"
+                        };
+                        let _ = reply.send(format!(
+                            "{} frames={} queued={} timeouts={} circuit={}",
+                            state,
+                            frames_captured,
+                            upload_queue.len(),
+                            screen_capture.timeout_count(),
+                            breaker.state()
+                        ));
                     }
                     MenuCommand::Quit => {
                         info!("Quit command received");
@@ -240,51 +873,1198 @@ async fn run_capture_loop(
                     }
                 }
             }
-            Ok(state) = activity_rx.recv() => {
+            state = next_activity(&mut activity_rx, &idle_detector) => {
                 match state {
                     ActivityState::Active => {
                         if is_idle {
                             info!("User activity resumed");
                             is_idle = false;
-                            let _ = jsonl_logger.log_idle_end();
+                            let _ = event_logger.log_idle_end();
+
+                            // Restart the interval so the next tick captures right away,
+                            // unless a capture just happened
+                            let captured_recently = last_capture_at
+                                .is_some_and(|t| t.elapsed() < RESUME_CAPTURE_DEBOUNCE);
+                            if config.idle.capture_on_resume && !captured_recently {
+                                interval.reset_immediately();
+                            }
                         }
                     }
                     ActivityState::Idle { since } => {
                         if !is_idle {
                             info!("User idle since {}", since);
                             is_idle = true;
-                            let _ = jsonl_logger.log_idle_start(config.idle.threshold_seconds);
+                            let _ = event_logger.log_idle_start(config.idle.threshold_seconds);
                         }
                     }
                 }
             }
         }
+
+        // Capture once a tick, or the jitter delay it started, says to
+        if !capture_now {
+            continue;
+        }
+
+        // Capture frame(s) - multi-monitor or single. The watchdog
+        // bounds how long a wedged capture can hold up the loop.
+        last_capture_at = Some(tokio::time::Instant::now());
+        let (frames_result, stalled) = match config.capture.watchdog() {
+            Some(limit) => match tokio::time::timeout(limit, capture_frames(screen_capture.as_ref())).await {
+                Ok(result) => (result, false),
+                Err(_) => (Err(anyhow::anyhow!("Capture did not finish within {:?}", limit)), true),
+            },
+            None => (capture_frames(screen_capture.as_ref()).await, false),
+        };
+        for failure in screen_capture.take_monitor_failures() {
+            let _ = event_logger.log_monitor_capture_failed(&failure);
+        }
+
+        match frames_result {
+            Ok(frames) => {
+                capture_backoff.record_success();
+
+                // Largest change across captured monitors since their previous frames
+                let change = frames
+                    .iter()
+                    .map(|f| {
+                        last_fingerprints
+                            .get(&f.monitor_id)
+                            .map(|prev| capture::difference(prev, &f.fingerprint))
+                            .unwrap_or(1.0)
+                    })
+                    .fold(0.0, f32::max);
+                for frame in &frames {
+                    last_fingerprints.insert(frame.monitor_id, frame.fingerprint.clone());
+                }
+                let interval_ms = adaptive.as_ref().map(|a| a.current().as_millis() as u64);
+                let input_events = config.idle.count_input.then(|| idle_detector.take_input_events());
+
+                // Idle captures record how long the user has been away
+                let idle_seconds_before = if is_idle {
+                    event_logger
+                        .idle_start_time()
+                        .map(|start| (Utc::now() - start).num_seconds().max(0) as u64)
+                        .unwrap_or(0)
+                        + config.idle.threshold_seconds
+                } else {
+                    0
+                };
+
+                // Only every Nth capture is uploaded; the rest still feed change detection
+                let upload = capture_count.is_multiple_of(config.upload.sample_rate);
+                capture_count += 1;
+
+                let mut uploaded_monitors = Vec::new();
+                let mut unchanged_monitors = Vec::new();
+                for mut frame in frames {
+                    frame.input_events = input_events;
+                    if !upload {
+                        frames_sampled_out += 1;
+                        if config.upload.log_sampled_out {
+                            if let Err(e) = event_logger.log_sampled_out(&frame, interval_ms) {
+                                warn!("Failed to log frame: {}", e);
+                            }
+                        }
+                        continue;
+                    }
+
+                    // Each monitor is compared with its own last upload
+                    if let Some(threshold) = skip_unchanged_below {
+                        let change = uploaded_fingerprints
+                            .get(&frame.monitor_id)
+                            .map(|prev| capture::difference(prev, &frame.fingerprint))
+                            .unwrap_or(1.0);
+                        if change < threshold {
+                            unchanged_monitors.push(frame.monitor_id);
+                            continue;
+                        }
+                        uploaded_fingerprints.insert(frame.monitor_id, frame.fingerprint.clone());
+                        uploaded_monitors.push(frame.monitor_id);
+                    }
+
+                    let queued = QueuedFrame { frame, idle_seconds_before, interval_ms };
+                    enqueue_frame(&mut upload_queue, &mut event_logger, queued);
+                }
+                if !unchanged_monitors.is_empty() {
+                    debug!(
+                        "Uploading monitors {:?}, skipping unchanged {:?}",
+                        uploaded_monitors, unchanged_monitors
+                    );
+                    let _ = event_logger.log_monitors_unchanged(uploaded_monitors, unchanged_monitors);
+                }
+
+                let upload_durations = drain_upload_queue(
+                    &mut upload_queue,
+                    storage.as_ref(),
+                    &mut event_logger,
+                    &mut failure_notifier,
+                    &mut breaker,
+                    webhook.as_ref(),
+                    &mut frames_captured,
+                    &mut usage,
+                )
+                .await;
+                adapt_quality(&mut adaptive_quality, screen_capture.as_mut(), &upload_durations);
+
+                // Retune the interval from how much the screen changed
+                if let Some(next) = adaptive.as_mut().and_then(|a| a.update(change)) {
+                    info!("Adaptive interval now {}ms (change={:.3})", next.as_millis(), change);
+                    interval = tokio::time::interval_at(tokio::time::Instant::now() + next, next);
+                }
+
+                if config.capture.max_frames.is_some_and(|max| frames_captured >= max) {
+                    info!("Reached max frames ({}), stopping", frames_captured);
+                    running.store(false, Ordering::SeqCst);
+                    break;
+                }
+            }
+            // The display works; its frames are just too big to keep
+            Err(e) if FrameTooLarge::is_cause_of(&e) => warn!("{:#}", e),
+            Err(e) => {
+                capture_backoff.record_failure(&e);
+                let _ = event_logger
+                    .log_capture_failed(&format!("{:#}", e), capture_backoff.consecutive_failures());
+
+                // Rebuild the capture streams if they hung or kept failing
+                let failing_too_long = config
+                    .capture
+                    .watchdog()
+                    .is_some_and(|limit| capture_backoff.failing_for() >= limit);
+                if stalled || failing_too_long {
+                    let reason = if stalled { "stalled" } else { "failing" };
+                    warn!(
+                        "Capture {} ({} consecutive failures), restarting capture streams",
+                        reason,
+                        capture_backoff.consecutive_failures()
+                    );
+                    screen_capture.restart();
+                    capture_backoff.record_restart();
+                    let _ = event_logger
+                        .log_capture_restart(reason, capture_backoff.consecutive_failures());
+                }
+            }
+        }
     }
 
     // Cleanup
     info!("Shutting down...");
-    jsonl_logger.log_session_end(frames_captured)?;
+    // Give frames still waiting for upload one last try
+    drain_upload_queue(
+        &mut upload_queue,
+        storage.as_ref(),
+        &mut event_logger,
+        &mut failure_notifier,
+        &mut breaker,
+        webhook.as_ref(),
+        &mut frames_captured,
+        &mut usage,
+    )
+    .await;
+    if upload_queue.len() > 0 {
+        warn!("Dropping {} frames still waiting for upload", upload_queue.len());
+    }
+    while let Some(queued) = upload_queue.pop() {
+        let _ = event_logger.log_frame_dropped(&queued.frame, "shutdown");
+    }
+    // Upload the open archive first so its frames count in the session end
+    if let Err(e) = storage.finish().await {
+        warn!("Failed to finish storage: {:#}", e);
+    }
+    record_settled_uploads(storage.as_ref(), &mut usage);
+    // Failures here are only logged, so teardown below still runs
+    match event_logger.log_session_end(frames_captured, frames_sampled_out, usage.session_bytes()) {
+        Ok(summary) if config.logging.upload_session_summary => {
+            if let Err(e) = store_session_summary(storage.as_ref(), &config, &summary).await {
+                warn!("Failed to store session summary: {:#}", e);
+            }
+        }
+        Ok(_) => {}
+        Err(e) => warn!("Failed to log session end: {:#}", e),
+    }
     idle_detector.stop();
+    screen_capture.shutdown();
+
+    info!(
+        "Captured {} frames total ({} capture timeouts). Goodbye!",
+        frames_captured,
+        screen_capture.timeout_count()
+    );
+
+    Ok(())
+}
+
+/// Store the session summary next to the frames.
+async fn store_session_summary(
+    storage: &dyn StorageBackend,
+    config: &Config,
+    summary: &impl serde::Serialize,
+) -> Result<()> {
+    let key = KeyLayout::from_config(config)?.session_summary(session::id());
+    storage
+        .upload_object(&key, serde_json::to_vec_pretty(summary)?, "application/json")
+        .await?;
+    info!("Stored session summary at {}", key);
+    Ok(())
+}
+
+/// Decrypt a downloaded frame, writing to `output` or stdout.
+fn decrypt_file(key: &str, input: &Path, output: Option<&Path>) -> Result<()> {
+    let cipher = FrameCipher::from_hex(key)?;
+    let data = std::fs::read(input)
+        .with_context(|| format!("Failed to read encrypted frame: {:?}", input))?;
+    let plaintext = cipher.decrypt(&data)?;
+
+    match output {
+        Some(path) => std::fs::write(path, plaintext)
+            .with_context(|| format!("Failed to write decrypted frame: {:?}", path))?,
+        None => std::io::stdout().write_all(&plaintext)?,
+    }
+    Ok(())
+}
+
+/// List available monitors, waiting for screen capture to become available
+/// (e.g. Screen Recording permission granted after launch), then set up
+/// capture from the configured display.
+async fn create_frame_source(config: &Config) -> Result<Box<dyn FrameSource>> {
+    let mock_display = MockDisplay::from_env()?;
+    let monitors = match &mock_display {
+        Some(mock) => {
+            warn!("Mock capture enabled, producing synthetic {}x{} frames", mock.width, mock.height);
+            mock.monitors()
+        }
+        None => {
+            capture::check_screen_capture_access(config.capture.request_permission);
+            wait_for_monitors(config.capture.init_retry_attempts).await?
+        }
+    };
+    info!("Available monitors:");
+    for m in &monitors {
+        info!("  Monitor {}: {}x{}{}", m.id, m.width, m.height,
+            if m.is_primary { " (primary)" } else { "" });
+    }
+
+    let screen_capture = ScreenCapture::new(
+        config.capture.monitor_id,
+        config.capture.jpeg_quality,
+        config.capture.resolution_scale,
+    )?
+    .with_region(config.capture.region)
+    .with_follow_cursor(config.capture.follow_cursor)
+    .with_max_dimension(config.capture.max_dimension)
+    .with_color_space(config.capture.color_space)
+    .with_output_format(config.capture.output_format)
+    .with_jpeg_subsampling(config.capture.jpeg_subsampling)
+    .with_keep_alpha(config.capture.keep_alpha)
+    .with_optimize_jpeg(config.capture.optimize_jpeg)
+    .with_stitch(config.capture.stitch)
+    .with_on_monitor_error(config.capture.on_monitor_error)
+    .with_thumbnail_width(config.capture.thumbnail_width)
+    .with_timeout(config.capture.timeout())
+    .with_max_frame_bytes(config.capture.max_frame_bytes)
+    .with_capture_interval(config.capture.interval())
+    .with_mock(mock_display)
+    .with_embedded_metadata(config.capture.embed_metadata, config.device_id())
+    .with_audio_activity(config.capture.audio_activity);
+
+    info!(
+        "Capture settings: monitor_id={}, resolution_scale={:.0}%",
+        if config.capture.monitor_id < 0 { "all".to_string() } else { config.capture.monitor_id.to_string() },
+        config.capture.resolution_scale * 100.0
+    );
+
+    Ok(Box::new(screen_capture))
+}
+
+/// Enumerate monitors, retrying with backoff while screen capture is unavailable.
+async fn wait_for_monitors(attempts: u32) -> Result<Vec<MonitorInfo>> {
+    let mut delay = INIT_RETRY_BASE_DELAY;
+    let mut attempt = 1;
+    loop {
+        match ScreenCapture::list_monitors() {
+            Ok(monitors) => return Ok(monitors),
+            // No display isn't a permission problem; the capture loop waits for one
+            Err(e) if capture::active_display_count() == Some(0) => {
+                warn!("No display attached ({}), capture will start when one is", e);
+                return Ok(Vec::new());
+            }
+            Err(e) if attempt < attempts => {
+                warn!(
+                    "Screen capture not available (attempt {}/{}): {}; retrying in {:?}",
+                    attempt, attempts, e, delay
+                );
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(INIT_RETRY_MAX_DELAY);
+                attempt += 1;
+            }
+            Err(e) => {
+                return Err(e.context(format!(
+                    "Screen capture unavailable after {} attempts; check Screen Recording permission",
+                    attempts
+                )));
+            }
+        }
+    }
+}
+
+/// Hold off the next capture for the startup delay after a pause ends, if
+/// `delay_after_resume` is set.
+fn delay_after_resume(interval: &mut tokio::time::Interval, capture: &CaptureConfig) {
+    let delay = capture.startup_delay();
+    if capture.delay_after_resume && !delay.is_zero() {
+        info!("Delaying next capture by {:?} after resuming", delay);
+        interval.reset_after(delay);
+    }
+}
+
+/// Wait for the next tick, or forever if there is no interval.
+async fn tick_optional(interval: &mut Option<tokio::time::Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Persist the pause state so it is restored after a restart.
+fn save_capture_state(
+    path: &Path,
+    capture_enabled: &AtomicBool,
+    resume_at: Option<(tokio::time::Instant, Duration)>,
+) {
+    let state = CaptureState {
+        capture_enabled: capture_enabled.load(Ordering::SeqCst),
+        resume_at: resume_at.and_then(|(at, _)| {
+            let remaining = at.saturating_duration_since(tokio::time::Instant::now());
+            chrono::Duration::from_std(remaining).ok().map(|d| Utc::now() + d)
+        }),
+    };
+    if let Err(e) = state.save(path) {
+        warn!("Failed to save capture state: {:#}", e);
+    }
+}
+
+/// Capture every monitor or just the configured one.
+async fn capture_frames(screen_capture: &dyn FrameSource) -> Result<Vec<CapturedFrame>> {
+    let span = info_span!("capture", frames = tracing::field::Empty, bytes = tracing::field::Empty);
+    let frames = if screen_capture.captures_all_monitors() {
+        screen_capture.capture_all().instrument(span.clone()).await
+    } else {
+        screen_capture.capture().instrument(span.clone()).await.map(|f| vec![f])
+    }?;
+    span.record("frames", frames.len());
+    span.record("bytes", frames.iter().map(|f| f.data.len()).sum::<usize>());
+    Ok(frames)
+}
+
+/// Returns true if the data directory has room for more frames, logging
+/// when it runs low or recovers.
+fn has_disk_space(disk_guard: &mut DiskGuard, event_logger: &mut EventLogger, config: &Config) -> bool {
+    let disk_check = disk_guard.check().unwrap_or_else(|e| {
+        warn!("Failed to check free disk space: {}", e);
+        DiskCheck::Ok
+    });
+    match disk_check {
+        DiskCheck::BecameLow { free_bytes } => {
+            warn!(
+                "Low disk space on {:?}: {} MB free, pausing capture",
+                config.logging.data_dir,
+                free_bytes / (1024 * 1024)
+            );
+            let _ = event_logger.log_low_disk(free_bytes, disk_guard.min_free_bytes());
+        }
+        DiskCheck::Recovered { free_bytes } => {
+            info!(
+                "Disk space recovered: {} MB free, resuming capture",
+                free_bytes / (1024 * 1024)
+            );
+        }
+        _ => {}
+    }
+    disk_check.has_space()
+}
+
+/// Returns true if the frontmost app may be captured: it's allowlisted (if
+/// `capture_only_apps` is set), and no full-screen app is being skipped.
+/// Tracks full-screen periods in `fullscreen_since`, logging their start and end.
+fn frontmost_allowed(
+    fullscreen_since: &mut Option<tokio::time::Instant>,
+    event_logger: &mut EventLogger,
+    config: &Config,
+) -> bool {
+    if !config.capture.capture_only_apps.is_empty() {
+        let app = capture::foreground_app();
+        if !app.as_ref().is_some_and(|a| a.matches_any(&config.capture.capture_only_apps)) {
+            debug!(
+                "Skipping capture, frontmost app {:?} not allowlisted",
+                app.as_ref().map(|a| a.name.as_str())
+            );
+            let _ = event_logger.log_frame_skipped_app(app.map(|a| a.name));
+            return false;
+        }
+    }
+
+    if config.capture.fullscreen == FullscreenPolicy::Skip {
+        let fullscreen = capture::fullscreen_app();
+        match (&fullscreen, *fullscreen_since) {
+            (Some(app), None) => {
+                info!("{} is full screen, skipping captures", app.name);
+                *fullscreen_since = Some(tokio::time::Instant::now());
+                let _ = event_logger.log_fullscreen_start(app.name.clone());
+            }
+            (None, Some(since)) => {
+                info!("Full screen ended after {:?}, resuming captures", since.elapsed());
+                *fullscreen_since = None;
+                let _ = event_logger.log_fullscreen_end(since.elapsed().as_secs());
+            }
+            _ => {}
+        }
+        if fullscreen.is_some() {
+            return false;
+        }
+    }
+    true
+}
+
+/// The checks a scheduled tick makes before capturing, for captures taken
+/// between ticks: disk space, room in the upload queue, the frontmost app,
+/// and capture backoff.
+fn may_capture_now(
+    disk_guard: &mut DiskGuard,
+    fullscreen_since: &mut Option<tokio::time::Instant>,
+    event_logger: &mut EventLogger,
+    upload_queue: &UploadQueue,
+    capture_backoff: &CaptureBackoff,
+    config: &Config,
+) -> bool {
+    has_disk_space(disk_guard, event_logger, config)
+        && !upload_queue.blocks_capture()
+        && frontmost_allowed(fullscreen_since, event_logger, config)
+        && capture_backoff.ready()
+}
+
+/// Queue a frame for upload, logging any frame the queue drops to make room.
+fn enqueue_frame(upload_queue: &mut UploadQueue, event_logger: &mut EventLogger, queued: QueuedFrame) {
+    if let Some(dropped) = upload_queue.push(queued) {
+        warn!("Upload queue full, dropping frame {}", dropped.frame.frame_id());
+        let _ = event_logger.log_frame_dropped(&dropped.frame, "queue_full");
+    }
+}
+
+/// Upload queued frames oldest first, stopping at the first failure so the
+/// remaining frames are retried on a later tick. Nothing is attempted while
+/// the circuit breaker is open.
+/// Returns the duration of each upload attempt, None for one that failed or
+/// timed out (rejected frames say nothing about the link and are left out).
+#[allow(clippy::too_many_arguments)]
+async fn drain_upload_queue(
+    queue: &mut UploadQueue,
+    storage: &dyn StorageBackend,
+    event_logger: &mut EventLogger,
+    failure_notifier: &mut FailureNotifier,
+    breaker: &mut CircuitBreaker,
+    webhook: Option<&WebhookNotifier>,
+    frames_captured: &mut u64,
+    usage: &mut UsageTracker,
+) -> Vec<Option<u64>> {
+    let mut upload_durations = Vec::new();
+    while queue.len() > 0 && breaker.allow() {
+        let Some(queued) = queue.pop() else { break };
+        let frame = &queued.frame;
+        let span = info_span!(
+            "upload",
+            frame_id = %frame.frame_id(),
+            monitor_id = frame.monitor_id,
+            bytes = frame.data.len(),
+            capture_ms = frame.capture_duration_ms,
+            upload_ms = tracing::field::Empty,
+            attempts = tracing::field::Empty,
+        );
+        match storage.upload_frame(frame).instrument(span.clone()).await {
+            Ok(result) => {
+                span.record("upload_ms", result.upload_duration_ms);
+                *frames_captured += 1;
+                // Archived frames are counted once their archive is uploaded
+                if !result.pending {
+                    usage.record(frame.data.len() as u64);
+                    upload_durations.push(Some(result.upload_duration_ms));
+                }
+                failure_notifier.record_success();
+                breaker.record_success();
+
+                // Log frame metadata
+                if let Err(e) = event_logger.log_frame(
+                    frame,
+                    &result,
+                    storage.location(),
+                    queued.idle_seconds_before,
+                    queued.interval_ms,
+                ) {
+                    warn!("Failed to log frame: {}", e);
+                }
+
+                if result.pending {
+                    info!(
+                        "Captured frame {} (mon:{}) -> {} ({} bytes, capture={}ms, upload pending)",
+                        frame.frame_id(),
+                        frame.monitor_id,
+                        result.key,
+                        frame.data.len(),
+                        frame.capture_duration_ms
+                    );
+                    continue;
+                }
+
+                if let Some(webhook) = webhook {
+                    webhook.notify(frame, &result.key, storage.location());
+                }
+
+                info!(
+                    "Captured frame {} (mon:{}) -> {} ({} bytes, capture={}ms, upload={}ms)",
+                    frame.frame_id(),
+                    frame.monitor_id,
+                    result.key,
+                    frame.data.len(),
+                    frame.capture_duration_ms,
+                    result.upload_duration_ms
+                );
+            }
+            Err(e) => {
+                failure_notifier.record_failure(&e.to_string());
+                event_logger.record_upload_failure();
+
+                // Retrying a rejected frame can't help, and would hold up
+                // every frame queued behind it. It says nothing about an
+                // outage either, so the breaker doesn't count it, and a
+                // half-open probe moves on to the next frame.
+                if UploadError::is_permanent_failure(&e) {
+                    error!("Upload of frame {} rejected, dropping it: {:#}", frame.frame_id(), e);
+                    let _ = event_logger.log_frame_dropped(frame, "upload_rejected");
+                    continue;
+                }
+                breaker.record_failure();
+                upload_durations.push(None);
+
+                error!(
+                    "Failed to upload frame {} ({} queued): {}",
+                    frame.frame_id(),
+                    queue.len() + 1,
+                    e
+                );
+                queue.requeue(queued);
+                break;
+            }
+        }
+    }
+    record_settled_uploads(storage, usage);
+    upload_durations
+}
+
+/// Count archived frames as uploaded once their archive is stored.
+fn record_settled_uploads(storage: &dyn StorageBackend, usage: &mut UsageTracker) {
+    let settled = storage.take_settled_uploads();
+    if settled.frames > 0 {
+        usage.record_many(settled.frames, settled.bytes);
+    }
+}
+
+/// Feed upload times and failures to the adaptive quality controller,
+/// applying any change.
+fn adapt_quality(
+    adaptive_quality: &mut Option<AdaptiveQuality>,
+    screen_capture: &mut dyn FrameSource,
+    upload_durations: &[Option<u64>],
+) {
+    let Some(controller) = adaptive_quality else {
+        return;
+    };
+    for &upload_ms in upload_durations {
+        let changed = match upload_ms {
+            Some(ms) => controller.record(ms).map(|quality| (quality, format!("last upload took {}ms", ms))),
+            None => controller.record_failure().map(|quality| (quality, "last upload failed".to_string())),
+        };
+        if let Some((quality, reason)) = changed {
+            info!("Adaptive quality now {} ({})", quality, reason);
+            screen_capture.set_quality(quality);
+        }
+    }
+}
 
-    info!("Captured {} frames total. Goodbye!", frames_captured);
+/// Wait for the next activity change. If the receiver fell behind and missed
+/// transitions, resynchronize from the detector's current state instead.
+async fn next_activity(
+    activity_rx: &mut broadcast::Receiver<ActivityState>,
+    idle_detector: &IdleDetector,
+) -> ActivityState {
+    match activity_rx.recv().await {
+        Ok(state) => state,
+        Err(broadcast::error::RecvError::Lagged(missed)) => {
+            warn!("Missed {} activity updates, resynchronizing idle state", missed);
+            idle_detector.state()
+        }
+        // The detector outlives the capture loop, so this shouldn't happen
+        Err(broadcast::error::RecvError::Closed) => std::future::pending().await,
+    }
+}
 
-    // Exit the process to close the menu bar
-    std::process::exit(0);
+/// Sleep until the deadline, or forever if there is none.
+async fn sleep_until_deadline(deadline: Option<tokio::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
 }
 
-/// Initialize tracing subscriber with the given log level.
-fn init_tracing(level: &str) -> Result<()> {
+/// Initialize tracing subscriber with the given log level, exporting spans
+/// to an OTLP endpoint when one is configured.
+fn init_tracing(level: &str, otlp_endpoint: Option<&str>) -> Result<()> {
     use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 
+    // RUST_LOG wins; otherwise the level may hold per-target directives
+    let mut invalid = None;
     let filter = EnvFilter::try_from_default_env()
         .or_else(|_| EnvFilter::try_new(level))
-        .unwrap_or_else(|_| EnvFilter::new("info"));
+        .unwrap_or_else(|e| {
+            invalid = Some(e);
+            EnvFilter::new("info")
+        });
+
+    let mut otel_error = None;
+    let otel = otlp_endpoint.and_then(|endpoint| {
+        telemetry::layer(endpoint)
+            .map_err(|e| otel_error = Some(e))
+            .ok()
+    });
 
     tracing_subscriber::registry()
+        .with(otel)
         .with(fmt::layer().with_target(true).with_thread_ids(false))
         .with(filter)
         .init();
 
+    if let Some(e) = invalid {
+        warn!("Invalid logging level '{}' ({}), using info", level, e);
+    }
+    match (otlp_endpoint, otel_error) {
+        (_, Some(e)) => warn!("OpenTelemetry export disabled: {:#}", e),
+        (Some(endpoint), None) => info!("Exporting spans to {}", endpoint),
+        (None, None) => {}
+    }
+
     Ok(())
 }
 
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::capture::MonitorFailure;
+    use crate::config::LoggingConfig;
+    use crate::logging::read_frames_since;
+    use crate::storage::{ArchiveStore, FilesystemStore, UploadResult};
+
+    /// Frame source replaying one scripted tick of frames per capture.
+    struct ScriptedSource {
+        ticks: Mutex<VecDeque<Vec<CapturedFrame>>>,
+        captures: Arc<Mutex<usize>>,
+    }
+
+    #[async_trait]
+    impl FrameSource for ScriptedSource {
+        async fn capture(&self) -> Result<CapturedFrame> {
+            anyhow::bail!("only captures all monitors")
+        }
+
+        async fn capture_all(&self) -> Result<Vec<CapturedFrame>> {
+            *self.captures.lock().unwrap() += 1;
+            self.ticks
+                .lock()
+                .unwrap()
+                .pop_front()
+                .ok_or_else(|| anyhow::anyhow!("script exhausted"))
+        }
+
+        fn captures_all_monitors(&self) -> bool {
+            true
+        }
+
+        fn list_monitors(&self) -> Result<Vec<MonitorInfo>> {
+            Ok(Vec::new())
+        }
+
+        fn take_monitor_failures(&self) -> Vec<MonitorFailure> {
+            Vec::new()
+        }
+    }
+
+    /// Storage recording each stored frame as `<tick>/<monitor>`, failing
+    /// the first `failures` uploads.
+    struct RecordingStorage {
+        stored: Arc<Mutex<Vec<String>>>,
+        failures: Mutex<usize>,
+    }
+
+    #[async_trait]
+    impl StorageBackend for RecordingStorage {
+        async fn upload_frame(&self, frame: &CapturedFrame) -> Result<UploadResult> {
+            let mut failures = self.failures.lock().unwrap();
+            if *failures > 0 {
+                *failures -= 1;
+                anyhow::bail!("storage unavailable");
+            }
+            let key = format!("{}/{}", frame.data[0], frame.monitor_id);
+            self.stored.lock().unwrap().push(key.clone());
+            Ok(UploadResult::stored(&key))
+        }
+
+        async fn upload_object(&self, _key: &str, _data: Vec<u8>, _content_type: &str) -> Result<UploadResult> {
+            anyhow::bail!("not supported")
+        }
+
+        async fn download_bytes(&self, _key: &str) -> Result<Vec<u8>> {
+            anyhow::bail!("not supported")
+        }
+
+        fn location(&self) -> &str {
+            "memory"
+        }
+    }
+
+    /// A frame from `tick` of `monitor`, whose content is `fingerprint`.
+    fn frame(tick: u8, monitor: u32, fingerprint: u8) -> CapturedFrame {
+        let mut frame = CapturedFrame::for_test(vec![tick]);
+        frame.monitor_id = monitor;
+        frame.fingerprint = vec![fingerprint; 16];
+        frame
+    }
+
+    /// A running capture loop around a scripted source and recording storage.
+    struct Harness {
+        captures: Arc<Mutex<usize>>,
+        stored: Arc<Mutex<Vec<String>>>,
+        cmd_tx: mpsc::Sender<MenuCommand>,
+        activity_tx: broadcast::Sender<ActivityState>,
+        tray_rx: std::sync::mpsc::Receiver<TrayUpdate>,
+        task: tokio::task::JoinHandle<Result<()>>,
+        data_dir: tempfile::TempDir,
+    }
+
+    impl Harness {
+        fn start(config: Config, ticks: Vec<Vec<CapturedFrame>>, failures: usize) -> Self {
+            Self::run(
+                config,
+                |captures| {
+                    Box::new(ScriptedSource {
+                        ticks: Mutex::new(ticks.into()),
+                        captures,
+                    })
+                },
+                |stored| {
+                    Box::new(RecordingStorage {
+                        stored,
+                        failures: Mutex::new(failures),
+                    })
+                },
+            )
+        }
+
+        /// Run the loop on the source and storage made from the harness's
+        /// capture counter and stored frame list.
+        fn run(
+            mut config: Config,
+            source: impl FnOnce(Arc<Mutex<usize>>) -> Box<dyn FrameSource>,
+            storage: impl FnOnce(Arc<Mutex<Vec<String>>>) -> Box<dyn StorageBackend>,
+        ) -> Self {
+            let data_dir = tempfile::tempdir().unwrap();
+            config.logging.data_dir = data_dir.path().to_path_buf();
+            config.logging.min_free_disk_mb = 0;
+            std::fs::create_dir_all(config.logging.logs_dir()).unwrap();
+            std::fs::create_dir_all(config.logging.staging_dir()).unwrap();
+
+            let captures = Arc::new(Mutex::new(0));
+            let source = source(captures.clone());
+            let stored = Arc::new(Mutex::new(Vec::new()));
+            let storage = storage(stored.clone());
+            let (activity_tx, activity_rx) = broadcast::channel(16);
+            let services = LoopServices {
+                storage,
+                event_logger: EventLogger::new(&config.logging).unwrap(),
+                idle_detector: IdleDetector::new(config.idle.threshold()).unwrap(),
+                activity_rx,
+                display_watcher: None,
+                _control_server: None,
+            };
+
+            let (cmd_tx, cmd_rx) = mpsc::channel(10);
+            let (tray_tx, tray_rx) = std::sync::mpsc::channel();
+            // The loop holds non-`Send` capture state, so it runs on a local set
+            let task = tokio::task::spawn_local(run_capture_loop(
+                config,
+                source,
+                services,
+                cmd_rx,
+                Arc::new(AtomicBool::new(true)),
+                Arc::new(AtomicBool::new(true)),
+                None,
+                Some(tray_tx),
+            ));
+            Self {
+                captures,
+                stored,
+                cmd_tx,
+                activity_tx,
+                tray_rx,
+                task,
+                data_dir,
+            }
+        }
+
+        fn captures(&self) -> usize {
+            *self.captures.lock().unwrap()
+        }
+
+        fn stored(&self) -> Vec<String> {
+            self.stored.lock().unwrap().clone()
+        }
+
+        /// Toggle item states sent to the menu bar so far.
+        fn tray_capture_states(&self) -> Vec<bool> {
+            self.tray_rx
+                .try_iter()
+                .filter_map(|update| match update {
+                    TrayUpdate::CaptureEnabled(enabled) => Some(enabled),
+                    TrayUpdate::Title(_) => None,
+                })
+                .collect()
+        }
+
+        async fn finish(self) {
+            let _ = self.cmd_tx.send(MenuCommand::Quit).await;
+            self.task.await.unwrap().unwrap();
+        }
+    }
+
+    fn config() -> Config {
+        let mut config = Config::default();
+        config.capture.interval_seconds = 1;
+        // Lets a test's setup (e.g. going idle) land before the first tick
+        config.capture.startup_delay_seconds = 1;
+        config
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failed_uploads_are_retried_in_order_before_new_frames() {
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                let mut config = config();
+                config.capture.max_frames = Some(4);
+                let ticks = vec![vec![frame(1, 1, 0), frame(1, 2, 0)], vec![frame(2, 1, 0), frame(2, 2, 0)]];
+                let harness = Harness::start(config, ticks, 1);
+
+                tokio::time::sleep(Duration::from_secs(10)).await;
+
+                assert_eq!(harness.stored(), ["1/1", "1/2", "2/1", "2/2"]);
+                assert!(harness.task.is_finished());
+                harness.task.await.unwrap().unwrap();
+            })
+            .await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn queued_frames_get_a_last_upload_attempt_at_shutdown() {
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                let harness = Harness::start(config(), vec![vec![frame(1, 1, 0), frame(1, 2, 0)]], 1);
+                tokio::time::sleep(Duration::from_millis(1500)).await;
+                assert!(harness.stored().is_empty());
+
+                let stored = harness.stored.clone();
+                harness.finish().await;
+                assert_eq!(*stored.lock().unwrap(), ["1/1", "1/2"]);
+            })
+            .await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn frames_left_queued_at_shutdown_are_logged_as_dropped() {
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                let harness = Harness::start(config(), vec![vec![frame(1, 1, 0), frame(1, 2, 0)]], 2);
+                tokio::time::sleep(Duration::from_millis(1500)).await;
+
+                harness.cmd_tx.send(MenuCommand::Quit).await.unwrap();
+                harness.task.await.unwrap().unwrap();
+                assert!(harness.stored.lock().unwrap().is_empty());
+                let logged = walkdir(harness.data_dir.path())
+                    .iter()
+                    .map(|p| std::fs::read_to_string(p).unwrap_or_default())
+                    .collect::<String>();
+                assert_eq!(logged.matches("\"reason\":\"shutdown\"").count(), 2, "{}", logged);
+            })
+            .await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn unchanged_monitors_are_skipped() {
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                let mut config = config();
+                config.capture.skip_unchanged_below = Some(0.01);
+                let ticks = vec![
+                    vec![frame(1, 1, 0), frame(1, 2, 0)],
+                    vec![frame(2, 1, 0), frame(2, 2, 255)],
+                    vec![frame(3, 1, 0), frame(3, 2, 0)],
+                ];
+                let harness = Harness::start(config, ticks, 0);
+
+                tokio::time::sleep(Duration::from_millis(3500)).await;
+
+                assert_eq!(harness.captures(), 3);
+                assert_eq!(harness.stored(), ["1/1", "1/2", "2/2", "3/2"]);
+                harness.finish().await;
+            })
+            .await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn idle_pauses_captures_until_activity_resumes() {
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                let ticks = (1..=5).map(|tick| vec![frame(tick, 1, tick)]).collect();
+                let harness = Harness::start(config(), ticks, 0);
+                harness.activity_tx.send(ActivityState::Idle { since: Utc::now() }).unwrap();
+
+                tokio::time::sleep(Duration::from_millis(4500)).await;
+                assert_eq!(harness.captures(), 0);
+
+                harness.activity_tx.send(ActivityState::Active).unwrap();
+                tokio::time::sleep(Duration::from_millis(600)).await;
+                assert_eq!(harness.captures(), 1);
+                assert_eq!(harness.stored(), ["1/1"]);
+                harness.finish().await;
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn lagged_activity_resynchronizes_from_the_detector() {
+        let detector = IdleDetector::new(Duration::from_secs(60)).unwrap();
+        let (tx, mut rx) = broadcast::channel(2);
+        for _ in 0..5 {
+            tx.send(ActivityState::Idle { since: Utc::now() }).unwrap();
+        }
+
+        // The stale idle updates are dropped for the detector's actual state
+        assert!(matches!(next_activity(&mut rx, &detector).await, ActivityState::Active));
+        // and the receiver keeps working afterwards
+        assert!(matches!(next_activity(&mut rx, &detector).await, ActivityState::Idle { .. }));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn overflowing_activity_updates_do_not_leave_the_loop_idle() {
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                let ticks = (1..=5).map(|tick| vec![frame(tick, 1, tick)]).collect();
+                let harness = Harness::start(config(), ticks, 0);
+                // Flapping faster than the loop reads overflows the channel
+                for _ in 0..20 {
+                    harness.activity_tx.send(ActivityState::Idle { since: Utc::now() }).unwrap();
+                    harness.activity_tx.send(ActivityState::Active).unwrap();
+                }
+
+                // The loop ends up active, as the user is
+                tokio::time::sleep(Duration::from_millis(2500)).await;
+                assert_eq!(harness.captures(), 2);
+                harness.finish().await;
+            })
+            .await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn mock_display_frames_are_encoded_stored_and_logged() {
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                let mut config = config();
+                config.capture.max_frames = Some(2);
+                let frames_dir = tempfile::tempdir().unwrap();
+                let keys = KeyLayout::new(None, None, None).unwrap();
+                let store = FilesystemStore::new(frames_dir.path().to_path_buf(), keys).unwrap();
+                let mock = MockDisplay { width: 64, height: 48 };
+                let harness = Harness::run(
+                    config,
+                    |_| Box::new(ScreenCapture::new(-1, 80, 1.0).unwrap().with_mock(Some(mock))),
+                    |_| Box::new(store),
+                );
+
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                assert!(harness.task.is_finished());
+
+                let mut stored = Vec::new();
+                for entry in walkdir(frames_dir.path()) {
+                    let data = std::fs::read(&entry).unwrap();
+                    assert!(data.starts_with(&[0xFF, 0xD8]), "{:?} is not a JPEG", entry);
+                    stored.push(entry);
+                }
+                assert_eq!(stored.len(), 2);
+
+                let logging = LoggingConfig {
+                    data_dir: harness.data_dir.path().to_path_buf(),
+                    ..Default::default()
+                };
+                let logged = read_frames_since(&logging, Utc::now() - chrono::Duration::hours(1)).unwrap();
+                assert_eq!(logged.len(), 2);
+                assert!(logged.iter().all(|f| (f.width, f.height) == (64, 48)));
+                harness.task.await.unwrap().unwrap();
+            })
+            .await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shutdown_finishes_the_archive_when_the_summary_fails() {
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                let mut config = config();
+                config.capture.max_frames = Some(1);
+                config.logging.upload_session_summary = true;
+                // Skips validation, so building the summary's key fails
+                config.s3.key_template = Some("no-timestamp".to_string());
+                let (frames_dir, staging) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+                let keys = KeyLayout::new(None, None, None).unwrap();
+                let inner = FilesystemStore::new(frames_dir.path().to_path_buf(), keys.clone()).unwrap();
+                let store = ArchiveStore::new(Box::new(inner), staging.path().to_path_buf(), keys).unwrap();
+                let harness = Harness::run(
+                    config,
+                    |captures| {
+                        Box::new(ScriptedSource {
+                            ticks: Mutex::new(vec![vec![frame(1, 1, 1)]].into()),
+                            captures,
+                        })
+                    },
+                    |_| Box::new(store),
+                );
+
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                assert!(harness.task.is_finished());
+                harness.task.await.unwrap().unwrap();
+                let uploaded = walkdir(frames_dir.path());
+                assert!(uploaded.iter().any(|p| p.extension().is_some_and(|e| e == "tar")));
+                assert!(walkdir(staging.path()).is_empty());
+            })
+            .await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn archived_frames_count_as_uploaded_once_their_archive_is_stored() {
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                let mut config = config();
+                config.capture.max_frames = Some(2);
+                let (frames_dir, staging) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+                let keys = KeyLayout::new(None, None, None).unwrap();
+                let inner = FilesystemStore::new(frames_dir.path().to_path_buf(), keys.clone()).unwrap();
+                let store = ArchiveStore::new(Box::new(inner), staging.path().to_path_buf(), keys).unwrap();
+                let harness = Harness::run(
+                    config,
+                    |captures| {
+                        Box::new(ScriptedSource {
+                            ticks: Mutex::new(vec![vec![frame(1, 1, 1)], vec![frame(2, 1, 2)]].into()),
+                            captures,
+                        })
+                    },
+                    |_| Box::new(store),
+                );
+
+                tokio::time::sleep(Duration::from_secs(30)).await;
+                assert!(harness.task.is_finished());
+                harness.task.await.unwrap().unwrap();
+                let usage = std::fs::read(harness.data_dir.path().join("usage.json")).unwrap();
+                let usage: serde_json::Value = serde_json::from_slice(&usage).unwrap();
+                assert_eq!((usage["frames"].as_u64(), usage["bytes"].as_u64()), (Some(2), Some(2)));
+                let logged = walkdir(harness.data_dir.path())
+                    .iter()
+                    .map(|p| std::fs::read_to_string(p).unwrap_or_default())
+                    .collect::<String>();
+                assert!(logged.contains("\"bytes_uploaded\":2"), "{}", logged);
+            })
+            .await;
+    }
+
+    /// All files below `dir`.
+    fn walkdir(dir: &Path) -> Vec<std::path::PathBuf> {
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                files.extend(walkdir(&path));
+            } else {
+                files.push(path);
+            }
+        }
+        files
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn commands_are_handled_while_a_jittered_capture_waits() {
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                let mut config = config();
+                config.capture.interval_seconds = 10;
+                config.capture.tick_jitter_ms = 9000;
+                config.capture.jitter_seed = Some(3);
+                let mut jitter = Jitter::new(false, config.capture.tick_jitter(), Some(3));
+                let (delay, next_delay) = (jitter.tick_delay(), jitter.tick_delay());
+                assert!(delay > Duration::from_secs(2), "seed gives a short delay: {:?}", delay);
+                assert!(next_delay > Duration::from_secs(1), "seed gives a short delay: {:?}", next_delay);
+                let ticks = (1..=3).map(|tick| vec![frame(tick, 1, tick)]).collect();
+                let harness = Harness::start(config, ticks, 0);
+
+                // Just after the first tick, the capture is still waiting
+                tokio::time::sleep(Duration::from_millis(1500)).await;
+                let (reply_tx, reply_rx) = oneshot::channel();
+                harness.cmd_tx.send(MenuCommand::Status(reply_tx)).await.unwrap();
+                let status = tokio::time::timeout(Duration::from_millis(10), reply_rx).await;
+                assert!(status.unwrap().is_ok());
+                assert_eq!(harness.captures(), 0);
+
+                tokio::time::sleep(delay).await;
+                assert_eq!(harness.captures(), 1);
+
+                // Pausing while the next capture waits cancels it
+                tokio::time::sleep(Duration::from_secs(10) - delay).await;
+                harness.cmd_tx.send(MenuCommand::Pause).await.unwrap();
+                tokio::time::sleep(Duration::from_secs(10)).await;
+                assert_eq!(harness.captures(), 1);
+                harness.finish().await;
+            })
+            .await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn pausing_and_resuming_relabels_the_menu() {
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                let ticks = (1..=30).map(|tick| vec![frame(tick, 1, tick)]).collect();
+                let harness = Harness::start(config(), ticks, 0);
+
+                harness.cmd_tx.send(MenuCommand::PauseFor(Duration::from_secs(5))).await.unwrap();
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                assert_eq!(harness.tray_capture_states(), [false]);
+
+                // The timed pause running out resumes capture
+                tokio::time::sleep(Duration::from_secs(6)).await;
+                assert_eq!(harness.tray_capture_states(), [true]);
+
+                harness.cmd_tx.send(MenuCommand::Pause).await.unwrap();
+                harness.cmd_tx.send(MenuCommand::Resume).await.unwrap();
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                assert_eq!(harness.tray_capture_states(), [false, true]);
+                harness.finish().await;
+            })
+            .await;
+    }
+}
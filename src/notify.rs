@@ -0,0 +1,117 @@
+//! Desktop notifications for persistent upload failures.
+
+use tracing::{debug, warn};
+
+/// Application name shown as the notification title.
+const APP_NAME: &str = "Preprompter";
+
+/// Tracks consecutive upload failures and notifies the user when a threshold is hit.
+pub struct FailureNotifier {
+    /// Number of consecutive failures before notifying (0 = disabled).
+    threshold: u32,
+    /// Whether to notify again once uploads recover.
+    notify_on_recovery: bool,
+    /// Current run of consecutive failures.
+    consecutive_failures: u32,
+    /// Whether a failure notification has been sent for the current run.
+    notified: bool,
+}
+
+impl FailureNotifier {
+    /// Create a new notifier with the given failure threshold.
+    pub fn new(threshold: u32, notify_on_recovery: bool) -> Self {
+        Self {
+            threshold,
+            notify_on_recovery,
+            consecutive_failures: 0,
+            notified: false,
+        }
+    }
+
+    /// Record a failed upload, notifying once the threshold is reached.
+    pub fn record_failure(&mut self, error: &str) {
+        self.consecutive_failures += 1;
+
+        if self.threshold == 0 || self.notified || self.consecutive_failures < self.threshold {
+            return;
+        }
+
+        self.notified = true;
+        warn!(
+            "{} consecutive upload failures, notifying user",
+            self.consecutive_failures
+        );
+        send_notification(
+            "Uploads failing",
+            &format!(
+                "{} consecutive uploads failed. Last error: {}",
+                self.consecutive_failures, error
+            ),
+        );
+    }
+
+    /// Record a successful upload, resetting the failure count.
+    pub fn record_success(&mut self) {
+        if self.notified && self.notify_on_recovery {
+            send_notification(
+                "Uploads recovered",
+                &format!(
+                    "Uploads are succeeding again after {} failures",
+                    self.consecutive_failures
+                ),
+            );
+        }
+
+        self.consecutive_failures = 0;
+        self.notified = false;
+    }
+}
+
+/// Post a native desktop notification without blocking the caller.
+pub fn send_notification(title: &str, body: &str) {
+    let Some(mut command) = notification_command(title, body) else {
+        debug!("Desktop notifications are not supported on this platform");
+        return;
+    };
+
+    std::thread::spawn(move || {
+        if let Err(e) = command.output() {
+            warn!("Failed to send desktop notification: {}", e);
+        }
+    });
+}
+
+#[cfg(target_os = "macos")]
+fn notification_command(title: &str, body: &str) -> Option<std::process::Command> {
+    let script = format!(
+        "display notification \"{}\" with title \"{}\" subtitle \"{}\"",
+        escape_applescript(body),
+        APP_NAME,
+        escape_applescript(title)
+    );
+    let mut command = std::process::Command::new("osascript");
+    command.arg("-e").arg(script);
+    Some(command)
+}
+
+#[cfg(target_os = "linux")]
+fn notification_command(title: &str, body: &str) -> Option<std::process::Command> {
+    let mut command = std::process::Command::new("notify-send");
+    command
+        .arg("--app-name")
+        .arg(APP_NAME)
+        .arg(format!("{}: {}", APP_NAME, title))
+        .arg(body);
+    Some(command)
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+fn notification_command(_title: &str, _body: &str) -> Option<std::process::Command> {
+    None
+}
+
+/// Escape a string for use inside an AppleScript string literal.
+#[cfg(target_os = "macos")]
+fn escape_applescript(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
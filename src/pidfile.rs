@@ -0,0 +1,70 @@
+//! PID file guarding against multiple daemons sharing a data directory.
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+/// A PID file owned by this process, removed on drop.
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Write our PID to `path`, failing if another live process holds it.
+    /// With `force`, an existing PID file is overwritten regardless.
+    pub fn acquire(path: &Path, force: bool) -> Result<Self> {
+        if let Some(pid) = read_pid(path) {
+            if process_alive(pid) {
+                if !force {
+                    anyhow::bail!(
+                        "Preprompter is already running (pid {}); remove {:?} or pass --force if it is stale",
+                        pid,
+                        path
+                    );
+                }
+                warn!("Overriding PID file {:?} held by pid {}", path, pid);
+            } else {
+                debug!("Removing stale PID file {:?} (pid {})", path, pid);
+            }
+        }
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create data directory: {:?}", parent))?;
+        }
+        std::fs::write(path, format!("{}\n", std::process::id()))
+            .with_context(|| format!("Failed to write PID file: {:?}", path))?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        // Only remove the file if it still holds our PID
+        if read_pid(&self.path) == Some(std::process::id()) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Read the PID stored in a PID file, if any.
+fn read_pid(path: &Path) -> Option<u32> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Returns true if a process with this PID exists.
+fn process_alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    if pid == 0 {
+        return false;
+    }
+
+    // Signal 0 performs error checking only; EPERM means it exists but isn't ours
+    let result = unsafe { libc::kill(pid, 0) };
+    result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
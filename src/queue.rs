@@ -0,0 +1,132 @@
+//! Bounded queue of frames waiting to be uploaded.
+
+use std::collections::VecDeque;
+
+use crate::capture::CapturedFrame;
+use crate::config::OverflowPolicy;
+
+/// A captured frame waiting for upload, with the metadata logged alongside it.
+pub struct QueuedFrame {
+    pub frame: CapturedFrame,
+    pub idle_seconds_before: u64,
+    pub interval_ms: Option<u64>,
+}
+
+/// Frames waiting for upload, oldest first. Frames whose upload failed stay
+/// queued, so the capacity bounds memory use while storage is unreachable.
+pub struct UploadQueue {
+    frames: VecDeque<QueuedFrame>,
+    capacity: usize,
+    policy: OverflowPolicy,
+}
+
+impl UploadQueue {
+    /// Create a queue holding at most `capacity` frames.
+    pub fn new(capacity: usize, policy: OverflowPolicy) -> Self {
+        Self {
+            frames: VecDeque::with_capacity(capacity),
+            capacity,
+            policy,
+        }
+    }
+
+    /// Number of frames waiting for upload.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Returns true if new captures should be skipped until the queue drains.
+    pub fn blocks_capture(&self) -> bool {
+        self.policy == OverflowPolicy::Block && self.frames.len() >= self.capacity
+    }
+
+    /// Add a frame, returning the frame dropped to make room, if any.
+    pub fn push(&mut self, item: QueuedFrame) -> Option<QueuedFrame> {
+        if self.frames.len() < self.capacity {
+            self.frames.push_back(item);
+            return None;
+        }
+
+        match self.policy {
+            OverflowPolicy::DropOldest => {
+                let dropped = self.frames.pop_front();
+                self.frames.push_back(item);
+                dropped
+            }
+            // Block skips captures while full, so anything arriving now is extra
+            OverflowPolicy::DropNewest | OverflowPolicy::Block => Some(item),
+        }
+    }
+
+    /// Take the oldest frame for upload.
+    pub fn pop(&mut self) -> Option<QueuedFrame> {
+        self.frames.pop_front()
+    }
+
+    /// Put a frame whose upload failed back at the front of the queue.
+    pub fn requeue(&mut self, item: QueuedFrame) {
+        self.frames.push_front(item);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queued(tick: u8) -> QueuedFrame {
+        QueuedFrame {
+            frame: CapturedFrame::for_test(vec![tick]),
+            idle_seconds_before: 0,
+            interval_ms: None,
+        }
+    }
+
+    fn ticks(queue: &mut UploadQueue) -> Vec<u8> {
+        std::iter::from_fn(|| queue.pop()).map(|q| q.frame.data[0]).collect()
+    }
+
+    #[test]
+    fn drop_oldest_makes_room_for_the_new_frame() {
+        let mut queue = UploadQueue::new(2, OverflowPolicy::DropOldest);
+        assert!(queue.push(queued(1)).is_none());
+        assert!(queue.push(queued(2)).is_none());
+        assert_eq!(queue.push(queued(3)).map(|q| q.frame.data[0]), Some(1));
+        assert!(!queue.blocks_capture());
+        assert_eq!(ticks(&mut queue), [2, 3]);
+    }
+
+    #[test]
+    fn drop_newest_keeps_the_queued_frames() {
+        let mut queue = UploadQueue::new(2, OverflowPolicy::DropNewest);
+        queue.push(queued(1));
+        queue.push(queued(2));
+        assert_eq!(queue.push(queued(3)).map(|q| q.frame.data[0]), Some(3));
+        assert!(!queue.blocks_capture());
+        assert_eq!(ticks(&mut queue), [1, 2]);
+    }
+
+    #[test]
+    fn block_stops_captures_while_full() {
+        let mut queue = UploadQueue::new(2, OverflowPolicy::Block);
+        queue.push(queued(1));
+        assert!(!queue.blocks_capture());
+        queue.push(queued(2));
+        assert!(queue.blocks_capture());
+        assert_eq!(queue.push(queued(3)).map(|q| q.frame.data[0]), Some(3));
+
+        queue.pop();
+        assert!(!queue.blocks_capture());
+    }
+
+    #[test]
+    fn requeued_frames_are_retried_first() {
+        let mut queue = UploadQueue::new(3, OverflowPolicy::DropOldest);
+        queue.push(queued(1));
+        queue.push(queued(2));
+        let failed = queue.pop().unwrap();
+        queue.push(queued(3));
+        queue.requeue(failed);
+        assert_eq!(queue.len(), 3);
+        assert_eq!(ticks(&mut queue), [1, 2, 3]);
+    }
+}
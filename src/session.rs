@@ -0,0 +1,15 @@
+//! Identifier for this run of the daemon, so one session's frames can be
+//! told apart from (and stored apart from) every other run's.
+
+use chrono::Utc;
+use std::sync::OnceLock;
+
+static SESSION_ID: OnceLock<String> = OnceLock::new();
+
+/// Unique ID for this run, generated on first use: the start time (so IDs
+/// sort chronologically) plus a random suffix, e.g. `20250214T101500Z-3f9a1c2e`.
+pub fn id() -> &'static str {
+    SESSION_ID.get_or_init(|| {
+        format!("{}-{:08x}", Utc::now().format("%Y%m%dT%H%M%SZ"), rand::random::<u32>())
+    })
+}
@@ -0,0 +1,52 @@
+//! Pause state persisted across restarts, so a daemon that crashes or is
+//! relaunched at login doesn't silently resume a capture the user paused.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tracing::warn;
+
+/// Whether capture is enabled, and when a timed pause ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CaptureState {
+    pub capture_enabled: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resume_at: Option<DateTime<Utc>>,
+}
+
+impl Default for CaptureState {
+    fn default() -> Self {
+        Self {
+            capture_enabled: true,
+            resume_at: None,
+        }
+    }
+}
+
+impl CaptureState {
+    /// Load the saved state from `path`, defaulting to enabled if it is
+    /// missing or unreadable. A timed pause that already ended loads as enabled.
+    pub fn load(path: &Path) -> Self {
+        let state = match std::fs::read(path) {
+            Ok(data) => serde_json::from_slice::<Self>(&data).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable capture state {}: {}", path.display(), e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        };
+
+        match state.resume_at {
+            Some(at) if at <= Utc::now() => Self::default(),
+            _ => state,
+        }
+    }
+
+    /// Write the state to `path`, replacing it atomically.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec(self)?)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, path).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
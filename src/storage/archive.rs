@@ -0,0 +1,570 @@
+//! Collecting frames into tar archives, uploaded as one object each.
+//!
+//! Frames are appended to a local archive, and a JSON-lines index records
+//! where each one starts. When the archive rotates (or the daemon stops) it is
+//! uploaded along with its index, so storage sees one request per archive
+//! instead of one per frame. Frames are logged under
+//! `<archive key>#<entry name>`, which `download_bytes` resolves via the index
+//! to a ranged read of just that frame.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::Mutex;
+use tracing::{debug, info, warn};
+
+use super::{KeyLayout, SettledUploads, StorageBackend, UploadResult};
+use crate::capture::CapturedFrame;
+
+/// Tar block size; headers and data are padded to a multiple of it.
+const BLOCK_SIZE: usize = 512;
+
+/// Longest entry name a plain ustar header holds.
+const MAX_NAME_LEN: usize = 100;
+
+/// Extension of an archive still being appended to.
+const OPEN_EXTENSION: &str = "part";
+
+/// Extension of a finished archive waiting for upload.
+const DONE_EXTENSION: &str = "tar";
+
+/// Extension of an archive's local index.
+const INDEX_EXTENSION: &str = "index.jsonl";
+
+const TAR_CONTENT_TYPE: &str = "application/x-tar";
+const INDEX_CONTENT_TYPE: &str = "application/x-ndjson";
+
+/// First line of an index, naming the key the archive is uploaded under.
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexHeader {
+    key: String,
+}
+
+/// Index line locating one frame in the archive.
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexEntry {
+    name: String,
+    /// Byte offset of the frame's data (after its header).
+    offset: u64,
+    size: u64,
+    timestamp: DateTime<Utc>,
+    monitor_id: u32,
+}
+
+/// The archive currently being appended to.
+struct OpenArchive {
+    /// Local path, without extension.
+    stem: PathBuf,
+    key: String,
+    opened: Instant,
+    len: u64,
+}
+
+/// Appends frames to local tar archives and uploads each archive to the
+/// wrapped backend once it rotates.
+pub struct ArchiveStore {
+    inner: Box<dyn StorageBackend>,
+    dir: PathBuf,
+    keys: KeyLayout,
+    rotate: Option<Duration>,
+    open: Mutex<Option<OpenArchive>>,
+    /// Frames and bytes in archives uploaded since the last
+    /// `take_settled_uploads`.
+    settled_frames: AtomicU64,
+    settled_bytes: AtomicU64,
+}
+
+impl ArchiveStore {
+    /// Create the store, finishing archives left open by an earlier run so
+    /// they are uploaded with the next rotation.
+    pub fn new(inner: Box<dyn StorageBackend>, dir: PathBuf, keys: KeyLayout) -> Result<Self> {
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create archive directory: {:?}", dir))?;
+
+        for entry in std::fs::read_dir(&dir)?.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|e| e == OPEN_EXTENSION) {
+                info!("Finishing archive left open by a previous run: {:?}", path);
+                finish_local(&path)?;
+            }
+        }
+
+        Ok(Self {
+            inner,
+            dir,
+            keys,
+            rotate: None,
+            open: Mutex::new(None),
+            settled_frames: AtomicU64::new(0),
+            settled_bytes: AtomicU64::new(0),
+        })
+    }
+
+    /// Start a new archive after this long (None = one per session).
+    pub fn with_rotation(mut self, rotate: Option<Duration>) -> Self {
+        self.rotate = rotate;
+        self
+    }
+
+    /// Begin a new local archive named after its first frame.
+    async fn open_archive(&self, frame: &CapturedFrame) -> Result<OpenArchive> {
+        let key = self.keys.render(frame, DONE_EXTENSION, Some("archives"));
+        let stem = self.dir.join(format!("archive-{}", frame.timestamp.timestamp_millis()));
+
+        let header = serde_json::to_string(&IndexHeader { key: key.clone() })?;
+        tokio::fs::write(index_path(&stem), format!("{}\n", header))
+            .await
+            .with_context(|| format!("Failed to create archive index for {:?}", stem))?;
+        tokio::fs::write(stem.with_extension(OPEN_EXTENSION), b"")
+            .await
+            .with_context(|| format!("Failed to create archive {:?}", stem))?;
+
+        debug!("Opened archive {} at {:?}", key, stem);
+        Ok(OpenArchive {
+            stem,
+            key,
+            opened: Instant::now(),
+            len: 0,
+        })
+    }
+
+    /// Finish the open archive, if any, then upload every finished archive.
+    async fn rotate(&self, open: &mut Option<OpenArchive>) -> Result<()> {
+        if let Some(archive) = open.take() {
+            finish_local(&archive.stem.with_extension(OPEN_EXTENSION))?;
+        }
+        self.upload_finished().await
+    }
+
+    /// Upload finished archives and their indexes, deleting each once stored.
+    /// Archives that fail stay on disk and are retried on the next rotation.
+    async fn upload_finished(&self) -> Result<()> {
+        let mut last_error = None;
+        for entry in std::fs::read_dir(&self.dir)?.flatten() {
+            let path = entry.path();
+            if path.extension().is_none_or(|e| e != DONE_EXTENSION) {
+                continue;
+            }
+            if let Err(e) = self.upload_archive(&path).await {
+                warn!("Failed to upload archive {:?}, will retry: {:#}", path, e);
+                last_error = Some(e);
+            }
+        }
+        last_error.map_or(Ok(()), Err)
+    }
+
+    async fn upload_archive(&self, path: &Path) -> Result<()> {
+        let stem = path.with_extension("");
+        let index = tokio::fs::read(index_path(&stem)).await?;
+        let header = read_header(&index)?;
+        let entries = read_entries(&index)?;
+        let size = tokio::fs::metadata(path).await?.len();
+
+        self.inner.upload_file(&header.key, path, TAR_CONTENT_TYPE).await?;
+        self.inner
+            .upload_object(&index_key(&header.key), index, INDEX_CONTENT_TYPE)
+            .await?;
+        info!("Uploaded archive {} ({} bytes)", header.key, size);
+        self.settled_frames.fetch_add(entries.len() as u64, Ordering::Relaxed);
+        self.settled_bytes
+            .fetch_add(entries.iter().map(|e| e.size).sum(), Ordering::Relaxed);
+
+        tokio::fs::remove_file(path).await?;
+        tokio::fs::remove_file(index_path(&stem)).await?;
+        Ok(())
+    }
+
+    /// Local archive and index for `key`, if it hasn't been uploaded yet.
+    fn find_local(&self, key: &str) -> Option<(PathBuf, Vec<u8>)> {
+        std::fs::read_dir(&self.dir).ok()?.flatten().find_map(|entry| {
+            let path = entry.path();
+            let name = path.file_name()?.to_str()?;
+            let stem = self.dir.join(name.strip_suffix(&format!(".{}", INDEX_EXTENSION))?);
+            let index = std::fs::read(&path).ok()?;
+            if read_header(&index).ok()?.key != key {
+                return None;
+            }
+            [OPEN_EXTENSION, DONE_EXTENSION]
+                .iter()
+                .map(|ext| stem.with_extension(ext))
+                .find(|p| p.exists())
+                .map(|p| (p, index))
+        })
+    }
+}
+
+#[async_trait]
+impl StorageBackend for ArchiveStore {
+    /// Append the frame to the open archive, rotating it first if it is due.
+    /// Thumbnails aren't archived.
+    async fn upload_frame(&self, frame: &CapturedFrame) -> Result<UploadResult> {
+        let start = Instant::now();
+        let mut open = self.open.lock().await;
+
+        let due = open
+            .as_ref()
+            .is_some_and(|a| self.rotate.is_some_and(|every| a.opened.elapsed() >= every));
+        if due {
+            if let Err(e) = self.rotate(&mut open).await {
+                warn!("Archive rotation incomplete: {:#}", e);
+            }
+        }
+        if open.is_none() {
+            *open = Some(self.open_archive(frame).await?);
+        }
+        let Some(archive) = open.as_mut() else {
+            anyhow::bail!("No open archive");
+        };
+
+        let name = format!("frame-{}-{}.{}", frame.frame_id(), frame.monitor_id, frame.format.extension());
+        let header = tar_header(&name, frame.data.len() as u64, frame.timestamp.timestamp())?;
+        let mut block = Vec::with_capacity(BLOCK_SIZE + padded(frame.data.len()));
+        block.extend_from_slice(&header);
+        block.extend_from_slice(&frame.data);
+        block.resize(BLOCK_SIZE + padded(frame.data.len()), 0);
+
+        let entry = IndexEntry {
+            name: name.clone(),
+            offset: archive.len + BLOCK_SIZE as u64,
+            size: frame.data.len() as u64,
+            timestamp: frame.timestamp,
+            monitor_id: frame.monitor_id,
+        };
+        let line = format!("{}\n", serde_json::to_string(&entry)?);
+        let appended = async {
+            append(&archive.stem.with_extension(OPEN_EXTENSION), &block).await?;
+            append(&index_path(&archive.stem), line.as_bytes()).await
+        }
+        .await;
+        if let Err(e) = appended {
+            // A partial append would shift the offset of every later frame
+            match trim_to_index(&archive.stem) {
+                Ok(len) => archive.len = len,
+                Err(trim_error) => {
+                    warn!("Failed to undo partial archive append, starting a new archive: {:#}", trim_error);
+                    if let Some(archive) = open.take() {
+                        if let Err(e) = finish_local(&archive.stem.with_extension(OPEN_EXTENSION)) {
+                            warn!("Failed to finish archive {:?}: {:#}", archive.stem, e);
+                        }
+                    }
+                }
+            }
+            return Err(e);
+        }
+        archive.len += block.len() as u64;
+
+        Ok(UploadResult {
+            key: format!("{}#{}", archive.key, name),
+            etag: String::new(),
+            uploaded_at: Utc::now(),
+            upload_duration_ms: start.elapsed().as_millis() as u64,
+            thumbnail_key: None,
+            mirrors: Vec::new(),
+            retention: None,
+            pending: true,
+        })
+    }
+
+    async fn upload_object(&self, key: &str, data: Vec<u8>, content_type: &str) -> Result<UploadResult> {
+        self.inner.upload_object(key, data, content_type).await
+    }
+
+    async fn upload_file(&self, key: &str, path: &Path, content_type: &str) -> Result<UploadResult> {
+        self.inner.upload_file(key, path, content_type).await
+    }
+
+    /// Read a frame by `<archive key>#<entry name>`, from the local archive if
+    /// it hasn't been uploaded yet. Other keys go straight to the wrapped backend.
+    async fn download_bytes(&self, key: &str) -> Result<Vec<u8>> {
+        let Some((archive_key, name)) = key.split_once('#') else {
+            return self.inner.download_bytes(key).await;
+        };
+
+        let local = {
+            let _open = self.open.lock().await;
+            self.find_local(archive_key)
+        };
+        let index = match &local {
+            Some((_, index)) => index.clone(),
+            None => self.inner.download_bytes(&index_key(archive_key)).await?,
+        };
+
+        let entry = read_entries(&index)?
+            .into_iter()
+            .find(|e| e.name == name)
+            .ok_or_else(|| anyhow::anyhow!("Frame {} not found in archive {}", name, archive_key))?;
+        match local {
+            Some((path, _)) => read_range(&path, entry.offset, entry.size)
+                .await
+                .with_context(|| format!("Archive {} is truncated", archive_key)),
+            None => self.inner.download_range(archive_key, entry.offset, entry.size).await,
+        }
+    }
+
+    fn location(&self) -> &str {
+        self.inner.location()
+    }
+
+    /// Finish and upload the open archive so a clean shutdown leaves nothing behind.
+    async fn finish(&self) -> Result<()> {
+        let mut open = self.open.lock().await;
+        self.rotate(&mut open).await?;
+        self.inner.finish().await
+    }
+
+    fn take_settled_uploads(&self) -> SettledUploads {
+        SettledUploads {
+            frames: self.settled_frames.swap(0, Ordering::Relaxed),
+            bytes: self.settled_bytes.swap(0, Ordering::Relaxed),
+        }
+    }
+}
+
+/// Keys of the archives in `dir` that haven't been uploaded yet.
+pub fn local_archive_keys(dir: &Path) -> HashSet<String> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return HashSet::new();
+    };
+    entries
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().ends_with(&format!(".{}", INDEX_EXTENSION)))
+        .filter_map(|entry| read_header(&std::fs::read(entry.path()).ok()?).ok())
+        .map(|header| header.key)
+        .collect()
+}
+
+/// Key the index of the archive at `key` is uploaded under.
+fn index_key(key: &str) -> String {
+    format!("{}.{}", key, INDEX_EXTENSION)
+}
+
+/// Local index path for the archive at `stem`.
+fn index_path(stem: &Path) -> PathBuf {
+    stem.with_extension(INDEX_EXTENSION)
+}
+
+fn read_header(index: &[u8]) -> Result<IndexHeader> {
+    let line = index.split(|&b| b == b'\n').next().unwrap_or_default();
+    serde_json::from_slice(line).context("Invalid archive index header")
+}
+
+fn read_entries(index: &[u8]) -> Result<Vec<IndexEntry>> {
+    index
+        .split(|&b| b == b'\n')
+        .skip(1)
+        .filter(|line| !line.is_empty())
+        .map(|line| serde_json::from_slice(line).context("Invalid archive index entry"))
+        .collect()
+}
+
+/// Cut an open archive and its index back to the last fully indexed frame,
+/// dropping whatever a failed or interrupted append left after it. Returns
+/// the archive's length.
+fn trim_to_index(stem: &Path) -> Result<u64> {
+    let index_path = index_path(stem);
+    let index = std::fs::read(&index_path).with_context(|| format!("Failed to read {:?}", index_path))?;
+
+    let (mut kept, mut end) = (0, 0);
+    for (i, line) in index.split_inclusive(|&b| b == b'\n').enumerate() {
+        let Some(line_body) = line.strip_suffix(b"\n") else {
+            break;
+        };
+        // The first line is the header; every later one must be a whole entry
+        if i > 0 {
+            let Ok(entry) = serde_json::from_slice::<IndexEntry>(line_body) else {
+                break;
+            };
+            end = entry.offset + entry.size.div_ceil(BLOCK_SIZE as u64) * BLOCK_SIZE as u64;
+        }
+        kept += line.len();
+    }
+
+    truncate(&index_path, kept as u64)?;
+    truncate(&stem.with_extension(OPEN_EXTENSION), end)?;
+    Ok(end)
+}
+
+/// Shorten the file at `path` to `len` bytes if it is longer.
+fn truncate(path: &Path, len: u64) -> Result<()> {
+    let file = std::fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {:?}", path))?;
+    if file.metadata()?.len() > len {
+        file.set_len(len).with_context(|| format!("Failed to truncate {:?}", path))?;
+    }
+    Ok(())
+}
+
+/// Append the end-of-archive marker to an open archive and mark it finished,
+/// first dropping any partly written frame.
+fn finish_local(open_path: &Path) -> Result<()> {
+    use std::io::Write;
+
+    trim_to_index(&open_path.with_extension(""))?;
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(open_path)
+        .with_context(|| format!("Failed to open archive {:?}", open_path))?;
+    file.write_all(&[0; BLOCK_SIZE * 2])?;
+    std::fs::rename(open_path, open_path.with_extension(DONE_EXTENSION))
+        .with_context(|| format!("Failed to finish archive {:?}", open_path))
+}
+
+/// Read `len` bytes at `offset` of a local archive.
+async fn read_range(path: &Path, offset: u64, len: u64) -> Result<Vec<u8>> {
+    let mut file = tokio::fs::File::open(path)
+        .await
+        .with_context(|| format!("Failed to open {:?}", path))?;
+    file.seek(std::io::SeekFrom::Start(offset)).await?;
+    let mut data = vec![0; usize::try_from(len)?];
+    file.read_exact(&mut data).await?;
+    Ok(data)
+}
+
+async fn append(path: &Path, data: &[u8]) -> Result<()> {
+    let mut file = tokio::fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(path)
+        .await
+        .with_context(|| format!("Failed to open {:?}", path))?;
+    // tokio finishes file writes in the background; flushing waits for them
+    // and surfaces their errors, so a later trim or read sees the whole write
+    file.write_all(data).await.with_context(|| format!("Failed to write {:?}", path))?;
+    file.flush()
+        .await
+        .with_context(|| format!("Failed to write {:?}", path))
+}
+
+/// `len` rounded up to a whole number of blocks.
+fn padded(len: usize) -> usize {
+    len.div_ceil(BLOCK_SIZE) * BLOCK_SIZE
+}
+
+/// Build a ustar header for a regular file.
+fn tar_header(name: &str, size: u64, mtime: i64) -> Result<[u8; BLOCK_SIZE]> {
+    if name.len() > MAX_NAME_LEN {
+        anyhow::bail!("Archive entry name too long: {}", name);
+    }
+
+    let mut header = [0u8; BLOCK_SIZE];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    write_octal(&mut header[100..108], 0o644);
+    write_octal(&mut header[108..116], 0);
+    write_octal(&mut header[116..124], 0);
+    write_octal(&mut header[124..136], size);
+    write_octal(&mut header[136..148], mtime.max(0) as u64);
+    header[156] = b'0';
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+
+    // The checksum is computed with its own field filled with spaces
+    header[148..156].fill(b' ');
+    let checksum: u64 = header.iter().map(|&b| b as u64).sum();
+    header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+    Ok(header)
+}
+
+/// Write `value` as zero-padded octal, NUL-terminated, filling `field`.
+fn write_octal(field: &mut [u8], value: u64) {
+    let width = field.len() - 1;
+    let digits = format!("{:0width$o}", value, width = width);
+    field[..width].copy_from_slice(&digits.as_bytes()[digits.len() - width..]);
+    field[width] = 0;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::FilesystemStore;
+
+    fn octal(field: &[u8]) -> u64 {
+        let digits = std::str::from_utf8(field).unwrap().trim_end_matches(['\0', ' ']);
+        u64::from_str_radix(digits, 8).unwrap()
+    }
+
+    #[test]
+    fn tar_header_is_valid_ustar() {
+        let header = tar_header("frame-1.jpg", 1234, 1_700_000_000).unwrap();
+
+        assert_eq!(&header[..11], b"frame-1.jpg");
+        assert_eq!(header[11], 0);
+        assert_eq!(octal(&header[124..136]), 1234);
+        assert_eq!(octal(&header[136..148]), 1_700_000_000);
+        assert_eq!(header[156], b'0');
+        assert_eq!(&header[257..263], b"ustar\0");
+
+        let mut unsummed = header;
+        unsummed[148..156].fill(b' ');
+        let sum: u64 = unsummed.iter().map(|&b| b as u64).sum();
+        assert_eq!(octal(&header[148..156]), sum);
+    }
+
+    #[test]
+    fn tar_header_rejects_long_names() {
+        assert!(tar_header(&"x".repeat(MAX_NAME_LEN + 1), 0, 0).is_err());
+    }
+
+    #[tokio::test]
+    async fn frames_are_pending_until_the_archive_is_uploaded() {
+        let staging = tempfile::tempdir().unwrap();
+        let frames = tempfile::tempdir().unwrap();
+        let keys = KeyLayout::new(None, None, None).unwrap();
+        let inner = FilesystemStore::new(frames.path().to_path_buf(), keys.clone()).unwrap();
+        let store = ArchiveStore::new(Box::new(inner), staging.path().to_path_buf(), keys).unwrap();
+
+        let first = store.upload_frame(&CapturedFrame::for_test(vec![1; 700])).await.unwrap();
+        let mut second_frame = CapturedFrame::for_test(vec![2; 10]);
+        second_frame.monitor_id = 1;
+        let second = store.upload_frame(&second_frame).await.unwrap();
+        assert!(first.pending && second.pending);
+        assert_eq!(store.download_bytes(&second.key).await.unwrap(), vec![2; 10]);
+        assert_eq!(store.take_settled_uploads(), SettledUploads::default());
+
+        store.finish().await.unwrap();
+        assert_eq!(store.take_settled_uploads(), SettledUploads { frames: 2, bytes: 710 });
+        assert_eq!(store.take_settled_uploads(), SettledUploads::default());
+
+        assert_eq!(store.download_bytes(&first.key).await.unwrap(), vec![1; 700]);
+        assert_eq!(store.download_bytes(&second.key).await.unwrap(), vec![2; 10]);
+        assert_eq!(std::fs::read_dir(staging.path()).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn leftover_archives_are_cut_back_to_their_last_indexed_frame() {
+        let staging = tempfile::tempdir().unwrap();
+        let frames = tempfile::tempdir().unwrap();
+        let keys = KeyLayout::new(None, None, None).unwrap();
+        let inner = FilesystemStore::new(frames.path().to_path_buf(), keys.clone()).unwrap();
+        let store = ArchiveStore::new(Box::new(inner), staging.path().to_path_buf(), keys.clone()).unwrap();
+        let stored = store.upload_frame(&CapturedFrame::for_test(vec![1; 700])).await.unwrap();
+        drop(store);
+
+        // A crash mid-append: part of a tar block and part of its index line
+        let part = std::fs::read_dir(staging.path())
+            .unwrap()
+            .flatten()
+            .map(|e| e.path())
+            .find(|p| p.extension().is_some_and(|e| e == OPEN_EXTENSION))
+            .unwrap();
+        append(&part, &[9; 300]).await.unwrap();
+        append(&index_path(&part.with_extension("")), b"{\"name\":\"frame-").await.unwrap();
+
+        let inner = FilesystemStore::new(frames.path().to_path_buf(), keys.clone()).unwrap();
+        let store = ArchiveStore::new(Box::new(inner), staging.path().to_path_buf(), keys).unwrap();
+        let finished = part.with_extension(DONE_EXTENSION);
+        assert_eq!(std::fs::metadata(&finished).unwrap().len(), (BLOCK_SIZE + 1024 + BLOCK_SIZE * 2) as u64);
+        let index = std::fs::read(index_path(&part.with_extension(""))).unwrap();
+        assert_eq!(read_entries(&index).unwrap().len(), 1);
+
+        store.finish().await.unwrap();
+        assert_eq!(store.download_bytes(&stored.key).await.unwrap(), vec![1; 700]);
+    }
+}
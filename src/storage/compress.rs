@@ -0,0 +1,45 @@
+//! Gzip and zstd compression of frame bytes.
+
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use std::io::{Read, Write};
+
+use crate::config::Compression;
+
+/// zstd compression level (1-22; 3 is the library default).
+const ZSTD_LEVEL: i32 = 3;
+
+/// Compress `data` with the given algorithm.
+pub fn compress(data: &[u8], compression: Compression) -> Result<Vec<u8>> {
+    match compression {
+        Compression::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(data).context("Failed to gzip frame")?;
+            encoder.finish().context("Failed to gzip frame")
+        }
+        Compression::Zstd => zstd::encode_all(data, ZSTD_LEVEL).context("Failed to zstd-compress frame"),
+    }
+}
+
+/// Decompress data produced by [`compress`].
+pub fn decompress(data: &[u8], compression: Compression) -> Result<Vec<u8>> {
+    match compression {
+        Compression::Gzip => {
+            let mut out = Vec::new();
+            GzDecoder::new(data)
+                .read_to_end(&mut out)
+                .context("Failed to gunzip frame")?;
+            Ok(out)
+        }
+        Compression::Zstd => zstd::decode_all(data).context("Failed to zstd-decompress frame"),
+    }
+}
+
+/// Decompress stored bytes if the key carries a compression suffix.
+pub fn decompress_for_key(key: &str, data: Vec<u8>) -> Result<Vec<u8>> {
+    match Compression::from_key(key) {
+        Some(compression) => decompress(&data, compression),
+        None => Ok(data),
+    }
+}
@@ -0,0 +1,84 @@
+//! Free disk space guard for the local data directory.
+
+use anyhow::{Context, Result};
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+/// Outcome of a free space check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiskCheck {
+    /// Enough free space is available.
+    Ok,
+    /// Free space just dropped below the threshold.
+    BecameLow { free_bytes: u64 },
+    /// Free space is still below the threshold.
+    StillLow,
+    /// Free space recovered above the threshold.
+    Recovered { free_bytes: u64 },
+}
+
+impl DiskCheck {
+    /// Returns true if there is enough space to keep writing.
+    pub fn has_space(&self) -> bool {
+        matches!(self, DiskCheck::Ok | DiskCheck::Recovered { .. })
+    }
+}
+
+/// Tracks free space on the data directory against a minimum threshold.
+pub struct DiskGuard {
+    path: PathBuf,
+    min_free_bytes: u64,
+    low: bool,
+}
+
+impl DiskGuard {
+    /// Create a new disk guard. A threshold of 0 disables the check.
+    pub fn new(path: PathBuf, min_free_bytes: u64) -> Self {
+        Self {
+            path,
+            min_free_bytes,
+            low: false,
+        }
+    }
+
+    /// Check free space, reporting transitions into and out of the low state.
+    pub fn check(&mut self) -> Result<DiskCheck> {
+        if self.min_free_bytes == 0 {
+            return Ok(DiskCheck::Ok);
+        }
+
+        let free_bytes = free_space(&self.path)?;
+        let is_low = free_bytes < self.min_free_bytes;
+
+        let result = match (self.low, is_low) {
+            (false, true) => DiskCheck::BecameLow { free_bytes },
+            (true, true) => DiskCheck::StillLow,
+            (true, false) => DiskCheck::Recovered { free_bytes },
+            (false, false) => DiskCheck::Ok,
+        };
+        self.low = is_low;
+
+        Ok(result)
+    }
+
+    /// Returns the configured minimum free space in bytes.
+    pub fn min_free_bytes(&self) -> u64 {
+        self.min_free_bytes
+    }
+}
+
+/// Get the free space available to unprivileged users on the filesystem containing `path`.
+fn free_space(path: &Path) -> Result<u64> {
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .with_context(|| format!("Invalid path: {:?}", path))?;
+
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    let rc = unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) };
+    if rc != 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Failed to query free space for {:?}", path));
+    }
+
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
@@ -0,0 +1,147 @@
+//! Classified upload errors.
+
+use aws_sdk_s3::config::http::HttpResponse;
+use aws_sdk_s3::error::{DisplayErrorContext, ProvideErrorMetadata, SdkError};
+use chrono::{DateTime, Utc};
+use std::time::Duration;
+
+/// Why an upload failed, so callers can tell transient from permanent errors.
+#[derive(Debug, thiserror::Error)]
+pub enum UploadError {
+    /// The service asked us to slow down, possibly saying for how long.
+    #[error("throttled: {message}")]
+    Throttled {
+        message: String,
+        retry_after: Option<Duration>,
+    },
+    /// Connection, timeout, or server-side failure.
+    #[error("network error: {0}")]
+    Network(String),
+    /// Credentials are missing, invalid, or lack permission.
+    #[error("access denied: {0}")]
+    Auth(String),
+    /// Temporary credentials (STS, SSO) have expired.
+    #[error("credentials expired: {0}")]
+    ExpiredCredentials(String),
+    /// The bucket (or key) does not exist.
+    #[error("not found: {0}")]
+    NotFound(String),
+    /// Anything else, including request construction errors.
+    #[error("{0}")]
+    Other(String),
+}
+
+impl UploadError {
+    /// Returns true if retrying the same request may succeed.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, UploadError::Throttled { .. } | UploadError::Network(_))
+    }
+
+    /// Returns true if the request itself was rejected, so resending the same
+    /// frame later can't succeed either.
+    pub fn is_permanent(&self) -> bool {
+        matches!(self, UploadError::Auth(_) | UploadError::NotFound(_) | UploadError::Other(_))
+    }
+
+    /// Returns true if a failed upload `err` can never succeed on retry.
+    pub fn is_permanent_failure(err: &anyhow::Error) -> bool {
+        err.downcast_ref::<UploadError>().is_some_and(UploadError::is_permanent)
+    }
+
+    /// Classify an SDK error, prefixing the message with `context`.
+    pub fn from_sdk<E>(err: SdkError<E, HttpResponse>, context: &str) -> Self
+    where
+        E: ProvideErrorMetadata + std::error::Error + 'static,
+    {
+        let message = format!("{}: {}", context, DisplayErrorContext(&err));
+
+        match &err {
+            SdkError::TimeoutError(_) | SdkError::DispatchFailure(_) | SdkError::ResponseError(_) => {
+                UploadError::Network(message)
+            }
+            SdkError::ServiceError(_) => {
+                let status = err.raw_response().map(|r| r.status().as_u16());
+                let code = err.code().unwrap_or_default();
+                let retry_after = err
+                    .raw_response()
+                    .and_then(|r| r.headers().get("retry-after"))
+                    .and_then(parse_retry_after);
+                classify_service_error(status, code, message, retry_after)
+            }
+            _ => UploadError::Other(message),
+        }
+    }
+}
+
+/// Classify a service error from its HTTP status and S3 error code.
+fn classify_service_error(
+    status: Option<u16>,
+    code: &str,
+    message: String,
+    retry_after: Option<Duration>,
+) -> UploadError {
+    match (status, code) {
+        (_, "SlowDown" | "Throttling" | "ThrottlingException" | "RequestLimitExceeded")
+        | (Some(429), _) => UploadError::Throttled { message, retry_after },
+        // A 503 that says when to come back is throttling, whatever its code
+        (Some(503), _) if retry_after.is_some() => UploadError::Throttled { message, retry_after },
+        (_, "ExpiredToken" | "ExpiredTokenException" | "TokenRefreshRequired") => {
+            UploadError::ExpiredCredentials(message)
+        }
+        (_, "AccessDenied" | "InvalidAccessKeyId" | "SignatureDoesNotMatch")
+        | (Some(401 | 403), _) => UploadError::Auth(message),
+        (_, "NoSuchBucket" | "NoSuchKey") | (Some(404), _) => UploadError::NotFound(message),
+        // The body was corrupted in transit; a fresh attempt sends it again
+        (_, "BadDigest" | "XAmzContentChecksumMismatch") => UploadError::Network(message),
+        (Some(500..=599), _) => UploadError::Network(message),
+        _ => UploadError::Other(message),
+    }
+}
+
+/// Parse a `Retry-After` header: delay seconds or an HTTP date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = DateTime::parse_from_rfc2822(value).ok()?.with_timezone(&Utc);
+    Some((at - Utc::now()).to_std().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    fn classify(status: u16, code: &str) -> UploadError {
+        classify_service_error(Some(status), code, String::new(), None)
+    }
+
+    #[test]
+    fn rejected_requests_are_permanent() {
+        assert!(classify(403, "AccessDenied").is_permanent());
+        assert!(classify(404, "NoSuchBucket").is_permanent());
+        assert!(classify(400, "InvalidRequest").is_permanent());
+    }
+
+    #[test]
+    fn outages_are_not_permanent() {
+        for error in [
+            classify(503, "ServiceUnavailable"),
+            classify(429, "SlowDown"),
+            classify(400, "ExpiredToken"),
+            classify(400, "BadDigest"),
+        ] {
+            assert!(!error.is_permanent(), "{}", error);
+        }
+    }
+
+    #[test]
+    fn permanent_failure_seen_through_context() {
+        let err = Err::<(), _>(UploadError::Auth("denied".to_string()))
+            .context("Failed to upload frame")
+            .unwrap_err();
+        assert!(UploadError::is_permanent_failure(&err));
+        assert!(!UploadError::is_permanent_failure(&anyhow::anyhow!("disk full")));
+    }
+}
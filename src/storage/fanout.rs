@@ -0,0 +1,335 @@
+//! Storing each frame at a primary destination plus any number of mirrors.
+
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::{StorageBackend, UploadResult};
+use crate::capture::CapturedFrame;
+
+/// Outcome of storing a frame at one mirror.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MirrorResult {
+    /// Bucket name or directory of the mirror.
+    pub location: String,
+    /// Key the frame was stored under, if it succeeded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// Why storing the frame failed, if it did.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// How many items whose primary upload failed have their mirror results
+/// remembered. Past this, the oldest are forgotten (e.g. frames the upload
+/// queue dropped) and would be stored at every mirror again on a retry.
+const MAX_PENDING: usize = 1024;
+
+/// Mirror results, by mirror index, of successes for one item.
+type Delivered = Vec<Option<MirrorResult>>;
+
+/// Contents of an object stored at every destination.
+#[derive(Clone, Copy)]
+enum Object<'a> {
+    Bytes(&'a [u8]),
+    File(&'a Path),
+}
+
+impl Object<'_> {
+    async fn upload(self, backend: &dyn StorageBackend, key: &str, content_type: &str) -> Result<UploadResult> {
+        match self {
+            Object::Bytes(data) => backend.upload_object(key, data.to_vec(), content_type).await,
+            Object::File(path) => backend.upload_file(key, path, content_type).await,
+        }
+    }
+}
+
+/// Uploads every frame to the primary backend while storing it at each
+/// mirror in turn.
+///
+/// Only the primary's outcome decides whether the frame counts as stored,
+/// so a mirror failure is logged and recorded without holding the frame
+/// back. A frame the primary rejects stays queued, and its retry only goes
+/// to the primary and the mirrors that don't have it yet.
+pub struct FanOut {
+    primary: Box<dyn StorageBackend>,
+    mirrors: Vec<Box<dyn StorageBackend>>,
+    /// Mirrors that already stored an item whose primary upload failed.
+    pending: Mutex<VecDeque<(String, Delivered)>>,
+}
+
+impl FanOut {
+    pub fn new(primary: Box<dyn StorageBackend>, mirrors: Vec<Box<dyn StorageBackend>>) -> Self {
+        Self {
+            primary,
+            mirrors,
+            pending: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Store the frame at each mirror in turn, collecting every outcome and
+    /// reusing earlier successes instead of uploading again.
+    async fn upload_mirrors(&self, frame: &CapturedFrame, mut delivered: Delivered) -> Vec<MirrorResult> {
+        let mut results = Vec::with_capacity(self.mirrors.len());
+        for (i, mirror) in self.mirrors.iter().enumerate() {
+            if let Some(result) = delivered.get_mut(i).and_then(Option::take) {
+                results.push(result);
+                continue;
+            }
+            let outcome = mirror.upload_frame(frame).await;
+            results.push(mirror_result(mirror.as_ref(), &frame.frame_id(), outcome));
+        }
+        results
+    }
+
+    /// Store an object at each mirror in turn, collecting every outcome and
+    /// reusing earlier successes instead of uploading again.
+    async fn upload_object_mirrors(
+        &self,
+        key: &str,
+        object: Object<'_>,
+        content_type: &str,
+        mut delivered: Delivered,
+    ) -> Vec<MirrorResult> {
+        let mut results = Vec::with_capacity(self.mirrors.len());
+        for (i, mirror) in self.mirrors.iter().enumerate() {
+            if let Some(result) = delivered.get_mut(i).and_then(Option::take) {
+                results.push(result);
+                continue;
+            }
+            let outcome = object.upload(mirror.as_ref(), key, content_type).await;
+            results.push(mirror_result(mirror.as_ref(), key, outcome));
+        }
+        results
+    }
+
+    /// Take the mirror successes remembered for `id` from an earlier attempt.
+    fn take_delivered(&self, id: &str) -> Delivered {
+        let mut pending = self.pending.lock().unwrap();
+        match pending.iter().position(|(pending_id, _)| pending_id == id) {
+            Some(index) => pending.remove(index).map(|(_, delivered)| delivered).unwrap_or_default(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Combine the primary's outcome with the mirrors'. When the primary
+    /// failed, remember which mirrors succeeded so the retry skips them.
+    fn settle(&self, id: String, primary: Result<UploadResult>, mirrors: Vec<MirrorResult>) -> Result<UploadResult> {
+        match primary {
+            Ok(mut result) => {
+                result.mirrors = mirrors;
+                Ok(result)
+            }
+            Err(e) => {
+                let delivered: Delivered = mirrors.into_iter().map(|m| m.key.is_some().then_some(m)).collect();
+                if delivered.iter().any(Option::is_some) {
+                    let mut pending = self.pending.lock().unwrap();
+                    if pending.len() >= MAX_PENDING {
+                        pending.pop_front();
+                    }
+                    pending.push_back((id, delivered));
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Record a mirror's outcome for `what` (a frame ID or key), logging failures.
+fn mirror_result(mirror: &dyn StorageBackend, what: &str, outcome: Result<UploadResult>) -> MirrorResult {
+    let location = mirror.location().to_string();
+    match outcome {
+        Ok(result) => MirrorResult {
+            location,
+            key: Some(result.key),
+            error: None,
+        },
+        Err(e) => {
+            warn!("Failed to store {} at mirror {}: {:#}", what, location, e);
+            MirrorResult {
+                location,
+                key: None,
+                error: Some(format!("{:#}", e)),
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl StorageBackend for FanOut {
+    async fn upload_frame(&self, frame: &CapturedFrame) -> Result<UploadResult> {
+        let id = format!("frame {} of monitor {}", frame.frame_id(), frame.monitor_id);
+        let delivered = self.take_delivered(&id);
+        let (primary, mirrors) =
+            tokio::join!(self.primary.upload_frame(frame), self.upload_mirrors(frame, delivered));
+        self.settle(id, primary, mirrors)
+    }
+
+    async fn upload_object(&self, key: &str, data: Vec<u8>, content_type: &str) -> Result<UploadResult> {
+        let id = format!("object {}", key);
+        let delivered = self.take_delivered(&id);
+        let (primary, mirrors) = tokio::join!(
+            self.primary.upload_object(key, data.clone(), content_type),
+            self.upload_object_mirrors(key, Object::Bytes(&data), content_type, delivered)
+        );
+        self.settle(id, primary, mirrors)
+    }
+
+    async fn upload_file(&self, key: &str, path: &Path, content_type: &str) -> Result<UploadResult> {
+        let id = format!("object {}", key);
+        let delivered = self.take_delivered(&id);
+        let (primary, mirrors) = tokio::join!(
+            self.primary.upload_file(key, path, content_type),
+            self.upload_object_mirrors(key, Object::File(path), content_type, delivered)
+        );
+        self.settle(id, primary, mirrors)
+    }
+
+    /// Read from the primary, falling back to each mirror in order.
+    async fn download_bytes(&self, key: &str) -> Result<Vec<u8>> {
+        let mut last_error = match self.primary.download_bytes(key).await {
+            Ok(data) => return Ok(data),
+            Err(e) => e,
+        };
+        for mirror in &self.mirrors {
+            match mirror.download_bytes(key).await {
+                Ok(data) => return Ok(data),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Read from the primary, falling back to each mirror in order.
+    async fn download_range(&self, key: &str, offset: u64, len: u64) -> Result<Vec<u8>> {
+        let mut last_error = match self.primary.download_range(key, offset, len).await {
+            Ok(data) => return Ok(data),
+            Err(e) => e,
+        };
+        for mirror in &self.mirrors {
+            match mirror.download_range(key, offset, len).await {
+                Ok(data) => return Ok(data),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+
+    fn location(&self) -> &str {
+        self.primary.location()
+    }
+
+    async fn finish(&self) -> Result<()> {
+        for mirror in &self.mirrors {
+            if let Err(e) = mirror.finish().await {
+                warn!("Failed to finish mirror {}: {:#}", mirror.location(), e);
+            }
+        }
+        self.primary.finish().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use anyhow::anyhow;
+
+    use super::*;
+
+    /// Backend that fails its first `failures` uploads and counts attempts.
+    struct Flaky {
+        location: String,
+        failures: usize,
+        attempts: Arc<AtomicUsize>,
+    }
+
+    impl Flaky {
+        fn boxed(location: &str, failures: usize) -> (Box<dyn StorageBackend>, Arc<AtomicUsize>) {
+            let attempts = Arc::new(AtomicUsize::new(0));
+            let backend = Flaky {
+                location: location.to_string(),
+                failures,
+                attempts: attempts.clone(),
+            };
+            (Box::new(backend), attempts)
+        }
+
+        fn attempt(&self, key: &str) -> Result<UploadResult> {
+            if self.attempts.fetch_add(1, Ordering::SeqCst) < self.failures {
+                return Err(anyhow!("{} is down", self.location));
+            }
+            Ok(UploadResult::stored(key))
+        }
+    }
+
+    #[async_trait]
+    impl StorageBackend for Flaky {
+        async fn upload_frame(&self, frame: &CapturedFrame) -> Result<UploadResult> {
+            self.attempt(&frame.frame_id())
+        }
+
+        async fn upload_object(&self, key: &str, _data: Vec<u8>, _content_type: &str) -> Result<UploadResult> {
+            self.attempt(key)
+        }
+
+        async fn download_bytes(&self, _key: &str) -> Result<Vec<u8>> {
+            Err(anyhow!("not stored"))
+        }
+
+        fn location(&self) -> &str {
+            &self.location
+        }
+    }
+
+    fn frame() -> CapturedFrame {
+        CapturedFrame::for_test(vec![0; 16])
+    }
+
+    #[tokio::test]
+    async fn primary_retry_skips_mirrors_that_stored_the_frame() {
+        let (primary, primary_attempts) = Flaky::boxed("primary", 1);
+        let (healthy, healthy_attempts) = Flaky::boxed("healthy", 0);
+        let (broken, broken_attempts) = Flaky::boxed("broken", 1);
+        let fanout = FanOut::new(primary, vec![healthy, broken]);
+        let frame = frame();
+
+        assert!(fanout.upload_frame(&frame).await.is_err());
+        let result = fanout.upload_frame(&frame).await.unwrap();
+
+        assert_eq!(primary_attempts.load(Ordering::SeqCst), 2);
+        assert_eq!(healthy_attempts.load(Ordering::SeqCst), 1);
+        assert_eq!(broken_attempts.load(Ordering::SeqCst), 2);
+        assert!(result.mirrors.iter().all(|m| m.key.is_some()));
+    }
+
+    #[tokio::test]
+    async fn mirror_failure_does_not_fail_the_frame() {
+        let (primary, _) = Flaky::boxed("primary", 0);
+        let (broken, _) = Flaky::boxed("broken", 1);
+        let fanout = FanOut::new(primary, vec![broken]);
+
+        let result = fanout.upload_frame(&frame()).await.unwrap();
+
+        assert_eq!(result.mirrors.len(), 1);
+        assert!(result.mirrors[0].error.is_some());
+    }
+
+    #[tokio::test]
+    async fn object_retry_skips_mirrors_that_stored_it() {
+        let (primary, _) = Flaky::boxed("primary", 1);
+        let (mirror, mirror_attempts) = Flaky::boxed("mirror", 0);
+        let fanout = FanOut::new(primary, vec![mirror]);
+
+        assert!(fanout.upload_object("archive.tar", vec![1], "application/x-tar").await.is_err());
+        assert!(fanout.upload_object("archive.tar", vec![1], "application/x-tar").await.is_ok());
+
+        assert_eq!(mirror_attempts.load(Ordering::SeqCst), 1);
+    }
+}
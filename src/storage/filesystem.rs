@@ -0,0 +1,500 @@
+//! Local filesystem storage that keeps a bounded ring of recent frames.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tracing::{debug, info, warn};
+
+use super::compress::{compress, decompress_for_key};
+use super::{slice_range, KeyLayout, StorageBackend, UploadResult};
+use crate::capture::CapturedFrame;
+use crate::config::Compression;
+use crate::crypto::FrameCipher;
+
+/// Extensions of the files this store writes: frames, thumbnails, and
+/// compressed frames, plus archives, archive indexes, and session summaries
+/// stored as objects. Anything else in the directory is left alone.
+const STORED_EXTENSIONS: &[&str] = &["jpg", "avif", "gz", "zst", "tar", "jsonl", "json"];
+
+/// A stored frame file tracked for retention.
+struct StoredFile {
+    path: PathBuf,
+    size: u64,
+    modified: SystemTime,
+}
+
+/// Stored files, oldest first, and their total size. Overwriting a file
+/// leaves its earlier entry in the queue, skipped once it reaches the front,
+/// so tracking a write doesn't scan the queue.
+#[derive(Default)]
+struct RingIndex {
+    files: VecDeque<StoredFile>,
+    /// Queue entries and current size of each stored path.
+    live: HashMap<PathBuf, (usize, u64)>,
+    total_bytes: u64,
+}
+
+impl RingIndex {
+    fn new(files: Vec<StoredFile>) -> Self {
+        let mut index = Self::default();
+        for file in files {
+            index.push(file);
+        }
+        index
+    }
+
+    /// Number of stored files.
+    fn len(&self) -> usize {
+        self.live.len()
+    }
+
+    /// Add a newly written file, replacing any earlier entry for its path.
+    fn push(&mut self, file: StoredFile) {
+        let (entries, size) = self.live.entry(file.path.clone()).or_default();
+        self.total_bytes = self.total_bytes.saturating_sub(*size) + file.size;
+        *entries += 1;
+        *size = file.size;
+        self.files.push_back(file);
+    }
+
+    /// The oldest stored file, dropping entries for paths written since.
+    fn front(&mut self) -> Option<&StoredFile> {
+        while let Some(file) = self.files.front() {
+            match self.live.get_mut(&file.path) {
+                Some((entries, _)) if *entries > 1 => {
+                    *entries -= 1;
+                    self.files.pop_front();
+                }
+                _ => break,
+            }
+        }
+        self.files.front()
+    }
+
+    /// Remove the oldest stored file from the index.
+    fn pop_front(&mut self) -> Option<StoredFile> {
+        self.front()?;
+        let file = self.files.pop_front()?;
+        if let Some((_, size)) = self.live.remove(&file.path) {
+            self.total_bytes = self.total_bytes.saturating_sub(size);
+        }
+        Some(file)
+    }
+}
+
+/// Writes frames under a directory, deleting the oldest once the configured
+/// age or size limit is exceeded.
+pub struct FilesystemStore {
+    dir: PathBuf,
+    location: String,
+    keys: KeyLayout,
+    retention: Option<Duration>,
+    max_bytes: Option<u64>,
+    compression: Option<Compression>,
+    cipher: Option<FrameCipher>,
+    index: Mutex<RingIndex>,
+}
+
+impl FilesystemStore {
+    /// Open the store, indexing frames already on disk.
+    pub fn new(dir: PathBuf, keys: KeyLayout) -> Result<Self> {
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create frames directory: {:?}", dir))?;
+
+        let mut files = Vec::new();
+        scan(&dir, &mut files)?;
+        files.sort_by_key(|f| f.modified);
+
+        let index = RingIndex::new(files);
+
+        info!(
+            "Filesystem storage initialized: dir={:?}, {} existing frames ({} MB)",
+            dir,
+            index.len(),
+            index.total_bytes / (1024 * 1024)
+        );
+
+        Ok(Self {
+            location: dir.display().to_string(),
+            dir,
+            keys,
+            retention: None,
+            max_bytes: None,
+            compression: None,
+            cipher: None,
+            index: Mutex::new(index),
+        })
+    }
+
+    /// Delete frames older than this.
+    pub fn with_retention(mut self, retention: Option<Duration>) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// Delete the oldest frames once the total size exceeds this.
+    pub fn with_max_bytes(mut self, max_bytes: Option<u64>) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Compress frames before writing them.
+    pub fn with_compression(mut self, compression: Option<Compression>) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Encrypt everything written, as the S3 backend does.
+    pub fn with_cipher(mut self, cipher: Option<FrameCipher>) -> Self {
+        self.cipher = cipher;
+        self
+    }
+
+    /// Delete the oldest frames until the retention limits are met.
+    fn enforce_retention(&self, index: &mut RingIndex) {
+        let cutoff = self.retention.and_then(|r| SystemTime::now().checked_sub(r));
+
+        while let Some(oldest) = index.front().map(|f| f.modified) {
+            let too_old = cutoff.is_some_and(|c| oldest < c);
+            let too_big = self.max_bytes.is_some_and(|max| index.total_bytes > max);
+            // Never delete the only frame; a limit smaller than one frame would empty the ring
+            if !(too_old || too_big) || index.len() == 1 {
+                break;
+            }
+
+            let Some(file) = index.pop_front() else {
+                break;
+            };
+            match std::fs::remove_file(&file.path) {
+                Ok(()) => debug!("Evicted {:?}", file.path),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => warn!("Failed to evict {:?}: {}", file.path, e),
+            }
+            self.remove_empty_parents(&file.path);
+        }
+    }
+
+    /// Write a file under the store, encrypted if configured, and track it
+    /// for retention.
+    async fn write_file(&self, key: &str, data: &[u8]) -> Result<()> {
+        let path = self.dir.join(key);
+        let encrypted = match &self.cipher {
+            Some(cipher) => Some(cipher.encrypt(data)?),
+            None => None,
+        };
+        let data = encrypted.as_deref().unwrap_or(data);
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .with_context(|| format!("Failed to create directory: {:?}", parent))?;
+        }
+        tokio::fs::write(&path, data)
+            .await
+            .with_context(|| format!("Failed to write frame: {:?}", path))?;
+        self.track(path, data.len() as u64);
+        Ok(())
+    }
+
+    /// Write the frame's thumbnail, if it has one, returning its key once
+    /// written. A failed thumbnail doesn't fail the frame.
+    async fn write_thumbnail(&self, frame: &CapturedFrame) -> Option<String> {
+        let thumbnail = frame.thumbnail.as_ref()?;
+        let thumb_key = frame.thumbnail_key(&self.keys);
+        match self.write_file(&thumb_key, thumbnail).await {
+            Ok(()) => Some(thumb_key),
+            Err(e) => {
+                warn!("Failed to write thumbnail {}: {}", thumb_key, e);
+                None
+            }
+        }
+    }
+
+    /// Track a newly written file for retention, evicting as needed.
+    fn track(&self, path: PathBuf, size: u64) {
+        if let Ok(mut index) = self.index.lock() {
+            // An overwritten key (e.g. the latest pointer) replaces its old entry
+            index.push(StoredFile {
+                path,
+                size,
+                modified: SystemTime::now(),
+            });
+            self.enforce_retention(&mut index);
+        }
+    }
+
+    /// Remove now-empty date directories left behind by eviction.
+    fn remove_empty_parents(&self, path: &Path) {
+        let mut dir = path.parent();
+        while let Some(d) = dir {
+            if d == self.dir || std::fs::remove_dir(d).is_err() {
+                break;
+            }
+            dir = d.parent();
+        }
+    }
+}
+
+#[async_trait]
+impl StorageBackend for FilesystemStore {
+    async fn upload_frame(&self, frame: &CapturedFrame) -> Result<UploadResult> {
+        let start = Instant::now();
+        let key = frame.s3_key(&self.keys);
+        if self.keys.is_content_addressed() {
+            let stored = match self.compression {
+                Some(compression) => format!("{}.{}", key, compression.extension()),
+                None => key.clone(),
+            };
+            if tokio::fs::try_exists(self.dir.join(&stored)).await.unwrap_or(false) {
+                debug!("Frame {} already stored as {}, skipping write", frame.frame_id(), stored);
+                // The thumbnail may have failed or been off when the frame was written
+                let thumb_key = frame.thumbnail_key(&self.keys);
+                let thumbnail_key = match &frame.thumbnail {
+                    Some(_) if tokio::fs::try_exists(self.dir.join(&thumb_key)).await.unwrap_or(false) => {
+                        Some(thumb_key)
+                    }
+                    Some(_) => self.write_thumbnail(frame).await,
+                    None => None,
+                };
+                return Ok(UploadResult {
+                    key: stored,
+                    etag: String::new(),
+                    uploaded_at: Utc::now(),
+                    upload_duration_ms: start.elapsed().as_millis() as u64,
+                    thumbnail_key,
+                    mirrors: Vec::new(),
+                    retention: None,
+                    pending: false,
+                });
+            }
+        }
+        let key = match self.compression {
+            Some(compression) => {
+                let key = format!("{}.{}", key, compression.extension());
+                self.write_file(&key, &compress(&frame.data, compression)?).await?;
+                key
+            }
+            None => {
+                self.write_file(&key, &frame.data).await?;
+                key
+            }
+        };
+
+        Ok(UploadResult {
+            key,
+            etag: String::new(),
+            uploaded_at: Utc::now(),
+            upload_duration_ms: start.elapsed().as_millis() as u64,
+            thumbnail_key: self.write_thumbnail(frame).await,
+            mirrors: Vec::new(),
+            retention: None,
+            pending: false,
+        })
+    }
+
+    async fn upload_object(&self, key: &str, data: Vec<u8>, _content_type: &str) -> Result<UploadResult> {
+        let start = Instant::now();
+        self.write_file(key, &data).await?;
+        Ok(UploadResult {
+            key: key.to_string(),
+            etag: String::new(),
+            uploaded_at: Utc::now(),
+            upload_duration_ms: start.elapsed().as_millis() as u64,
+            thumbnail_key: None,
+            mirrors: Vec::new(),
+            retention: None,
+            pending: false,
+        })
+    }
+
+    /// Copy the file into place without loading it, unless it has to be
+    /// encrypted first.
+    async fn upload_file(&self, key: &str, source: &Path, content_type: &str) -> Result<UploadResult> {
+        if self.cipher.is_some() {
+            let data = tokio::fs::read(source)
+                .await
+                .with_context(|| format!("Failed to read {:?}", source))?;
+            return self.upload_object(key, data, content_type).await;
+        }
+
+        let start = Instant::now();
+        let path = self.dir.join(key);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .with_context(|| format!("Failed to create directory: {:?}", parent))?;
+        }
+        let size = tokio::fs::copy(source, &path)
+            .await
+            .with_context(|| format!("Failed to copy {:?} to {:?}", source, path))?;
+        self.track(path, size);
+        Ok(UploadResult {
+            key: key.to_string(),
+            etag: String::new(),
+            uploaded_at: Utc::now(),
+            upload_duration_ms: start.elapsed().as_millis() as u64,
+            thumbnail_key: None,
+            mirrors: Vec::new(),
+            retention: None,
+            pending: false,
+        })
+    }
+
+    async fn download_bytes(&self, key: &str) -> Result<Vec<u8>> {
+        let path = self.dir.join(key);
+        let data = tokio::fs::read(&path)
+            .await
+            .with_context(|| format!("Failed to read frame: {:?}", path))?;
+        let data = match &self.cipher {
+            Some(cipher) => cipher.decrypt(&data)?,
+            None => data,
+        };
+        decompress_for_key(key, data)
+    }
+
+    /// Read just the requested bytes, unless the file has to be decrypted or
+    /// decompressed whole first.
+    async fn download_range(&self, key: &str, offset: u64, len: u64) -> Result<Vec<u8>> {
+        if self.cipher.is_some() || Compression::from_key(key).is_some() {
+            return slice_range(self.download_bytes(key).await?, offset, len, key);
+        }
+        let path = self.dir.join(key);
+        let mut file = tokio::fs::File::open(&path)
+            .await
+            .with_context(|| format!("Failed to read frame: {:?}", path))?;
+        file.seek(std::io::SeekFrom::Start(offset)).await?;
+        let mut data = vec![0; usize::try_from(len)?];
+        file.read_exact(&mut data)
+            .await
+            .with_context(|| format!("{:?} is truncated", path))?;
+        Ok(data)
+    }
+
+    fn location(&self) -> &str {
+        &self.location
+    }
+}
+
+/// Whether the store could have written the file at `path`.
+fn is_stored_file(path: &Path) -> bool {
+    let hidden = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_none_or(|name| name.starts_with('.'));
+    !hidden
+        && path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| STORED_EXTENSIONS.contains(&ext))
+}
+
+/// Recursively collect the files the store wrote under `dir`.
+fn scan(dir: &Path, files: &mut Vec<StoredFile>) -> Result<()> {
+    for entry in std::fs::read_dir(dir).with_context(|| format!("Failed to read {:?}", dir))? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            scan(&entry.path(), files)?;
+        } else if metadata.is_file() && is_stored_file(&entry.path()) {
+            files.push(StoredFile {
+                path: entry.path(),
+                size: metadata.len(),
+                modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+    fn store(dir: &Path) -> FilesystemStore {
+        FilesystemStore::new(dir.to_path_buf(), KeyLayout::new(None, None, None).unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn encrypts_files_and_reads_them_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store(dir.path()).with_cipher(Some(FrameCipher::from_hex(KEY).unwrap()));
+
+        store.upload_object("a/frame.jpg", b"plaintext".to_vec(), "image/jpeg").await.unwrap();
+
+        let on_disk = std::fs::read(dir.path().join("a/frame.jpg")).unwrap();
+        assert!(!on_disk.windows(9).any(|w| w == b"plaintext"));
+        assert_eq!(store.download_bytes("a/frame.jpg").await.unwrap(), b"plaintext");
+    }
+
+    #[test]
+    fn scan_only_indexes_stored_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("day")).unwrap();
+        std::fs::write(dir.path().join("day/frame.jpg"), b"x").unwrap();
+        std::fs::write(dir.path().join("day/frame.jpg.zst"), b"x").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), b"x").unwrap();
+        std::fs::write(dir.path().join(".DS_Store"), b"x").unwrap();
+
+        let mut files = Vec::new();
+        scan(dir.path(), &mut files).unwrap();
+
+        assert_eq!(files.len(), 2);
+        assert!(files.iter().all(|f| f.path.starts_with(dir.path().join("day"))));
+    }
+
+    #[tokio::test]
+    async fn eviction_leaves_foreign_files_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("notes.txt"), b"keep me").unwrap();
+        let store = store(dir.path()).with_max_bytes(Some(1));
+
+        store.upload_object("one.jpg", vec![0; 8], "image/jpeg").await.unwrap();
+        store.upload_object("two.jpg", vec![0; 8], "image/jpeg").await.unwrap();
+
+        assert!(dir.path().join("notes.txt").exists());
+        assert!(!dir.path().join("one.jpg").exists());
+        assert!(dir.path().join("two.jpg").exists());
+    }
+
+    #[tokio::test]
+    async fn overwritten_keys_count_once_toward_the_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store(dir.path()).with_max_bytes(Some(20));
+
+        store.upload_object("frame.jpg", vec![0; 8], "image/jpeg").await.unwrap();
+        store.upload_object("latest.jpg", vec![0; 8], "image/jpeg").await.unwrap();
+        store.upload_object("latest.jpg", vec![0; 8], "image/jpeg").await.unwrap();
+        store.upload_object("latest.jpg", vec![0; 8], "image/jpeg").await.unwrap();
+        assert!(dir.path().join("frame.jpg").exists());
+
+        store.upload_object("next.jpg", vec![0; 8], "image/jpeg").await.unwrap();
+        assert!(!dir.path().join("frame.jpg").exists());
+        assert!(dir.path().join("latest.jpg").exists());
+        assert!(dir.path().join("next.jpg").exists());
+    }
+
+    #[tokio::test]
+    async fn skipped_frames_still_get_their_thumbnail() {
+        let dir = tempfile::tempdir().unwrap();
+        let keys = KeyLayout::new(None, None, None).unwrap().with_content_addressing(true);
+        let store = FilesystemStore::new(dir.path().to_path_buf(), keys).unwrap();
+        let mut frame = CapturedFrame::for_test(vec![1, 2, 3]);
+        store.upload_frame(&frame).await.unwrap();
+
+        // Stored before thumbnails were on: the frame is skipped, the thumbnail written
+        frame.thumbnail = Some(vec![4, 5]);
+        let result = store.upload_frame(&frame).await.unwrap();
+        let thumb_key = result.thumbnail_key.unwrap();
+        assert_eq!(std::fs::read(dir.path().join(&thumb_key)).unwrap(), [4, 5]);
+
+        let result = store.upload_frame(&frame).await.unwrap();
+        assert_eq!(result.thumbnail_key, Some(thumb_key));
+    }
+}
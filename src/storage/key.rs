@@ -0,0 +1,189 @@
+//! Object key layout for stored frames.
+
+use anyhow::Result;
+
+use crate::capture::CapturedFrame;
+use crate::config::{validate_key_template, Config};
+
+/// Key template used when none is configured.
+pub const DEFAULT_KEY_TEMPLATE: &str = "{device}/{year}/{month}/{day}/{hour}/frame-{ts}.{ext}";
+
+/// Default template when monitors are captured as separate frames with one
+/// shared timestamp.
+pub const DEFAULT_MONITOR_KEY_TEMPLATE: &str = "{device}/{year}/{month}/{day}/{hour}/frame-{ts}-{monitor}.{ext}";
+
+/// Renders object keys for frames from a prefix and a template.
+#[derive(Debug, Clone)]
+pub struct KeyLayout {
+    prefix: Option<String>,
+    template: String,
+    device_id: Option<String>,
+    /// Session ID placed right after the prefix, isolating each run's frames.
+    session_id: Option<String>,
+    /// Key frames by their content hash instead of the template.
+    content_addressed: bool,
+}
+
+impl KeyLayout {
+    /// Create a layout, validating the template.
+    pub fn new(prefix: Option<&str>, template: Option<&str>, device_id: Option<String>) -> Result<Self> {
+        let template = template.unwrap_or(DEFAULT_KEY_TEMPLATE);
+        validate_key_template(template)?;
+
+        Ok(Self {
+            prefix: prefix
+                .map(|p| p.trim_matches('/').to_string())
+                .filter(|p| !p.is_empty()),
+            template: template.to_string(),
+            device_id,
+            session_id: None,
+            content_addressed: false,
+        })
+    }
+
+    /// The layout configured for stored frames.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let default_template = if config.capture.captures_monitors_separately() {
+            DEFAULT_MONITOR_KEY_TEMPLATE
+        } else {
+            DEFAULT_KEY_TEMPLATE
+        };
+        Self::new(
+            config.s3.prefix.as_deref(),
+            Some(config.s3.key_template.as_deref().unwrap_or(default_template)),
+            config.device_id(),
+        )
+        .map(|keys| {
+            keys.with_session(config.s3.session_prefix.then(|| crate::session::id().to_string()))
+                .with_content_addressing(config.s3.content_addressed)
+        })
+    }
+
+    /// Store frames under a directory for this session, just below the prefix.
+    pub fn with_session(mut self, session_id: Option<String>) -> Self {
+        self.session_id = session_id;
+        self
+    }
+
+    /// Key frames by the SHA-256 of their bytes, so identical frames share
+    /// one object.
+    pub fn with_content_addressing(mut self, enabled: bool) -> Self {
+        self.content_addressed = enabled;
+        self
+    }
+
+    /// Returns true if frames are keyed by their content hash.
+    pub fn is_content_addressed(&self) -> bool {
+        self.content_addressed
+    }
+
+    /// Render the key for content with the given hex hash, below the prefix
+    /// but outside any session so every run shares it:
+    /// `cas/ab/cd/<hash>.<ext>`, optionally under an extra directory.
+    pub fn content(&self, hash: &str, extension: &str, subdir: Option<&str>) -> String {
+        let name = format!("{}.{}", hash, extension);
+        self.prefix
+            .iter()
+            .map(String::as_str)
+            .chain(subdir)
+            .chain(["cas", &hash[..2], &hash[2..4], name.as_str()])
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    /// Render the key for a frame stored with the given file extension,
+    /// optionally under an extra directory (e.g. `thumbs`) below the prefix.
+    pub fn render(&self, frame: &CapturedFrame, extension: &str, subdir: Option<&str>) -> String {
+        let ts = &frame.timestamp;
+        let mut key = self.template.clone();
+        for (token, value) in [
+            ("{year}", ts.format("%Y").to_string()),
+            ("{month}", ts.format("%m").to_string()),
+            ("{day}", ts.format("%d").to_string()),
+            ("{hour}", ts.format("%H").to_string()),
+            ("{minute}", ts.format("%M").to_string()),
+            ("{ts}", ts.timestamp_millis().to_string()),
+            ("{monitor}", frame.monitor_id.to_string()),
+            ("{device}", self.device_id.clone().unwrap_or_default()),
+            ("{ext}", extension.to_string()),
+        ] {
+            key = key.replace(token, &value);
+        }
+
+        // Empty tokens (e.g. no device ID) must not leave empty path segments
+        self.prefix
+            .iter()
+            .chain(&self.session_id)
+            .map(String::as_str)
+            .chain(subdir)
+            .chain(key.split('/'))
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    /// Render the stable key overwritten with each new frame, below the
+    /// prefix but outside any session: `latest/<device>.<ext>`, or
+    /// `latest/<device>-<monitor>.<ext>` with one pointer per monitor.
+    pub fn latest(&self, frame: &CapturedFrame, extension: &str, per_monitor: bool) -> String {
+        let device = self.device_id.as_deref().unwrap_or("screen");
+        let name = if per_monitor {
+            format!("{}-{}.{}", device, frame.monitor_id, extension)
+        } else {
+            format!("{}.{}", device, extension)
+        };
+        self.prefix
+            .iter()
+            .map(String::as_str)
+            .chain(["latest", name.as_str()])
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    /// Render the key for a session's summary report, below the prefix but
+    /// outside any session: `sessions/<device>/<session_id>.json`.
+    pub fn session_summary(&self, session_id: &str) -> String {
+        let name = format!("{}.json", session_id);
+        self.prefix
+            .iter()
+            .map(String::as_str)
+            .chain(["sessions"])
+            .chain(self.device_id.as_deref())
+            .chain([name.as_str()])
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CaptureConfig;
+
+    fn layout(monitor_id: i32) -> KeyLayout {
+        let config = Config {
+            device_id: Some("mac".to_string()),
+            capture: CaptureConfig {
+                monitor_id,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        KeyLayout::from_config(&config).unwrap()
+    }
+
+    #[test]
+    fn separately_captured_monitors_get_their_own_keys() {
+        let mut first = CapturedFrame::for_test(vec![0]);
+        let mut second = first.clone();
+        first.monitor_id = 1;
+        second.monitor_id = 2;
+
+        let keys = layout(-1);
+        assert_ne!(keys.render(&first, "jpg", None), keys.render(&second, "jpg", None));
+        assert!(keys.render(&first, "jpg", None).ends_with(&format!("frame-{}-1.jpg", first.timestamp.timestamp_millis())));
+
+        let keys = layout(0);
+        assert!(keys.render(&first, "jpg", None).ends_with(&format!("frame-{}.jpg", first.timestamp.timestamp_millis())));
+    }
+}
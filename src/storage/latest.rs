@@ -0,0 +1,129 @@
+//! Stable "latest" object overwritten with each stored frame.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::path::Path;
+use tracing::{debug, warn};
+
+use super::{KeyLayout, SettledUploads, StorageBackend, UploadResult};
+use crate::capture::CapturedFrame;
+
+/// Wraps a backend, also writing each frame to a fixed key so dashboards
+/// have a predictable URL for the current screen.
+pub struct LatestPointer {
+    inner: Box<dyn StorageBackend>,
+    keys: KeyLayout,
+    /// Keep one pointer per monitor rather than one per device.
+    per_monitor: bool,
+}
+
+impl LatestPointer {
+    pub fn new(inner: Box<dyn StorageBackend>, keys: KeyLayout, per_monitor: bool) -> Self {
+        Self {
+            inner,
+            keys,
+            per_monitor,
+        }
+    }
+}
+
+#[async_trait]
+impl StorageBackend for LatestPointer {
+    async fn upload_frame(&self, frame: &CapturedFrame) -> Result<UploadResult> {
+        // Only move the pointer once the timestamped object is stored
+        let result = self.inner.upload_frame(frame).await?;
+
+        // The pointer is a convenience; a failed one doesn't fail the frame
+        let key = self.keys.latest(frame, frame.format.extension(), self.per_monitor);
+        match self
+            .inner
+            .upload_object(&key, frame.data.clone(), frame.format.content_type())
+            .await
+        {
+            Ok(_) => debug!("Updated {} to frame {}", key, frame.frame_id()),
+            Err(e) => warn!("Failed to update {}: {:#}", key, e),
+        }
+
+        Ok(result)
+    }
+
+    async fn upload_object(&self, key: &str, data: Vec<u8>, content_type: &str) -> Result<UploadResult> {
+        self.inner.upload_object(key, data, content_type).await
+    }
+
+    async fn upload_file(&self, key: &str, path: &Path, content_type: &str) -> Result<UploadResult> {
+        self.inner.upload_file(key, path, content_type).await
+    }
+
+    async fn download_bytes(&self, key: &str) -> Result<Vec<u8>> {
+        self.inner.download_bytes(key).await
+    }
+
+    async fn download_range(&self, key: &str, offset: u64, len: u64) -> Result<Vec<u8>> {
+        self.inner.download_range(key, offset, len).await
+    }
+
+    fn location(&self) -> &str {
+        self.inner.location()
+    }
+
+    async fn finish(&self) -> Result<()> {
+        self.inner.finish().await
+    }
+
+    fn take_settled_uploads(&self) -> SettledUploads {
+        self.inner.take_settled_uploads()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use anyhow::anyhow;
+
+    use super::*;
+
+    /// Backend that records the keys it stores.
+    struct Recording {
+        keys: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Recording {
+        fn result(&self, key: &str) -> Result<UploadResult> {
+            self.keys.lock().unwrap().push(key.to_string());
+            Ok(UploadResult::stored(key))
+        }
+    }
+
+    #[async_trait]
+    impl StorageBackend for Recording {
+        async fn upload_frame(&self, frame: &CapturedFrame) -> Result<UploadResult> {
+            self.result(&frame.frame_id())
+        }
+
+        async fn upload_object(&self, key: &str, _data: Vec<u8>, _content_type: &str) -> Result<UploadResult> {
+            self.result(key)
+        }
+
+        async fn download_bytes(&self, _key: &str) -> Result<Vec<u8>> {
+            Err(anyhow!("not stored"))
+        }
+
+        fn location(&self) -> &str {
+            "recording"
+        }
+    }
+
+    #[tokio::test]
+    async fn pointer_follows_the_stored_frame() {
+        let keys = Arc::new(Mutex::new(Vec::new()));
+        let inner = Recording { keys: keys.clone() };
+        let pointer = LatestPointer::new(Box::new(inner), KeyLayout::new(None, None, None).unwrap(), false);
+
+        pointer.upload_frame(&CapturedFrame::for_test(vec![0; 16])).await.unwrap();
+        let keys = keys.lock().unwrap().clone();
+        assert_eq!(keys.len(), 2);
+        assert!(keys[1].starts_with("latest/"));
+    }
+}
@@ -1,6 +1,194 @@
-//! Storage module for S3-compatible uploads.
+//! Storage backends for captured frames, plus local disk checks.
 
+mod archive;
+mod compress;
+mod disk;
+mod error;
+mod fanout;
+mod filesystem;
+mod key;
+mod latest;
 mod s3;
+mod tags;
 
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::capture::CapturedFrame;
+use crate::config::{Config, MirrorConfig, RetentionMode, S3Config, StorageKind};
+use crate::crypto::FrameCipher;
+
+pub use archive::{local_archive_keys, ArchiveStore};
+pub use disk::{DiskCheck, DiskGuard};
+pub use error::UploadError;
+pub use fanout::{FanOut, MirrorResult};
+pub use filesystem::FilesystemStore;
+pub use key::KeyLayout;
+pub use latest::LatestPointer;
 pub use s3::S3Uploader;
+pub use tags::validate_tags;
+
+/// Result of storing a frame.
+#[derive(Debug, Clone)]
+pub struct UploadResult {
+    /// Key (relative path) where the object was stored.
+    pub key: String,
+    /// ETag of the uploaded object.
+    pub etag: String,
+    /// Upload timestamp.
+    pub uploaded_at: DateTime<Utc>,
+    /// Duration of the upload operation.
+    pub upload_duration_ms: u64,
+    /// Key where the frame's thumbnail was stored, if one was uploaded.
+    pub thumbnail_key: Option<String>,
+    /// Outcome at each mirror, when mirrors are configured.
+    pub mirrors: Vec<MirrorResult>,
+    /// Object Lock retention applied to the object, if any.
+    pub retention: Option<Retention>,
+    /// Kept locally for now and uploaded later (appended to an archive).
+    pub pending: bool,
+}
+
+#[cfg(test)]
+impl UploadResult {
+    /// A frame stored under `key`, as test backends report it.
+    pub fn stored(key: &str) -> Self {
+        Self {
+            key: key.to_string(),
+            etag: String::new(),
+            uploaded_at: Utc::now(),
+            upload_duration_ms: 0,
+            thumbnail_key: None,
+            mirrors: Vec::new(),
+            retention: None,
+            pending: false,
+        }
+    }
+}
+
+/// Frames reported as pending that have since reached storage.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SettledUploads {
+    pub frames: u64,
+    /// Encoded size of those frames.
+    pub bytes: u64,
+}
+
+/// Object Lock retention set on an uploaded object.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Retention {
+    pub mode: RetentionMode,
+    /// The object can't be deleted or overwritten before this time.
+    pub retain_until: DateTime<Utc>,
+}
+
+/// Destination for captured frames.
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    /// Store a captured frame.
+    async fn upload_frame(&self, frame: &CapturedFrame) -> Result<UploadResult>;
+    /// Store an arbitrary object (e.g. a frame archive) under `key`.
+    async fn upload_object(&self, key: &str, data: Vec<u8>, content_type: &str) -> Result<UploadResult>;
+    /// Store the file at `path` as an object under `key`. Backends that can
+    /// stream it override this; by default it is read into memory.
+    async fn upload_file(&self, key: &str, path: &Path, content_type: &str) -> Result<UploadResult> {
+        let data = tokio::fs::read(path)
+            .await
+            .with_context(|| format!("Failed to read {:?}", path))?;
+        self.upload_object(key, data, content_type).await
+    }
+    /// Read back a stored frame by key.
+    async fn download_bytes(&self, key: &str) -> Result<Vec<u8>>;
+    /// Read `len` bytes at `offset` of the object at `key`. Backends that can
+    /// fetch a range override this; by default the whole object is read.
+    async fn download_range(&self, key: &str, offset: u64, len: u64) -> Result<Vec<u8>> {
+        slice_range(self.download_bytes(key).await?, offset, len, key)
+    }
+    /// Where frames are stored (bucket name or directory), recorded in logs.
+    fn location(&self) -> &str;
+    /// Write out anything still buffered before shutdown.
+    async fn finish(&self) -> Result<()> {
+        Ok(())
+    }
+    /// Pending frames stored since the last call.
+    fn take_settled_uploads(&self) -> SettledUploads {
+        SettledUploads::default()
+    }
+}
+
+/// Cut `len` bytes at `offset` out of the object at `key`.
+fn slice_range(data: Vec<u8>, offset: u64, len: u64, key: &str) -> Result<Vec<u8>> {
+    let start = usize::try_from(offset)?;
+    let end = start.saturating_add(usize::try_from(len)?);
+    data.get(start..end)
+        .map(<[u8]>::to_vec)
+        .ok_or_else(|| anyhow::anyhow!("{} is truncated", key))
+}
+
+/// Create the configured storage backend, fanning out to any mirrors.
+pub async fn create_backend(config: &Config) -> Result<Box<dyn StorageBackend>> {
+    let primary: Box<dyn StorageBackend> = match config.storage.backend {
+        StorageKind::S3 => Box::new(create_s3(config, &config.s3).await?),
+        StorageKind::Filesystem => Box::new(
+            FilesystemStore::new(config.frames_dir(), KeyLayout::from_config(config)?)?
+                .with_retention(config.storage.retention())
+                .with_max_bytes(config.storage.max_size_bytes())
+                .with_compression(config.upload.compress)
+                .with_cipher(FrameCipher::from_config(&config.crypto)?),
+        ),
+    };
+    let stored = if config.storage.mirrors.is_empty() {
+        primary
+    } else {
+        Box::new(FanOut::new(primary, create_mirrors(config).await?))
+    };
+
+    let archived: Box<dyn StorageBackend> = match &config.storage.archive {
+        Some(archive) => Box::new(
+            ArchiveStore::new(stored, config.logging.staging_dir().join("archives"), KeyLayout::from_config(config)?)?
+                .with_rotation(archive.rotate_interval()),
+        ),
+        None => stored,
+    };
+
+    if !config.storage.latest_pointer {
+        return Ok(archived);
+    }
+    // Monitors captured as separate frames each get their own pointer
+    let per_monitor = config.capture.monitor_id < 0
+        && !config.capture.stitch
+        && config.capture.follow_cursor.is_none();
+    Ok(Box::new(LatestPointer::new(archived, KeyLayout::from_config(config)?, per_monitor)))
+}
+
+/// Create a backend for each configured mirror.
+async fn create_mirrors(config: &Config) -> Result<Vec<Box<dyn StorageBackend>>> {
+    let mut mirrors: Vec<Box<dyn StorageBackend>> = Vec::with_capacity(config.storage.mirrors.len());
+    for mirror in &config.storage.mirrors {
+        mirrors.push(match mirror {
+            MirrorConfig::S3(s3) => Box::new(create_s3(config, s3).await?),
+            MirrorConfig::Filesystem(fs) => Box::new(
+                FilesystemStore::new(fs.dir.clone(), KeyLayout::from_config(config)?)?
+                    .with_retention(fs.retention())
+                    .with_max_bytes(fs.max_size_bytes())
+                    .with_compression(config.upload.compress)
+                    .with_cipher(FrameCipher::from_config(&config.crypto)?),
+            ),
+        });
+    }
+    Ok(mirrors)
+}
 
+/// Create an S3 uploader for `s3`, sharing upload, key, and crypto settings.
+async fn create_s3(config: &Config, s3: &S3Config) -> Result<S3Uploader> {
+    Ok(S3Uploader::new(s3)
+        .await?
+        .with_retry_attempts(config.upload.retry_attempts)
+        .with_max_retry_delay(config.upload.max_retry_delay())
+        .with_key_layout(KeyLayout::from_config(config)?)
+        .with_compression(config.upload.compress)
+        .with_cipher(FrameCipher::from_config(&config.crypto)?))
+}
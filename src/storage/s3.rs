@@ -1,63 +1,153 @@
 //! S3 upload client for screen captures.
 
 use anyhow::{Context, Result};
-use aws_config::BehaviorVersion;
-use aws_sdk_s3::primitives::ByteStream;
+use async_trait::async_trait;
+use aws_config::retry::RetryConfig;
+use aws_config::timeout::TimeoutConfig;
+use aws_config::{AppName, BehaviorVersion};
+use aws_sdk_s3::config::SharedHttpClient;
+use aws_sdk_s3::error::{DisplayErrorContext, ProvideErrorMetadata};
+use aws_sdk_s3::primitives::{ByteStream, DateTime as S3DateTime};
+use aws_sdk_s3::types::{ChecksumAlgorithm, ObjectLockEnabled, ObjectLockMode, StorageClass};
 use aws_sdk_s3::Client;
-use chrono::{DateTime, Utc};
+use aws_smithy_http_client::proxy::ProxyConfig;
+use aws_smithy_http_client::tls::{self, rustls_provider::CryptoMode, TlsContext, TrustStore};
+use aws_smithy_http_client::{Builder as HttpClientBuilder, Connector};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::Utc;
+use md5::{Digest, Md5};
+use rand::Rng;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, RwLock};
 use std::time::{Duration, Instant};
+use tokio::io::AsyncReadExt;
 use tracing::{debug, error, info, warn};
 
+use super::compress::{compress, decompress_for_key};
+use super::tags::ObjectTags;
+use super::{slice_range, KeyLayout, Retention, StorageBackend, UploadError, UploadResult};
 use crate::capture::CapturedFrame;
-use crate::config::S3Config;
-
-/// Result of an S3 upload operation.
-#[derive(Debug, Clone)]
-pub struct UploadResult {
-    /// S3 key where the object was uploaded.
-    pub key: String,
-    /// ETag of the uploaded object.
-    pub etag: String,
-    /// Upload timestamp.
-    pub uploaded_at: DateTime<Utc>,
-    /// Duration of the upload operation.
-    pub upload_duration_ms: u64,
+use crate::config::{Compression, ObjectLockConfig, OutputFormat, RetentionMode, S3Config, UploadChecksum};
+use crate::crypto::{self, FrameCipher};
+
+/// Object metadata key marking client-side encrypted uploads.
+const ENCRYPTED_METADATA_KEY: &str = "x-preprompter-encrypted";
+
+/// Object metadata key recording which checksum S3 verified on upload.
+const CHECKSUM_METADATA_KEY: &str = "x-preprompter-checksum";
+
+/// Content type for encrypted uploads.
+const ENCRYPTED_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// Bytes to upload, either in memory or streamed from a file on each attempt.
+enum Body {
+    Bytes(Vec<u8>),
+    File(PathBuf),
+}
+
+impl Body {
+    async fn stream(&self) -> Result<ByteStream, UploadError> {
+        match self {
+            Body::Bytes(data) => Ok(ByteStream::from(data.clone())),
+            Body::File(path) => ByteStream::from_path(path)
+                .await
+                .map_err(|e| UploadError::Other(format!("Failed to read {:?}: {}", path, e))),
+        }
+    }
+
+    /// Load the whole body, e.g. to encrypt it.
+    async fn into_bytes(self) -> Result<Vec<u8>, UploadError> {
+        match self {
+            Body::Bytes(data) => Ok(data),
+            Body::File(path) => tokio::fs::read(&path)
+                .await
+                .map_err(|e| UploadError::Other(format!("Failed to read {:?}: {}", path, e))),
+        }
+    }
+
+    /// Base64 MD5 of the body, reading files in chunks.
+    async fn md5(&self) -> Result<String, UploadError> {
+        let digest = match self {
+            Body::Bytes(data) => Md5::digest(data),
+            Body::File(path) => {
+                let read_error = |e: std::io::Error| UploadError::Other(format!("Failed to read {:?}: {}", path, e));
+                let mut file = tokio::fs::File::open(path).await.map_err(read_error)?;
+                let mut hasher = Md5::new();
+                let mut buf = vec![0; 64 * 1024];
+                loop {
+                    let n = file.read(&mut buf).await.map_err(read_error)?;
+                    if n == 0 {
+                        break;
+                    }
+                    hasher.update(&buf[..n]);
+                }
+                hasher.finalize()
+            }
+        };
+        Ok(BASE64.encode(digest))
+    }
 }
 
 /// S3 uploader client.
 pub struct S3Uploader {
-    client: Client,
+    /// Rebuilt when credentials expire, re-reading the credential chain.
+    client: RwLock<Client>,
+    /// Settings the client is rebuilt from.
+    config: S3Config,
+    /// Rebuild the client this often even without errors (None = only on expiry).
+    refresh_interval: Option<Duration>,
+    last_refresh: Mutex<Instant>,
     bucket: String,
-    prefix: Option<String>,
+    keys: KeyLayout,
+    storage_class: Option<StorageClass>,
+    checksum: Option<UploadChecksum>,
     retry_attempts: u32,
+    max_retry_delay: Duration,
+    cipher: Option<FrameCipher>,
+    compression: Option<Compression>,
+    tags: ObjectTags,
+    object_lock: Option<ObjectLockConfig>,
+    /// Set once a failed existence check was reported, so a policy without
+    /// GetObject warns once instead of on every frame.
+    head_failure_warned: AtomicBool,
 }
 
 impl S3Uploader {
     /// Create a new S3 uploader with the given configuration.
     pub async fn new(config: &S3Config) -> Result<Self> {
-        let mut aws_config_builder = aws_config::defaults(BehaviorVersion::latest())
-            .region(aws_config::Region::new(config.region.clone()));
-
-        // Apply custom endpoint if specified (for R2, MinIO, etc.)
-        if let Some(endpoint) = &config.endpoint_url {
-            if !endpoint.is_empty() {
-                aws_config_builder = aws_config_builder.endpoint_url(endpoint);
-            }
+        let client = build_client(config).await?;
+        if config.object_lock.is_some() {
+            check_object_lock(&client, &config.bucket).await?;
         }
 
-        let aws_config = aws_config_builder.load().await;
-        let client = Client::new(&aws_config);
+        let storage_class = config.storage_class.as_deref().and_then(parse_storage_class);
 
         info!(
-            "S3 uploader initialized: bucket={}, region={}",
-            config.bucket, config.region
+            "S3 uploader initialized: bucket={}, region={}, storage_class={}",
+            config.bucket,
+            config.region,
+            storage_class.as_ref().map(|c| c.as_str()).unwrap_or("default")
         );
 
         Ok(Self {
-            client,
+            client: RwLock::new(client),
+            config: config.clone(),
+            refresh_interval: config.credential_refresh_interval(),
+            last_refresh: Mutex::new(Instant::now()),
             bucket: config.bucket.clone(),
-            prefix: config.prefix.clone(),
+            keys: KeyLayout::new(config.prefix.as_deref(), None, None)?,
+            storage_class,
+            checksum: config.checksum,
             retry_attempts: 3,
+            max_retry_delay: Duration::from_secs(10),
+            cipher: None,
+            compression: None,
+            tags: ObjectTags::new(&config.tags),
+            object_lock: config.object_lock.clone(),
+            head_failure_warned: AtomicBool::new(false),
         })
     }
 
@@ -67,12 +157,55 @@ impl S3Uploader {
         self
     }
 
-    /// Upload a captured frame to S3.
-    pub async fn upload_frame(&self, frame: &CapturedFrame) -> Result<UploadResult> {
-        let key = frame.s3_key(self.prefix.as_deref());
-        let data = frame.data.clone();
+    /// Set the maximum delay between retries.
+    pub fn with_max_retry_delay(mut self, max_delay: Duration) -> Self {
+        self.max_retry_delay = max_delay;
+        self
+    }
+
+    /// Set how object keys are laid out.
+    pub fn with_key_layout(mut self, keys: KeyLayout) -> Self {
+        self.keys = keys;
+        self
+    }
+
+    /// Compress frames before upload.
+    pub fn with_compression(mut self, compression: Option<Compression>) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Encrypt all uploads with the given cipher.
+    pub fn with_cipher(mut self, cipher: Option<FrameCipher>) -> Self {
+        self.cipher = cipher;
+        self
+    }
+
+    /// The current client; cheap to clone.
+    fn client(&self) -> Client {
+        self.client.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Rebuild the client so credentials are loaded afresh.
+    async fn refresh_client(&self) -> Result<()> {
+        let client = build_client(&self.config).await?;
+        *self.client.write().unwrap_or_else(|e| e.into_inner()) = client;
+        *self.last_refresh.lock().unwrap_or_else(|e| e.into_inner()) = Instant::now();
+        Ok(())
+    }
 
-        self.upload_bytes(&key, data, "image/jpeg").await
+    /// Rebuild the client if the proactive refresh interval has passed.
+    async fn refresh_if_due(&self) {
+        let Some(interval) = self.refresh_interval else {
+            return;
+        };
+        let due = self.last_refresh.lock().unwrap_or_else(|e| e.into_inner()).elapsed() >= interval;
+        if due {
+            info!("Refreshing S3 credentials ({:?} since last refresh)", interval);
+            if let Err(e) = self.refresh_client().await {
+                warn!("Failed to refresh S3 client: {:#}", e);
+            }
+        }
     }
 
     /// Upload raw bytes to S3 with retries.
@@ -81,19 +214,62 @@ impl S3Uploader {
         key: &str,
         data: Vec<u8>,
         content_type: &str,
-    ) -> Result<UploadResult> {
+        content_encoding: Option<&str>,
+        tagging: Option<&str>,
+    ) -> Result<UploadResult, UploadError> {
+        self.upload_body(key, Body::Bytes(data), content_type, content_encoding, tagging)
+            .await
+    }
+
+    /// Upload a body to S3 with retries.
+    async fn upload_body(
+        &self,
+        key: &str,
+        body: Body,
+        content_type: &str,
+        content_encoding: Option<&str>,
+        tagging: Option<&str>,
+    ) -> Result<UploadResult, UploadError> {
         let start = Instant::now();
         let mut last_error = None;
+        let mut refreshed = false;
+        self.refresh_if_due().await;
+
+        // Encrypt once up front so storage never sees plaintext. The ciphertext
+        // is opaque, so it must not advertise a content encoding.
+        let (body, content_type, content_encoding) = match &self.cipher {
+            Some(cipher) => (
+                Body::Bytes(
+                    cipher
+                        .encrypt(&body.into_bytes().await?)
+                        .map_err(|e| UploadError::Other(e.to_string()))?,
+                ),
+                ENCRYPTED_CONTENT_TYPE,
+                None,
+            ),
+            None => (body, content_type, content_encoding),
+        };
 
-        for attempt in 0..self.retry_attempts {
+        // Fixed before the first attempt, so retries don't extend it
+        let retention = self.object_lock.as_ref().map(|lock| Retention {
+            mode: lock.retention_mode,
+            retain_until: Utc::now() + chrono::Duration::days(lock.retain_days as i64),
+        });
+
+        let mut attempt = 0;
+        while attempt < self.retry_attempts {
             if attempt > 0 {
-                // Exponential backoff
-                let delay = Duration::from_millis(100 * 2u64.pow(attempt));
+                let delay = self.retry_delay(attempt, last_error.as_ref());
                 debug!("Retry attempt {} after {:?}", attempt + 1, delay);
                 tokio::time::sleep(delay).await;
             }
+            attempt += 1;
+            tracing::Span::current().record("attempts", attempt);
 
-            match self.do_upload(key, data.clone(), content_type).await {
+            match self
+                .do_upload(key, &body, content_type, content_encoding, tagging, retention)
+                .await
+            {
                 Ok(etag) => {
                     let duration = start.elapsed();
                     return Ok(UploadResult {
@@ -101,32 +277,102 @@ impl S3Uploader {
                         etag,
                         uploaded_at: Utc::now(),
                         upload_duration_ms: duration.as_millis() as u64,
+                        thumbnail_key: None,
+                        mirrors: Vec::new(),
+                        retention,
+                        pending: false,
                     });
                 }
+                // Rebuild the client once and try again right away, without
+                // using up a retry
+                Err(UploadError::ExpiredCredentials(message)) if !refreshed => {
+                    warn!("S3 credentials expired, reloading: {}", message);
+                    refreshed = true;
+                    if let Err(e) = self.refresh_client().await {
+                        return Err(UploadError::ExpiredCredentials(format!("{} (reload failed: {:#})", message, e)));
+                    }
+                    attempt -= 1;
+                }
+                Err(e) if !e.is_retryable() => {
+                    warn!("Upload failed permanently, not retrying: {}", e);
+                    return Err(e);
+                }
                 Err(e) => {
-                    warn!("Upload attempt {} failed: {}", attempt + 1, e);
+                    warn!("Upload attempt {} failed: {}", attempt, e);
                     last_error = Some(e);
                 }
             }
         }
 
-        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("Upload failed with no error")))
+        // Only reachable with no attempts configured; the frame stays queued
+        Err(last_error.unwrap_or_else(|| UploadError::Network("no upload attempt was made".to_string())))
+    }
+
+    /// How long to wait before retry `attempt`. Throttling waits as long as
+    /// the service asks, or backs off more slowly than generic failures.
+    fn retry_delay(&self, attempt: u32, last_error: Option<&UploadError>) -> Duration {
+        let mut rng = rand::thread_rng();
+        match last_error {
+            Some(UploadError::Throttled { retry_after: Some(after), .. }) => (*after).min(MAX_RETRY_AFTER),
+            Some(UploadError::Throttled { .. }) => throttle_delay(attempt, self.max_retry_delay, &mut rng),
+            _ => backoff_delay(attempt, self.max_retry_delay, &mut rng),
+        }
     }
 
     /// Perform the actual S3 upload.
-    async fn do_upload(&self, key: &str, data: Vec<u8>, content_type: &str) -> Result<String> {
-        let body = ByteStream::from(data);
+    async fn do_upload(
+        &self,
+        key: &str,
+        body: &Body,
+        content_type: &str,
+        content_encoding: Option<&str>,
+        tagging: Option<&str>,
+        retention: Option<Retention>,
+    ) -> Result<String, UploadError> {
+        let mut metadata = HashMap::new();
+        if self.cipher.is_some() {
+            metadata.insert(ENCRYPTED_METADATA_KEY.to_string(), crypto::ALGORITHM.to_string());
+        }
+        if let Some(checksum) = self.checksum {
+            metadata.insert(CHECKSUM_METADATA_KEY.to_string(), checksum.as_str().to_string());
+        }
+
+        // S3 recomputes the checksum and rejects the upload on mismatch
+        let content_md5 = match self.checksum {
+            Some(UploadChecksum::Md5) => Some(body.md5().await?),
+            _ => None,
+        };
+        // Object Lock uploads must carry an integrity check
+        let checksum_algorithm = match self.checksum {
+            Some(checksum) => checksum_algorithm(checksum),
+            None if retention.is_some() => Some(ChecksumAlgorithm::Crc32),
+            None => None,
+        };
+
+        let body = body.stream().await?;
 
         let response = self
-            .client
+            .client()
             .put_object()
             .bucket(&self.bucket)
             .key(key)
             .content_type(content_type)
+            .set_content_encoding(content_encoding.map(str::to_string))
+            .set_storage_class(self.storage_class.clone())
+            .set_tagging(tagging.map(str::to_string))
+            .set_content_md5(content_md5)
+            .set_checksum_algorithm(checksum_algorithm)
+            .set_metadata((!metadata.is_empty()).then_some(metadata))
+            .set_object_lock_mode(retention.map(|r| object_lock_mode(r.mode)))
+            .set_object_lock_retain_until_date(
+                retention.map(|r| S3DateTime::from_millis(r.retain_until.timestamp_millis())),
+            )
             .body(body)
             .send()
             .await
-            .with_context(|| format!("Failed to upload to s3://{}/{}", self.bucket, key))?;
+            .map_err(|e| {
+                UploadError::from_sdk(e, &format!("Failed to upload to s3://{}/{}", self.bucket, key))
+            })?;
 
         let etag = response
             .e_tag()
@@ -138,6 +384,70 @@ impl S3Uploader {
         Ok(etag)
     }
 
+    /// Upload the frame's thumbnail, if it has one, returning its key once
+    /// stored. Thumbnails are a convenience; a failed one doesn't fail the frame.
+    async fn upload_thumbnail(&self, frame: &CapturedFrame, tagging: &str) -> Option<String> {
+        let thumbnail = frame.thumbnail.as_ref()?;
+        let thumb_key = frame.thumbnail_key(&self.keys);
+        match self
+            .upload_bytes(
+                &thumb_key,
+                thumbnail.clone(),
+                OutputFormat::Jpeg.content_type(),
+                None,
+                Some(tagging),
+            )
+            .await
+        {
+            Ok(_) => Some(thumb_key),
+            Err(e) => {
+                warn!("Failed to upload thumbnail {}: {}", thumb_key, e);
+                None
+            }
+        }
+    }
+
+    /// ETag and Object Lock retention of the object at `key`, or None if
+    /// there is no such object or the check failed. A credential allowed to
+    /// PutObject but not GetObject gets 403 for missing keys, so a failed
+    /// check means "upload it".
+    async fn head_object(&self, key: &str) -> Option<(String, Option<Retention>)> {
+        match self.client().head_object().bucket(&self.bucket).key(key).send().await {
+            Ok(response) => {
+                let etag = response
+                    .e_tag()
+                    .map(|s| s.trim_matches('"').to_string())
+                    .unwrap_or_default();
+                let mode = match response.object_lock_mode() {
+                    Some(ObjectLockMode::Governance) => Some(RetentionMode::Governance),
+                    Some(ObjectLockMode::Compliance) => Some(RetentionMode::Compliance),
+                    _ => None,
+                };
+                let retain_until = response
+                    .object_lock_retain_until_date()
+                    .and_then(|d| d.to_millis().ok())
+                    .and_then(chrono::DateTime::from_timestamp_millis);
+                let retention = mode.zip(retain_until).map(|(mode, retain_until)| Retention { mode, retain_until });
+                Some((etag, retention))
+            }
+            Err(e) if e.as_service_error().is_some_and(|e| e.is_not_found()) => None,
+            Err(e) => {
+                let message = format!(
+                    "Failed to check s3://{}/{}, uploading anyway: {}",
+                    self.bucket,
+                    key,
+                    DisplayErrorContext(&e)
+                );
+                if self.head_failure_warned.swap(true, Ordering::Relaxed) {
+                    debug!("{}", message);
+                } else {
+                    warn!("{}", message);
+                }
+                None
+            }
+        }
+    }
+
     /// Upload multiple frames in batch.
     pub async fn upload_batch(&self, frames: Vec<CapturedFrame>) -> Result<Vec<UploadResult>> {
         let mut results = Vec::with_capacity(frames.len());
@@ -161,3 +471,677 @@ impl S3Uploader {
     }
 }
 
+#[async_trait]
+impl StorageBackend for S3Uploader {
+    async fn upload_frame(&self, frame: &CapturedFrame) -> Result<UploadResult> {
+        let mut key = frame.s3_key(&self.keys);
+        let data = match self.compression {
+            Some(compression) => {
+                key = format!("{}.{}", key, compression.extension());
+                compress(&frame.data, compression)?
+            }
+            None => frame.data.clone(),
+        };
+        let tagging = self.tags.for_frame(frame.timestamp);
+        if self.keys.is_content_addressed() {
+            let start = Instant::now();
+            // Under Object Lock, a stored copy that isn't locked any more is
+            // uploaded again so the frame is protected
+            let stored = self.head_object(&key).await.filter(|(_, retention)| {
+                self.object_lock.is_none() || retention.is_some_and(|r| r.retain_until > Utc::now())
+            });
+            if let Some((etag, retention)) = stored {
+                debug!("Frame {} already stored as {}, skipping upload", frame.frame_id(), key);
+                // The thumbnail may have failed or been off when the frame was stored
+                let thumb_key = frame.thumbnail_key(&self.keys);
+                let thumbnail_key = match &frame.thumbnail {
+                    Some(_) if self.head_object(&thumb_key).await.is_some() => Some(thumb_key),
+                    Some(_) => self.upload_thumbnail(frame, &tagging).await,
+                    None => None,
+                };
+                return Ok(UploadResult {
+                    key,
+                    etag,
+                    uploaded_at: Utc::now(),
+                    upload_duration_ms: start.elapsed().as_millis() as u64,
+                    thumbnail_key,
+                    mirrors: Vec::new(),
+                    retention,
+                    pending: false,
+                });
+            }
+        }
+
+        let content_encoding = self.compression.map(|c| c.content_encoding());
+        let mut result = self
+            .upload_bytes(&key, data, frame.format.content_type(), content_encoding, Some(&tagging))
+            .await?;
+        result.thumbnail_key = self.upload_thumbnail(frame, &tagging).await;
+
+        Ok(result)
+    }
+
+    async fn upload_object(&self, key: &str, data: Vec<u8>, content_type: &str) -> Result<UploadResult> {
+        let tagging = self.tags.for_frame(Utc::now());
+        Ok(self.upload_bytes(key, data, content_type, None, Some(&tagging)).await?)
+    }
+
+    /// Stream the file from disk rather than loading it, unless it has to be
+    /// encrypted first.
+    async fn upload_file(&self, key: &str, path: &Path, content_type: &str) -> Result<UploadResult> {
+        let tagging = self.tags.for_frame(Utc::now());
+        Ok(self
+            .upload_body(key, Body::File(path.to_path_buf()), content_type, None, Some(&tagging))
+            .await?)
+    }
+
+    /// Fetch just the requested bytes with a ranged GET. Encrypted and
+    /// compressed objects can only be decoded whole, so they are downloaded
+    /// in full.
+    async fn download_range(&self, key: &str, offset: u64, len: u64) -> Result<Vec<u8>> {
+        if self.cipher.is_some() || Compression::from_key(key).is_some() || len == 0 {
+            return slice_range(self.download_bytes(key).await?, offset, len, key);
+        }
+        let range = format!("bytes={}-{}", offset, offset + len - 1);
+        let response = self
+            .client()
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .range(range)
+            .send()
+            .await
+            .with_context(|| format!("Failed to download s3://{}/{}", self.bucket, key))?;
+
+        let data = response
+            .body
+            .collect()
+            .await
+            .with_context(|| format!("Failed to read s3://{}/{}", self.bucket, key))?
+            .into_bytes()
+            .to_vec();
+        if data.len() as u64 != len {
+            anyhow::bail!("s3://{}/{} is truncated", self.bucket, key);
+        }
+        Ok(data)
+    }
+
+    async fn download_bytes(&self, key: &str) -> Result<Vec<u8>> {
+        let response = self
+            .client()
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .with_context(|| format!("Failed to download s3://{}/{}", self.bucket, key))?;
+
+        let data = response
+            .body
+            .collect()
+            .await
+            .with_context(|| format!("Failed to read s3://{}/{}", self.bucket, key))?
+            .into_bytes()
+            .to_vec();
+
+        let data = match &self.cipher {
+            Some(cipher) => cipher.decrypt(&data)?,
+            None => data,
+        };
+        decompress_for_key(key, data)
+    }
+
+    fn location(&self) -> &str {
+        &self.bucket
+    }
+}
+
+/// Base delay for the first retry.
+const BASE_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Base delay for the first retry after throttling.
+const THROTTLE_BASE_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest `Retry-After` honored, so a bad hint can't stall capture.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Compute a retry delay after throttling: exponential from a longer base with
+/// equal jitter, so there is always some pause before hitting the prefix again.
+fn throttle_delay(attempt: u32, max_delay: Duration, rng: &mut impl Rng) -> Duration {
+    let exp = THROTTLE_BASE_RETRY_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt - 1))
+        .min(max_delay.max(THROTTLE_BASE_RETRY_DELAY));
+    let half_ms = exp.as_millis() as u64 / 2;
+    Duration::from_millis(half_ms + rng.gen_range(0..=half_ms))
+}
+
+/// Compute a retry delay using full jitter: a random duration between zero and
+/// the exponential backoff for this attempt, capped at `max_delay`.
+fn backoff_delay(attempt: u32, max_delay: Duration, rng: &mut impl Rng) -> Duration {
+    let exp = BASE_RETRY_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(max_delay);
+    let ceiling_ms = exp.as_millis() as u64;
+    Duration::from_millis(rng.gen_range(0..=ceiling_ms))
+}
+
+/// Build an S3 client, loading credentials from the default provider chain.
+async fn build_client(config: &S3Config) -> Result<Client> {
+    let mut aws_config_builder = aws_config::defaults(BehaviorVersion::latest())
+        .region(aws_config::Region::new(config.region.clone()));
+
+    // Apply custom endpoint if specified (for R2, MinIO, etc.)
+    if let Some(endpoint) = &config.endpoint_url {
+        if !endpoint.is_empty() {
+            aws_config_builder = aws_config_builder.endpoint_url(endpoint);
+        }
+    }
+
+    if config.proxy_url.is_some() || config.ca_bundle_path.is_some() {
+        aws_config_builder = aws_config_builder.http_client(http_client(config)?);
+    }
+
+    // Fail fast on dead endpoints so retries and the queue take over sooner
+    let mut timeouts = TimeoutConfig::builder();
+    timeouts
+        .set_connect_timeout(config.connect_timeout())
+        .set_read_timeout(config.read_timeout())
+        .set_operation_timeout(config.operation_timeout());
+    aws_config_builder = aws_config_builder.timeout_config(timeouts.build());
+    // Retries are ours (`retry_attempts`), so the SDK makes one attempt each
+    aws_config_builder = aws_config_builder.retry_config(RetryConfig::disabled());
+
+    if let Some(name) = &config.user_agent {
+        let app_name = AppName::new(name.clone()).context("Invalid S3 user_agent")?;
+        aws_config_builder = aws_config_builder.app_name(app_name);
+    }
+
+    let aws_config = aws_config_builder.load().await;
+    let s3_config = aws_sdk_s3::config::Builder::from(&aws_config)
+        .force_path_style(config.force_path_style)
+        .build();
+    Ok(Client::from_conf(s3_config))
+}
+
+/// Build an HTTPS client that routes through `proxy_url` and trusts the extra
+/// certificates in `ca_bundle_path` (on top of the system roots).
+fn http_client(config: &S3Config) -> Result<SharedHttpClient> {
+    let mut trust_store = TrustStore::default();
+    if let Some(path) = &config.ca_bundle_path {
+        let pem = std::fs::read(path)
+            .with_context(|| format!("Failed to read CA bundle {}", path.display()))?;
+        trust_store = trust_store.with_pem_certificate(pem);
+    }
+    let tls_context = TlsContext::builder()
+        .with_trust_store(trust_store)
+        .build()
+        .context("Failed to build TLS context")?;
+
+    // Without an explicit proxy, keep honoring HTTP(S)_PROXY from the environment
+    let proxy = match &config.proxy_url {
+        Some(url) => ProxyConfig::all(url).context("Invalid S3 proxy_url")?,
+        None => ProxyConfig::from_env(),
+    };
+
+    Ok(HttpClientBuilder::new().build_with_connector_fn(move |settings, components| {
+        let mut builder = Connector::builder()
+            .proxy_config(proxy.clone())
+            .tls_provider(tls::Provider::Rustls(CryptoMode::AwsLc))
+            .tls_context(tls_context.clone());
+        builder.set_connector_settings(settings.cloned());
+        if let Some(components) = components {
+            builder.set_sleep_impl(components.sleep_impl());
+        }
+        builder.build()
+    }))
+}
+
+/// The SDK-computed checksum for `checksum`, if it isn't sent as `Content-MD5`.
+fn checksum_algorithm(checksum: UploadChecksum) -> Option<ChecksumAlgorithm> {
+    match checksum {
+        UploadChecksum::Md5 => None,
+        UploadChecksum::Crc32 => Some(ChecksumAlgorithm::Crc32),
+        UploadChecksum::Crc32c => Some(ChecksumAlgorithm::Crc32C),
+        UploadChecksum::Sha256 => Some(ChecksumAlgorithm::Sha256),
+    }
+}
+
+/// Map a configured retention mode to the SDK's.
+fn object_lock_mode(mode: RetentionMode) -> ObjectLockMode {
+    match mode {
+        RetentionMode::Governance => ObjectLockMode::Governance,
+        RetentionMode::Compliance => ObjectLockMode::Compliance,
+    }
+}
+
+/// Fail unless `bucket` has Object Lock enabled, since locked uploads to any
+/// other bucket are rejected. Errors other than a missing lock configuration
+/// (e.g. no network yet) only warn, leaving uploads to report them.
+async fn check_object_lock(client: &Client, bucket: &str) -> Result<()> {
+    let enabled = match client.get_object_lock_configuration().bucket(bucket).send().await {
+        Ok(response) => {
+            response
+                .object_lock_configuration()
+                .and_then(|c| c.object_lock_enabled())
+                == Some(&ObjectLockEnabled::Enabled)
+        }
+        Err(e) if e.code() == Some("ObjectLockConfigurationNotFoundError") => false,
+        Err(e) => {
+            warn!("Could not check Object Lock on bucket {}: {}", bucket, DisplayErrorContext(&e));
+            return Ok(());
+        }
+    };
+    if !enabled {
+        anyhow::bail!("S3 object_lock is set, but bucket {} does not have Object Lock enabled", bucket);
+    }
+    info!("Uploads to {} will be locked with Object Lock", bucket);
+    Ok(())
+}
+
+/// Parse a storage class name, warning and falling back to the bucket default if unknown.
+fn parse_storage_class(name: &str) -> Option<StorageClass> {
+    let name = name.trim().to_uppercase();
+    if name.is_empty() {
+        return None;
+    }
+
+    if StorageClass::values().contains(&name.as_str()) {
+        Some(StorageClass::from(name.as_str()))
+    } else {
+        warn!(
+            "Unknown S3 storage class '{}', using bucket default (valid: {})",
+            name,
+            StorageClass::values().join(", ")
+        );
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::sync::{Arc, Once};
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::{TcpListener, TcpStream};
+
+    #[test]
+    fn backoff_grows_exponentially_up_to_the_cap() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let max = Duration::from_secs(10);
+        for attempt in 1..40 {
+            let ceiling = BASE_RETRY_DELAY.saturating_mul(2u32.saturating_pow(attempt)).min(max);
+            for _ in 0..50 {
+                assert!(backoff_delay(attempt, max, &mut rng) <= ceiling);
+            }
+        }
+        // Late attempts are capped rather than overflowing
+        let late: Vec<_> = (0..200).map(|_| backoff_delay(1000, max, &mut rng)).collect();
+        assert!(late.iter().all(|d| *d <= max));
+        assert!(late.iter().any(|d| *d > Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn backoff_is_jittered() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let delays: Vec<_> = (0..100)
+            .map(|_| backoff_delay(3, Duration::from_secs(10), &mut rng))
+            .collect();
+        let distinct: std::collections::HashSet<_> = delays.iter().collect();
+        assert!(distinct.len() > 50, "{:?}", delays);
+        // Full jitter spreads across the whole window, not just near its top
+        assert!(delays.iter().any(|d| *d < Duration::from_millis(200)));
+        assert!(delays.iter().any(|d| *d > Duration::from_millis(600)));
+    }
+
+    #[test]
+    fn throttle_backoff_always_pauses() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for attempt in 1..10 {
+            let delay = throttle_delay(attempt, Duration::from_secs(10), &mut rng);
+            assert!(delay >= THROTTLE_BASE_RETRY_DELAY / 2, "{:?}", delay);
+            assert!(delay <= Duration::from_secs(10));
+        }
+    }
+
+    /// A request as the mock S3 saw it.
+    #[derive(Debug, Clone)]
+    struct Request {
+        method: String,
+        path: String,
+        headers: HashMap<String, String>,
+        body: Vec<u8>,
+    }
+
+    /// In-process S3 endpoint that records requests, answering each with the
+    /// next scripted status (400 = expired token, 500 = internal error) and
+    /// with 200 afterwards. Buckets have Object Lock enabled.
+    struct MockS3 {
+        endpoint: String,
+        state: Arc<MockState>,
+    }
+
+    #[derive(Default)]
+    struct MockState {
+        requests: Mutex<Vec<Request>>,
+        statuses: Mutex<std::vec::IntoIter<u16>>,
+        /// Governance retention reported for existing objects, as an ISO 8601 date.
+        locked_until: Mutex<Option<String>>,
+    }
+
+    impl MockS3 {
+        async fn start(statuses: Vec<u16>) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let endpoint = format!("http://{}", listener.local_addr().unwrap());
+            let state = Arc::new(MockState {
+                statuses: Mutex::new(statuses.into_iter()),
+                ..Default::default()
+            });
+
+            let shared = state.clone();
+            tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    tokio::spawn(serve(stream, shared.clone()));
+                }
+            });
+            Self { endpoint, state }
+        }
+
+        fn requests(&self) -> Vec<Request> {
+            self.state.requests.lock().unwrap().clone()
+        }
+
+        fn lock_objects_until(&self, date: &str) {
+            *self.state.locked_until.lock().unwrap() = Some(date.to_string());
+        }
+
+        fn config(&self) -> S3Config {
+            S3Config {
+                bucket: "frames".to_string(),
+                region: "us-east-1".to_string(),
+                endpoint_url: Some(self.endpoint.clone()),
+                force_path_style: true,
+                ..S3Config::default()
+            }
+        }
+    }
+
+    /// Answer the requests on one keep-alive connection.
+    async fn serve(mut stream: TcpStream, state: Arc<MockState>) {
+        let mut buf = Vec::new();
+        loop {
+            let Some(request) = read_request(&mut stream, &mut buf).await else {
+                return;
+            };
+            let (method, path) = (request.method.clone(), request.path.clone());
+            state.requests.lock().unwrap().push(request);
+
+            let status = state.statuses.lock().unwrap().next().unwrap_or(200);
+            let response = match status {
+                200 if path.contains("object-lock") => {
+                    let body = concat!(
+                        "<ObjectLockConfiguration><ObjectLockEnabled>Enabled</ObjectLockEnabled>",
+                        "</ObjectLockConfiguration>"
+                    );
+                    format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}", body.len(), body)
+                }
+                200 => {
+                    let mut headers = String::new();
+                    if let Some(date) = state.locked_until.lock().unwrap().as_ref().filter(|_| method == "HEAD") {
+                        headers = format!(
+                            "x-amz-object-lock-mode: GOVERNANCE\r\nx-amz-object-lock-retain-until-date: {}\r\n",
+                            date
+                        );
+                    }
+                    format!("HTTP/1.1 200 OK\r\nETag: \"d41d8cd9\"\r\n{}Content-Length: 0\r\n\r\n", headers)
+                }
+                status => {
+                    let code = if status == 400 { "ExpiredToken" } else { "InternalError" };
+                    let body = format!("<Error><Code>{}</Code><Message>mock failure</Message></Error>", code);
+                    format!(
+                        "HTTP/1.1 {} Error\r\nContent-Type: application/xml\r\nContent-Length: {}\r\n\r\n{}",
+                        status,
+                        body.len(),
+                        body
+                    )
+                }
+            };
+            if stream.write_all(response.as_bytes()).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Read one request with a `Content-Length` body, or None once the
+    /// client hangs up.
+    async fn read_request(stream: &mut TcpStream, buf: &mut Vec<u8>) -> Option<Request> {
+        let header_end = loop {
+            if let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                break end;
+            }
+            let mut chunk = [0u8; 4096];
+            let n = stream.read(&mut chunk).await.ok().filter(|&n| n > 0)?;
+            buf.extend_from_slice(&chunk[..n]);
+        };
+
+        let head = String::from_utf8_lossy(&buf[..header_end]).into_owned();
+        let mut lines = head.split("\r\n");
+        let mut request_line = lines.next()?.split(' ');
+        let method = request_line.next()?.to_string();
+        let path = request_line.next()?.to_string();
+        let headers: HashMap<String, String> = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+            .collect();
+
+        let len: usize = headers.get("content-length").map_or(0, |v| v.parse().unwrap());
+        let body_start = header_end + 4;
+        while buf.len() < body_start + len {
+            let mut chunk = [0u8; 4096];
+            let n = stream.read(&mut chunk).await.ok().filter(|&n| n > 0)?;
+            buf.extend_from_slice(&chunk[..n]);
+        }
+        let body = buf[body_start..body_start + len].to_vec();
+        buf.drain(..body_start + len);
+
+        Some(Request { method, path, headers, body })
+    }
+
+    /// Static credentials for the mock.
+    fn mock_environment() {
+        static ONCE: Once = Once::new();
+        ONCE.call_once(|| {
+            std::env::set_var("AWS_ACCESS_KEY_ID", "test");
+            std::env::set_var("AWS_SECRET_ACCESS_KEY", "test");
+            std::env::set_var("AWS_EC2_METADATA_DISABLED", "true");
+        });
+    }
+
+    async fn uploader(mock: &MockS3, retry_attempts: u32) -> S3Uploader {
+        mock_environment();
+        S3Uploader::new(&mock.config())
+            .await
+            .unwrap()
+            .with_retry_attempts(retry_attempts)
+            .with_max_retry_delay(Duration::ZERO)
+    }
+
+    #[tokio::test]
+    async fn uploads_frames_under_their_key() {
+        let mock = MockS3::start(vec![]).await;
+        let uploader = uploader(&mock, 3).await;
+        let frame = CapturedFrame::for_test(vec![1, 2, 3, 4]);
+
+        let result = uploader.upload_frame(&frame).await.unwrap();
+
+        let expected_key = frame.s3_key(&KeyLayout::new(None, None, None).unwrap());
+        assert_eq!(result.key, expected_key);
+        assert_eq!(result.etag, "d41d8cd9");
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "PUT");
+        assert_eq!(requests[0].path.split('?').next().unwrap(), format!("/frames/{}", expected_key));
+        assert_eq!(requests[0].headers["content-type"], "image/jpeg");
+        assert_eq!(requests[0].body, [1, 2, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn retries_until_the_upload_succeeds() {
+        let mock = MockS3::start(vec![500, 500]).await;
+        let uploader = uploader(&mock, 3).await;
+
+        let result = uploader
+            .upload_object("sessions/a.json", b"{}".to_vec(), "application/json")
+            .await
+            .unwrap();
+
+        assert_eq!(result.key, "sessions/a.json");
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(requests.iter().all(|r| r.path.starts_with("/frames/sessions/a.json")));
+        assert_eq!(requests[2].headers["content-type"], "application/json");
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_last_attempt() {
+        let mock = MockS3::start(vec![500, 500, 500]).await;
+        let uploader = uploader(&mock, 2).await;
+
+        let err = uploader
+            .upload_bytes("a.json", b"{}".to_vec(), "application/json", None, None)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, UploadError::Network(_)), "{:?}", err);
+        assert!(!err.is_permanent());
+        assert_eq!(mock.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn addresses_the_custom_endpoint_by_path() {
+        let mock = MockS3::start(vec![]).await;
+        let config = S3Config {
+            prefix: Some("team".to_string()),
+            ..mock.config()
+        };
+        mock_environment();
+        let uploader = S3Uploader::new(&config).await.unwrap();
+
+        uploader
+            .upload_object("team/notes.txt", b"hi".to_vec(), "text/plain")
+            .await
+            .unwrap();
+
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].headers["host"], mock.endpoint.trim_start_matches("http://"));
+        assert!(requests[0].path.starts_with("/frames/team/notes.txt"));
+        assert_eq!(requests[0].headers["content-type"], "text/plain");
+    }
+
+    #[tokio::test]
+    async fn frames_are_stored_with_their_format() {
+        let mock = MockS3::start(vec![]).await;
+        let uploader = uploader(&mock, 1).await;
+        let mut frame = CapturedFrame::for_test(vec![1, 2, 3]);
+        frame.format = OutputFormat::Avif;
+        frame.thumbnail = Some(vec![4, 5]);
+
+        let result = uploader.upload_frame(&frame).await.unwrap();
+
+        assert!(result.key.ends_with(".avif"), "{}", result.key);
+        // Thumbnails are always JPEGs
+        let thumbnail_key = result.thumbnail_key.unwrap();
+        assert!(thumbnail_key.ends_with(".jpg"), "{}", thumbnail_key);
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].path.contains(&result.key));
+        assert_eq!(requests[0].headers["content-type"], "image/avif");
+        assert!(requests[1].path.contains(&thumbnail_key));
+        assert_eq!(requests[1].headers["content-type"], "image/jpeg");
+    }
+
+    #[tokio::test]
+    async fn expired_credentials_rebuild_the_client_once() {
+        let mock = MockS3::start(vec![400]).await;
+        let first = uploader(&mock, 1).await;
+        let built_at = *first.last_refresh.lock().unwrap();
+
+        // Retried right away on the rebuilt client, without using the one attempt
+        first
+            .upload_object("a.json", b"{}".to_vec(), "application/json")
+            .await
+            .unwrap();
+        assert_eq!(mock.requests().len(), 2);
+        assert!(*first.last_refresh.lock().unwrap() > built_at);
+
+        // Credentials still expired after the reload give up instead of looping
+        let mock = MockS3::start(vec![400, 400, 400]).await;
+        let err = uploader(&mock, 3)
+            .await
+            .upload_bytes("a.json", b"{}".to_vec(), "application/json", None, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, UploadError::ExpiredCredentials(_)), "{:?}", err);
+        assert_eq!(mock.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn stored_frames_report_their_existing_lock() {
+        let mock = MockS3::start(vec![]).await;
+        mock.lock_objects_until("2099-01-02T03:04:05.000Z");
+        let config = S3Config {
+            content_addressed: true,
+            object_lock: Some(ObjectLockConfig {
+                retention_mode: RetentionMode::Governance,
+                retain_days: 30,
+            }),
+            ..mock.config()
+        };
+        mock_environment();
+        let uploader = S3Uploader::new(&config)
+            .await
+            .unwrap()
+            .with_key_layout(KeyLayout::new(None, None, None).unwrap().with_content_addressing(true));
+        let frame = CapturedFrame::for_test(vec![1, 2, 3]);
+
+        // Already stored and still locked: no upload, the stored lock is reported
+        let result = uploader.upload_frame(&frame).await.unwrap();
+        let retention = result.retention.unwrap();
+        assert_eq!(retention.mode, RetentionMode::Governance);
+        assert_eq!(retention.retain_until.to_rfc3339(), "2099-01-02T03:04:05+00:00");
+        assert!(mock.requests().iter().all(|r| r.method != "PUT"));
+
+        // A copy whose lock has lapsed is uploaded again under a fresh lock
+        mock.lock_objects_until("2001-01-01T00:00:00.000Z");
+        let result = uploader.upload_frame(&frame).await.unwrap();
+        assert!(result.retention.unwrap().retain_until > Utc::now() + chrono::Duration::days(29));
+        let put = mock.requests().into_iter().find(|r| r.method == "PUT").unwrap();
+        assert_eq!(put.headers["x-amz-object-lock-mode"], "GOVERNANCE");
+    }
+
+    #[tokio::test]
+    async fn skipped_frames_still_get_their_thumbnail() {
+        // The frame exists, its thumbnail doesn't
+        let mock = MockS3::start(vec![200, 404]).await;
+        mock_environment();
+        let uploader = S3Uploader::new(&mock.config())
+            .await
+            .unwrap()
+            .with_key_layout(KeyLayout::new(None, None, None).unwrap().with_content_addressing(true));
+        let mut frame = CapturedFrame::for_test(vec![1, 2, 3]);
+        frame.thumbnail = Some(vec![4, 5]);
+
+        let result = uploader.upload_frame(&frame).await.unwrap();
+        let thumb_key = result.thumbnail_key.unwrap();
+        let puts: Vec<_> = mock.requests().into_iter().filter(|r| r.method == "PUT").collect();
+        assert_eq!(puts.len(), 1);
+        assert!(puts[0].path.contains(&thumb_key), "{}", puts[0].path);
+
+        // Both exist now: nothing is uploaded
+        let result = uploader.upload_frame(&frame).await.unwrap();
+        assert_eq!(result.thumbnail_key, Some(thumb_key));
+        assert_eq!(mock.requests().iter().filter(|r| r.method == "PUT").count(), 1);
+    }
+}
@@ -0,0 +1,88 @@
+//! S3 object tags, for targeting uploads with bucket lifecycle rules.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+
+/// Tag set automatically on every upload with the frame's UTC capture date.
+pub const CAPTURE_DATE_TAG: &str = "capture_date";
+
+/// S3 allows at most this many tags per object.
+const MAX_TAGS: usize = 10;
+
+/// Maximum tag key length, in characters.
+const MAX_KEY_CHARS: usize = 128;
+
+/// Maximum tag value length, in characters.
+const MAX_VALUE_CHARS: usize = 256;
+
+/// Check user tags against S3's limits, leaving room for the automatic tag.
+pub fn validate_tags(tags: &BTreeMap<String, String>) -> Result<()> {
+    if tags.len() >= MAX_TAGS {
+        anyhow::bail!(
+            "At most {} s3.tags are allowed ({} is added automatically)",
+            MAX_TAGS - 1,
+            CAPTURE_DATE_TAG
+        );
+    }
+    for (key, value) in tags {
+        if key.is_empty() || key.chars().count() > MAX_KEY_CHARS {
+            anyhow::bail!("S3 tag key '{}' must be 1 to {} characters", key, MAX_KEY_CHARS);
+        }
+        if value.chars().count() > MAX_VALUE_CHARS {
+            anyhow::bail!("S3 tag '{}' value must be at most {} characters", key, MAX_VALUE_CHARS);
+        }
+        if key.starts_with("aws:") {
+            anyhow::bail!("S3 tag key '{}' uses the reserved aws: prefix", key);
+        }
+        if key == CAPTURE_DATE_TAG {
+            anyhow::bail!("S3 tag key '{}' is set automatically", CAPTURE_DATE_TAG);
+        }
+        for text in [key, value] {
+            if let Some(c) = text.chars().find(|&c| !is_allowed(c)) {
+                anyhow::bail!("S3 tag '{}' contains unsupported character {:?}", key, c);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Characters S3 accepts in tag keys and values.
+fn is_allowed(c: char) -> bool {
+    c.is_alphanumeric() || c == ' ' || "+-=._:/@".contains(c)
+}
+
+/// Tagging header values for uploads: the configured tags plus the capture date.
+#[derive(Debug, Clone, Default)]
+pub struct ObjectTags {
+    /// URL-encoded configured tags, `&`-terminated when non-empty.
+    prefix: String,
+}
+
+impl ObjectTags {
+    pub fn new(tags: &BTreeMap<String, String>) -> Self {
+        let prefix = tags
+            .iter()
+            .map(|(k, v)| format!("{}={}&", url_encode(k), url_encode(v)))
+            .collect();
+        Self { prefix }
+    }
+
+    /// The `x-amz-tagging` value for a frame captured at `timestamp`.
+    pub fn for_frame(&self, timestamp: DateTime<Utc>) -> String {
+        format!("{}{}={}", self.prefix, CAPTURE_DATE_TAG, timestamp.format("%Y-%m-%d"))
+    }
+}
+
+/// Percent-encode everything but RFC 3986 unreserved characters.
+fn url_encode(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            out.push(byte as char);
+        } else {
+            out.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    out
+}
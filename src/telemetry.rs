@@ -0,0 +1,56 @@
+//! Optional OpenTelemetry export of capture and upload spans over OTLP.
+//!
+//! Compiled in with the `otel` feature, so the default build doesn't carry
+//! the exporter's dependencies.
+
+use anyhow::Result;
+use tracing_subscriber::{Layer, Registry};
+
+/// Subscriber layer that exports spans.
+pub type OtelLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+#[cfg(feature = "otel")]
+static PROVIDER: std::sync::OnceLock<opentelemetry_sdk::trace::SdkTracerProvider> = std::sync::OnceLock::new();
+
+/// Build a layer exporting spans to the OTLP/HTTP collector at `endpoint`.
+/// Spans are batched and sent from a background thread.
+#[cfg(feature = "otel")]
+pub fn layer(endpoint: &str) -> Result<OtelLayer> {
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_otlp::WithExportConfig;
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use opentelemetry_sdk::Resource;
+
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()?;
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(
+            Resource::builder()
+                .with_service_name("preprompter")
+                .with_attribute(opentelemetry::KeyValue::new("service.version", env!("CARGO_PKG_VERSION")))
+                .build(),
+        )
+        .build();
+    let tracer = provider.tracer("preprompter");
+    let _ = PROVIDER.set(provider);
+
+    Ok(Box::new(tracing_opentelemetry::layer().with_tracer(tracer)))
+}
+
+#[cfg(not(feature = "otel"))]
+pub fn layer(_endpoint: &str) -> Result<OtelLayer> {
+    anyhow::bail!("this build doesn't include OpenTelemetry support (build with --features otel)")
+}
+
+/// Export any spans still buffered. Call before the process exits.
+pub fn shutdown() {
+    #[cfg(feature = "otel")]
+    if let Some(provider) = PROVIDER.get() {
+        if let Err(e) = provider.shutdown() {
+            tracing::warn!("Failed to flush OpenTelemetry spans: {}", e);
+        }
+    }
+}
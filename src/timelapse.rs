@@ -0,0 +1,93 @@
+//! Animated GIF timelapse assembly from recently uploaded frames.
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::imageops::FilterType;
+use image::{Delay, Frame};
+use std::fs::File;
+use std::io::BufWriter;
+use tracing::{info, warn};
+
+use crate::cli::TimelapseOptions;
+use crate::config::Config;
+use crate::logging::read_frames_since;
+use crate::storage;
+
+/// GIF encoder speed (1 = best quality, 30 = fastest).
+const GIF_SPEED: i32 = 10;
+
+/// Build a timelapse from frames logged within `options.since`.
+pub async fn run(config: &Config, options: &TimelapseOptions) -> Result<()> {
+    let since = Utc::now() - chrono::Duration::from_std(options.since)?;
+    let entries = read_frames_since(&config.logging, since)?;
+
+    // Frames from different monitors don't share a canvas, so use one
+    let monitor_id = options
+        .monitor_id
+        .or_else(|| entries.first().map(|e| e.monitor_id))
+        .with_context(|| format!("No frames logged in the last {:?}", options.since))?;
+    let entries: Vec<_> = entries
+        .into_iter()
+        .filter(|e| e.monitor_id == monitor_id)
+        .collect();
+
+    info!(
+        "Assembling timelapse from {} frames (monitor {}) at {} fps",
+        entries.len(),
+        monitor_id,
+        options.fps
+    );
+
+    let storage = storage::create_backend(config).await?;
+
+    let file = File::create(&options.out)
+        .with_context(|| format!("Failed to create timelapse file: {:?}", options.out))?;
+    let mut encoder = GifEncoder::new_with_speed(BufWriter::new(file), GIF_SPEED);
+    encoder.set_repeat(Repeat::Infinite)?;
+    let delay = Delay::from_numer_denom_ms(1000, u32::from(options.fps));
+
+    let mut size = None;
+    let mut written = 0;
+    for entry in &entries {
+        let data = match storage.download_bytes(&entry.s3_key).await {
+            Ok(data) => data,
+            Err(e) => {
+                warn!("Skipping frame {}: {}", entry.frame_id, e);
+                continue;
+            }
+        };
+        let img = match image::load_from_memory(&data) {
+            Ok(img) => img,
+            Err(e) => {
+                warn!("Skipping frame {}: {}", entry.frame_id, e);
+                continue;
+            }
+        };
+
+        // All frames are scaled to the first frame's output size
+        let (width, height) =
+            *size.get_or_insert_with(|| scaled_size(img.width(), img.height(), options.width));
+        let rgba = img.resize_exact(width, height, FilterType::Triangle).to_rgba8();
+        encoder
+            .encode_frame(Frame::from_parts(rgba, 0, 0, delay))
+            .context("Failed to encode timelapse frame")?;
+        written += 1;
+    }
+
+    if written == 0 {
+        anyhow::bail!("No frames could be downloaded for the timelapse");
+    }
+
+    info!("Wrote {} frames to {:?}", written, options.out);
+    Ok(())
+}
+
+/// Scale dimensions down to at most `max_width`, preserving aspect ratio.
+fn scaled_size(width: u32, height: u32, max_width: u32) -> (u32, u32) {
+    if width <= max_width {
+        return (width, height);
+    }
+    let scaled_height = (height as u64 * max_width as u64 / width as u64).max(1) as u32;
+    (max_width, scaled_height)
+}
@@ -0,0 +1,200 @@
+//! Uploaded byte accounting: a persisted daily tally with an optional budget,
+//! and the `usage` report built from the metadata log.
+
+use anyhow::{Context, Result};
+use chrono::{Duration as ChronoDuration, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use tracing::warn;
+
+use crate::cli::UsageOptions;
+use crate::config::Config;
+use crate::export::local_midnight;
+use crate::logging::read_frames_since;
+use crate::storage::local_archive_keys;
+
+/// Uploads for one local calendar day.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DayTally {
+    date: NaiveDate,
+    frames: u64,
+    bytes: u64,
+}
+
+impl DayTally {
+    fn empty(date: NaiveDate) -> Self {
+        Self { date, frames: 0, bytes: 0 }
+    }
+}
+
+/// Running upload totals for this session and today, persisted so the daily
+/// budget survives restarts.
+pub struct UsageTracker {
+    path: PathBuf,
+    today: DayTally,
+    session_bytes: u64,
+    budget_bytes: Option<u64>,
+    warned: bool,
+}
+
+impl UsageTracker {
+    /// Load today's tally from `path`, starting fresh if it is missing,
+    /// unreadable, or from an earlier day.
+    pub fn load(path: PathBuf, budget_bytes: Option<u64>) -> Self {
+        let date = Local::now().date_naive();
+        let today = std::fs::read(&path)
+            .ok()
+            .and_then(|data| serde_json::from_slice::<DayTally>(&data).ok())
+            .filter(|tally| tally.date == date)
+            .unwrap_or_else(|| DayTally::empty(date));
+
+        Self {
+            path,
+            today,
+            session_bytes: 0,
+            budget_bytes,
+            warned: false,
+        }
+    }
+
+    /// Record an uploaded frame, warning once per day when over budget.
+    pub fn record(&mut self, bytes: u64) {
+        self.record_many(1, bytes);
+    }
+
+    /// Record several frames uploaded together, e.g. in an archive.
+    pub fn record_many(&mut self, frames: u64, bytes: u64) {
+        let date = Local::now().date_naive();
+        if self.today.date != date {
+            self.today = DayTally::empty(date);
+            self.warned = false;
+        }
+        self.today.frames += frames;
+        self.today.bytes += bytes;
+        self.session_bytes += bytes;
+
+        if let Some(budget) = self.budget_bytes {
+            if self.today.bytes > budget && !self.warned {
+                warn!(
+                    "Daily upload budget exceeded: {} of frames uploaded today (budget {})",
+                    format_bytes(self.today.bytes),
+                    format_bytes(budget)
+                );
+                self.warned = true;
+            }
+        }
+
+        if let Err(e) = self.save() {
+            warn!("Failed to save usage tally: {}", e);
+        }
+    }
+
+    /// Bytes uploaded since this session started.
+    pub fn session_bytes(&self) -> u64 {
+        self.session_bytes
+    }
+
+    fn save(&self) -> Result<()> {
+        let data = serde_json::to_vec(&self.today)?;
+        std::fs::write(&self.path, data)
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+}
+
+/// Print uploaded frames and bytes per local day, from the metadata log.
+pub fn run(config: &Config, options: &UsageOptions) -> Result<()> {
+    let today = Local::now().date_naive();
+    let first_day = today
+        .checked_sub_signed(ChronoDuration::days(i64::from(options.days) - 1))
+        .context("--days reaches back too far")?;
+    let since = local_midnight(first_day)?;
+
+    // Archived frames count once their archive is uploaded, as in the daemon
+    let local_archives = local_archive_keys(&config.logging.staging_dir().join("archives"));
+    let mut days: BTreeMap<NaiveDate, DayTally> = BTreeMap::new();
+    for entry in read_frames_since(&config.logging, since)? {
+        let archive_key = entry.s3_key.split_once('#').map(|(archive, _)| archive);
+        if entry.upload_pending && archive_key.is_some_and(|key| local_archives.contains(key)) {
+            continue;
+        }
+        let date = entry.timestamp.with_timezone(&Local).date_naive();
+        let day = days.entry(date).or_insert_with(|| DayTally::empty(date));
+        day.frames += 1;
+        day.bytes += entry.file_size_bytes as u64;
+    }
+
+    // Sizes are of the encoded frames, not what compression or encryption sent
+    println!("{:<12} {:>8} {:>12}", "date", "frames", "encoded");
+    let mut total = DayTally::empty(today);
+    for day in days.values() {
+        println!("{:<12} {:>8} {:>12}", day.date, day.frames, format_bytes(day.bytes));
+        total.frames += day.frames;
+        total.bytes += day.bytes;
+    }
+    println!("{:<12} {:>8} {:>12}", "total", total.frames, format_bytes(total.bytes));
+
+    if let Some(budget) = config.upload.daily_budget_bytes() {
+        println!("daily budget: {}", format_bytes(budget));
+    }
+    Ok(())
+}
+
+/// Format a byte count with a binary unit, e.g. `12.3 MB`.
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_rejects_days_before_the_calendar_starts() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let mut config = Config::default();
+        config.logging.data_dir = data_dir.path().to_path_buf();
+        std::fs::create_dir_all(config.logging.logs_dir()).unwrap();
+
+        let err = run(&config, &UsageOptions { days: u32::MAX }).unwrap_err();
+        assert!(err.to_string().contains("--days"), "{}", err);
+        assert!(run(&config, &UsageOptions { days: 7 }).is_ok());
+    }
+
+    #[test]
+    fn tally_persists_for_the_day() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let path = data_dir.path().join("usage.json");
+
+        let mut usage = UsageTracker::load(path.clone(), Some(10));
+        usage.record(6);
+        usage.record(6);
+        assert!(usage.warned);
+        assert_eq!(usage.session_bytes(), 12);
+
+        let reloaded = UsageTracker::load(path, Some(10));
+        assert_eq!((reloaded.today.frames, reloaded.today.bytes), (2, 12));
+        assert_eq!(reloaded.session_bytes(), 0);
+    }
+
+    #[test]
+    fn archived_frames_count_against_the_budget() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let mut usage = UsageTracker::load(data_dir.path().join("usage.json"), Some(10));
+        usage.record_many(3, 12);
+        assert!(usage.warned);
+        assert_eq!((usage.today.frames, usage.today.bytes), (3, 12));
+        assert_eq!(usage.session_bytes(), 12);
+    }
+}
@@ -0,0 +1,107 @@
+//! Best-effort webhook notifications for uploaded frames.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use tracing::{debug, warn};
+
+use crate::capture::CapturedFrame;
+use crate::config::WebhookConfig;
+
+/// Header carrying the hex HMAC-SHA256 of the request body.
+const SIGNATURE_HEADER: &str = "X-Preprompter-Signature";
+
+/// JSON body posted for each uploaded frame.
+#[derive(Debug, Serialize)]
+struct FramePayload<'a> {
+    frame_id: String,
+    timestamp: DateTime<Utc>,
+    s3_key: &'a str,
+    s3_bucket: &'a str,
+    monitor_id: u32,
+    width: u32,
+    height: u32,
+    file_size_bytes: usize,
+}
+
+/// Posts frame metadata to a configured URL after each upload.
+pub struct WebhookNotifier {
+    client: reqwest::Client,
+    url: String,
+    secret: Option<String>,
+}
+
+impl WebhookNotifier {
+    /// Create a notifier from config. Returns `None` if no URL is configured.
+    pub fn new(config: &WebhookConfig) -> Result<Option<Self>> {
+        let Some(url) = config.url.clone() else {
+            return Ok(None);
+        };
+
+        let client = reqwest::Client::builder()
+            .timeout(config.timeout())
+            .build()
+            .context("Failed to create webhook HTTP client")?;
+
+        Ok(Some(Self {
+            client,
+            url,
+            secret: config.secret.clone(),
+        }))
+    }
+
+    /// Send a notification for an uploaded frame without waiting for delivery.
+    pub fn notify(&self, frame: &CapturedFrame, s3_key: &str, s3_bucket: &str) {
+        let payload = FramePayload {
+            frame_id: frame.frame_id(),
+            timestamp: frame.timestamp,
+            s3_key,
+            s3_bucket,
+            monitor_id: frame.monitor_id,
+            width: frame.width,
+            height: frame.height,
+            file_size_bytes: frame.data.len(),
+        };
+
+        let body = match serde_json::to_vec(&payload) {
+            Ok(body) => body,
+            Err(e) => {
+                warn!("Failed to serialize webhook payload: {}", e);
+                return;
+            }
+        };
+
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json");
+        if let Some(secret) = &self.secret {
+            request = request.header(SIGNATURE_HEADER, format!("sha256={}", sign(secret, &body)));
+        }
+
+        let frame_id = payload.frame_id;
+        tokio::spawn(async move {
+            match request.body(body).send().await {
+                Ok(response) if response.status().is_success() => {
+                    debug!("Delivered webhook for frame {}", frame_id);
+                }
+                Ok(response) => {
+                    warn!("Webhook for frame {} returned {}", frame_id, response.status());
+                }
+                Err(e) => {
+                    warn!("Failed to deliver webhook for frame {}: {}", frame_id, e);
+                }
+            }
+        });
+    }
+}
+
+/// Compute the hex HMAC-SHA256 of `body` with `secret`.
+fn sign(secret: &str, body: &[u8]) -> String {
+    // HMAC accepts keys of any length
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC key of any size");
+    mac.update(body);
+    format!("{:x}", mac.finalize().into_bytes())
+}